@@ -0,0 +1,270 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    attr, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Order, Reply, Response, StdResult,
+    SubMsg, WasmMsg,
+};
+use cw0::parse_reply_instantiate_data;
+use cw2::set_contract_version;
+use cw20::Denom;
+use cw_storage_plus::{Bound, PrimaryKey};
+
+use crate::error::ContractError;
+use crate::msg::{
+    ConfigResponse, ExecuteMsg, InstantiateMsg, PoolResponse, PoolsResponse, QueryMsg,
+};
+use crate::state::{
+    denom_key, pair_key, Config, PoolInfo, CONFIG, PENDING_POOL, POOLS, TOKEN_POOLS,
+};
+
+// Version info for migration info
+pub const CONTRACT_NAME: &str = "crates.io:wasmswap-factory";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const INSTANTIATE_POOL_REPLY_ID: u64 = 0;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let config = Config {
+        owner: msg.owner.map(|h| deps.api.addr_validate(&h)).transpose()?,
+        pool_code_id: msg.pool_code_id,
+        lp_token_code_id: msg.lp_token_code_id,
+        protocol_fee_recipient: deps.api.addr_validate(&msg.protocol_fee_recipient)?,
+        protocol_fee_percent: msg.protocol_fee_percent,
+        lp_fee_percent: msg.lp_fee_percent,
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::CreatePool {
+            token1_denom,
+            token2_denom,
+        } => execute_create_pool(deps, env, token1_denom, token2_denom),
+        ExecuteMsg::UpdateConfig {
+            owner,
+            pool_code_id,
+            lp_token_code_id,
+            protocol_fee_recipient,
+            protocol_fee_percent,
+            lp_fee_percent,
+        } => {
+            let config = CONFIG.load(deps.storage)?;
+            if Some(info.sender) != config.owner {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let config = Config {
+                owner: Some(deps.api.addr_validate(&owner)?),
+                pool_code_id,
+                lp_token_code_id,
+                protocol_fee_recipient: deps.api.addr_validate(&protocol_fee_recipient)?,
+                protocol_fee_percent,
+                lp_fee_percent,
+            };
+            CONFIG.save(deps.storage, &config)?;
+
+            Ok(Response::new().add_attribute("action", "update_config"))
+        }
+        ExecuteMsg::RenounceOwnership {} => {
+            let mut config = CONFIG.load(deps.storage)?;
+            if Some(info.sender) != config.owner {
+                return Err(ContractError::Unauthorized {});
+            }
+            config.owner = None;
+            CONFIG.save(deps.storage, &config)?;
+
+            Ok(Response::new().add_attribute("action", "renounce_ownership"))
+        }
+    }
+}
+
+fn validate_denom(deps: Deps, denom: Denom) -> StdResult<Denom> {
+    match denom {
+        Denom::Cw20(addr) => Ok(Denom::Cw20(deps.api.addr_validate(addr.as_str())?)),
+        Denom::Native(denom) => Ok(Denom::Native(denom)),
+    }
+}
+
+pub fn execute_create_pool(
+    deps: DepsMut,
+    _env: Env,
+    token1_denom: Denom,
+    token2_denom: Denom,
+) -> Result<Response, ContractError> {
+    let token1_denom = validate_denom(deps.as_ref(), token1_denom)?;
+    let token2_denom = validate_denom(deps.as_ref(), token2_denom)?;
+    if token1_denom == token2_denom {
+        return Err(ContractError::IdenticalDenoms {});
+    }
+
+    let (key1, key2) = pair_key(&token1_denom, &token2_denom);
+    if let Some(pool) = POOLS.may_load(deps.storage, (&key1, &key2))? {
+        return Err(ContractError::PoolAlreadyExists {
+            pool_address: pool.pool_address.into_string(),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let instantiate_pool_msg = WasmMsg::Instantiate {
+        code_id: config.pool_code_id,
+        funds: vec![],
+        admin: config.owner.clone().map(|o| o.into_string()),
+        label: "wasmswap_pool".to_string(),
+        msg: to_binary(&wasmswap::msg::InstantiateMsg {
+            token1_denom: token1_denom.clone(),
+            token2_denom: token2_denom.clone(),
+            lp_token_code_id: config.lp_token_code_id,
+            owner: config.owner.map(|o| o.into_string()),
+            protocol_fee_recipient: config.protocol_fee_recipient.into_string(),
+            protocol_fee_percent: config.protocol_fee_percent,
+            lp_fee_percent: config.lp_fee_percent,
+            pool_type: wasmswap::state::PoolType::ConstantProduct,
+            amp: None,
+            weights: None,
+            extra_denoms: vec![],
+        })?,
+    };
+
+    // The pool address is only known once the pool is instantiated, keep the pair for the reply
+    PENDING_POOL.save(deps.storage, &(token1_denom, token2_denom))?;
+
+    let reply_msg = SubMsg::reply_on_success(instantiate_pool_msg, INSTANTIATE_POOL_REPLY_ID);
+    Ok(Response::new()
+        .add_submessage(reply_msg)
+        .add_attributes(vec![
+            attr("action", "create_pool"),
+            attr("pair", key1 + "-" + &key2),
+        ]))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::Pool {
+            token1_denom,
+            token2_denom,
+        } => to_binary(&query_pool(deps, token1_denom, token2_denom)?),
+        QueryMsg::Pools { start_after, limit } => {
+            to_binary(&query_pools(deps, start_after, limit)?)
+        }
+        QueryMsg::PoolsByToken {
+            denom,
+            start_after,
+            limit,
+        } => to_binary(&query_pools_by_token(deps, denom, start_after, limit)?),
+    }
+}
+
+pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        owner: config.owner.map(|o| o.into_string()),
+        pool_code_id: config.pool_code_id,
+        lp_token_code_id: config.lp_token_code_id,
+        protocol_fee_recipient: config.protocol_fee_recipient.into_string(),
+        protocol_fee_percent: config.protocol_fee_percent,
+        lp_fee_percent: config.lp_fee_percent,
+    })
+}
+
+fn to_pool_response(pool: PoolInfo) -> PoolResponse {
+    PoolResponse {
+        token1_denom: pool.token1_denom,
+        token2_denom: pool.token2_denom,
+        pool_address: pool.pool_address.into_string(),
+    }
+}
+
+pub fn query_pool(deps: Deps, token1_denom: Denom, token2_denom: Denom) -> StdResult<PoolResponse> {
+    let (key1, key2) = pair_key(&token1_denom, &token2_denom);
+    let pool = POOLS.load(deps.storage, (&key1, &key2))?;
+    Ok(to_pool_response(pool))
+}
+
+pub fn query_pools(
+    deps: Deps,
+    start_after: Option<(Denom, Denom)>,
+    limit: Option<u32>,
+) -> StdResult<PoolsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|(token1_denom, token2_denom)| {
+        let (key1, key2) = pair_key(&token1_denom, &token2_denom);
+        Bound::exclusive((key1.as_str(), key2.as_str()).joined_key())
+    });
+
+    let pools = POOLS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, pool)| to_pool_response(pool)))
+        .collect::<StdResult<_>>()?;
+    Ok(PoolsResponse { pools })
+}
+
+pub fn query_pools_by_token(
+    deps: Deps,
+    denom: Denom,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<PoolsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?
+        .map(|addr| Bound::exclusive(addr.as_bytes()));
+
+    let pools = TOKEN_POOLS
+        .prefix(&denom_key(&denom))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, pool)| to_pool_response(pool)))
+        .collect::<StdResult<_>>()?;
+    Ok(PoolsResponse { pools })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id != INSTANTIATE_POOL_REPLY_ID {
+        return Err(ContractError::UnknownReplyId { id: msg.id });
+    };
+    let res =
+        parse_reply_instantiate_data(msg).map_err(|_| ContractError::InstantiatePoolError {})?;
+    let pool_address = deps.api.addr_validate(&res.contract_address)?;
+
+    let (token1_denom, token2_denom) = PENDING_POOL.load(deps.storage)?;
+    PENDING_POOL.remove(deps.storage);
+
+    let (pair_key1, pair_key2) = pair_key(&token1_denom, &token2_denom);
+    let key1 = denom_key(&token1_denom);
+    let key2 = denom_key(&token2_denom);
+    let pool = PoolInfo {
+        token1_denom,
+        token2_denom,
+        pool_address: pool_address.clone(),
+    };
+    POOLS.save(deps.storage, (&pair_key1, &pair_key2), &pool)?;
+    TOKEN_POOLS.save(deps.storage, (&key1, &pool_address), &pool)?;
+    TOKEN_POOLS.save(deps.storage, (&key2, &pool_address), &pool)?;
+
+    Ok(Response::new().add_attribute("pool_address", pool_address))
+}
@@ -0,0 +1,23 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("A pool can not be created for two identical tokens")]
+    IdenticalDenoms {},
+
+    #[error("A pool for this pair already exists: {pool_address}")]
+    PoolAlreadyExists { pool_address: String },
+
+    #[error("Uknown reply id: {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("Failed to instantiate pool")]
+    InstantiatePoolError {},
+}
@@ -0,0 +1,309 @@
+#![cfg(test)]
+
+use cosmwasm_std::{Addr, Decimal, Empty, Uint128};
+use cw20::{Cw20Coin, Denom};
+use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+use std::str::FromStr;
+
+use crate::error::ContractError;
+use crate::msg::{
+    ConfigResponse, ExecuteMsg, InstantiateMsg, PoolResponse, PoolsResponse, QueryMsg,
+};
+
+fn mock_app() -> App {
+    App::default()
+}
+
+pub fn contract_factory() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    )
+    .with_reply(crate::contract::reply);
+    Box::new(contract)
+}
+
+pub fn contract_amm() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        wasmswap::contract::execute,
+        wasmswap::contract::instantiate,
+        wasmswap::contract::query,
+    )
+    .with_reply(wasmswap::contract::reply)
+    .with_migrate(wasmswap::contract::migrate);
+    Box::new(contract)
+}
+
+pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    );
+    Box::new(contract)
+}
+
+fn create_factory(router: &mut App, owner: &Addr) -> Addr {
+    let cw20_id = router.store_code(contract_cw20());
+    let amm_id = router.store_code(contract_amm());
+    let factory_id = router.store_code(contract_factory());
+    let msg = InstantiateMsg {
+        pool_code_id: amm_id,
+        lp_token_code_id: cw20_id,
+        owner: Some(owner.to_string()),
+        protocol_fee_recipient: owner.to_string(),
+        protocol_fee_percent: Decimal::zero(),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+    };
+    router
+        .instantiate_contract(factory_id, owner.clone(), &msg, &[], "factory", None)
+        .unwrap()
+}
+
+fn create_cw20(router: &mut App, owner: &Addr, symbol: &str) -> Addr {
+    let cw20_id = router.store_code(contract_cw20());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: symbol.to_lowercase(),
+        symbol: symbol.to_string(),
+        decimals: 6,
+        initial_balances: vec![Cw20Coin {
+            address: owner.to_string(),
+            amount: Uint128::new(5000),
+        }],
+        mint: None,
+        marketing: None,
+    };
+    router
+        .instantiate_contract(cw20_id, owner.clone(), &msg, &[], symbol, None)
+        .unwrap()
+}
+
+fn create_pool(
+    router: &mut App,
+    owner: &Addr,
+    factory: &Addr,
+    token1_denom: Denom,
+    token2_denom: Denom,
+) -> PoolResponse {
+    let msg = ExecuteMsg::CreatePool {
+        token1_denom: token1_denom.clone(),
+        token2_denom: token2_denom.clone(),
+    };
+    router
+        .execute_contract(owner.clone(), factory.clone(), &msg, &[])
+        .unwrap();
+    get_pool(router, factory, token1_denom, token2_denom)
+}
+
+fn get_pool(
+    router: &App,
+    factory: &Addr,
+    token1_denom: Denom,
+    token2_denom: Denom,
+) -> PoolResponse {
+    router
+        .wrap()
+        .query_wasm_smart(
+            factory,
+            &QueryMsg::Pool {
+                token1_denom,
+                token2_denom,
+            },
+        )
+        .unwrap()
+}
+
+#[test]
+fn create_pools() {
+    let mut router = mock_app();
+
+    let owner = Addr::unchecked("owner");
+    let factory = create_factory(&mut router, &owner);
+    let cw20_token = create_cw20(&mut router, &owner, "CWTOKEN");
+
+    let juno = Denom::Native("juno".to_string());
+    let atom = Denom::Native("atom".to_string());
+    let cw20 = Denom::Cw20(cw20_token);
+
+    let pool = create_pool(&mut router, &owner, &factory, juno.clone(), cw20.clone());
+    assert_eq!(pool.token1_denom, juno);
+    assert_eq!(pool.token2_denom, cw20);
+
+    // The pool is found regardless of the token order
+    let reversed = get_pool(&router, &factory, cw20.clone(), juno.clone());
+    assert_eq!(reversed, pool);
+
+    // The created pool is configured by the factory and has its own lp token
+    let info: wasmswap::msg::InfoResponse = router
+        .wrap()
+        .query_wasm_smart(&pool.pool_address, &wasmswap::msg::QueryMsg::Info {})
+        .unwrap();
+    assert_eq!(info.token1_denom, juno);
+    assert_eq!(info.token2_denom, cw20);
+    assert_ne!(info.lp_token_address, "");
+    let fee: wasmswap::msg::FeeResponse = router
+        .wrap()
+        .query_wasm_smart(&pool.pool_address, &wasmswap::msg::QueryMsg::Fee {})
+        .unwrap();
+    assert_eq!(fee.owner, Some(owner.to_string()));
+    assert_eq!(fee.lp_fee_percent, Decimal::from_str("0.3").unwrap());
+
+    // Duplicate pairs are rejected in either order
+    let msg = ExecuteMsg::CreatePool {
+        token1_denom: cw20.clone(),
+        token2_denom: juno.clone(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), factory.clone(), &msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::PoolAlreadyExists {
+            pool_address: pool.pool_address.clone()
+        },
+        err.downcast().unwrap()
+    );
+
+    let msg = ExecuteMsg::CreatePool {
+        token1_denom: atom.clone(),
+        token2_denom: atom.clone(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), factory.clone(), &msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::IdenticalDenoms {}, err.downcast().unwrap());
+
+    let pool2 = create_pool(&mut router, &owner, &factory, juno.clone(), atom.clone());
+    let pool3 = create_pool(&mut router, &owner, &factory, atom.clone(), cw20.clone());
+
+    // List all pools, ordered by their pair keys
+    let res: PoolsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &factory,
+            &QueryMsg::Pools {
+                start_after: None,
+                limit: Some(2),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.pools, vec![pool2.clone(), pool3.clone()]);
+    let res: PoolsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &factory,
+            &QueryMsg::Pools {
+                start_after: Some((cw20.clone(), atom)),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(res.pools, vec![pool.clone()]);
+
+    // List pools by token, ordered by pool address
+    let res: PoolsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &factory,
+            &QueryMsg::PoolsByToken {
+                denom: juno.clone(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(res.pools, vec![pool.clone(), pool2.clone()]);
+    let res: PoolsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &factory,
+            &QueryMsg::PoolsByToken {
+                denom: juno,
+                start_after: Some(pool.pool_address),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(res.pools, vec![pool2]);
+    let res: PoolsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &factory,
+            &QueryMsg::PoolsByToken {
+                denom: cw20,
+                start_after: None,
+                limit: Some(1),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.pools.len(), 1);
+}
+
+#[test]
+fn update_config() {
+    let mut router = mock_app();
+
+    let owner = Addr::unchecked("owner");
+    let factory = create_factory(&mut router, &owner);
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(&factory, &QueryMsg::Config {})
+        .unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: "new_owner".to_string(),
+        pool_code_id: config.pool_code_id,
+        lp_token_code_id: config.lp_token_code_id,
+        protocol_fee_recipient: "fee_recipient".to_string(),
+        protocol_fee_percent: Decimal::from_str("0.1").unwrap(),
+        lp_fee_percent: Decimal::from_str("0.2").unwrap(),
+    };
+    let err = router
+        .execute_contract(Addr::unchecked("invalid_owner"), factory.clone(), &msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    router
+        .execute_contract(owner.clone(), factory.clone(), &msg, &[])
+        .unwrap();
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(&factory, &QueryMsg::Config {})
+        .unwrap();
+    assert_eq!(config.owner, Some("new_owner".to_string()));
+    assert_eq!(config.protocol_fee_recipient, "fee_recipient".to_string());
+    assert_eq!(
+        config.protocol_fee_percent,
+        Decimal::from_str("0.1").unwrap()
+    );
+    assert_eq!(config.lp_fee_percent, Decimal::from_str("0.2").unwrap());
+
+    // Only the owner can renounce, after which the config can not be updated anymore
+    let new_owner = Addr::unchecked("new_owner");
+    let err = router
+        .execute_contract(
+            owner,
+            factory.clone(),
+            &ExecuteMsg::RenounceOwnership {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            new_owner.clone(),
+            factory.clone(),
+            &ExecuteMsg::RenounceOwnership {},
+            &[],
+        )
+        .unwrap();
+    let config: ConfigResponse = router
+        .wrap()
+        .query_wasm_smart(&factory, &QueryMsg::Config {})
+        .unwrap();
+    assert_eq!(config.owner, None);
+    let err = router
+        .execute_contract(new_owner, factory, &msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+}
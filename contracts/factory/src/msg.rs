@@ -0,0 +1,82 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::Decimal;
+
+use cw20::Denom;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub pool_code_id: u64,
+    pub lp_token_code_id: u64,
+    pub owner: Option<String>,
+    pub protocol_fee_recipient: String,
+    // NOTE: Fees percents are out of 100 e.g., 1 = 1%
+    pub protocol_fee_percent: Decimal,
+    pub lp_fee_percent: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Instantiates a new wasmswap pool for the pair, fails if the pair already has a pool
+    CreatePool {
+        token1_denom: Denom,
+        token2_denom: Denom,
+    },
+    /// Owner only. The owner can only be removed with `RenounceOwnership`
+    UpdateConfig {
+        owner: String,
+        pool_code_id: u64,
+        lp_token_code_id: u64,
+        protocol_fee_recipient: String,
+        protocol_fee_percent: Decimal,
+        lp_fee_percent: Decimal,
+    },
+    /// Owner only. Removes the owner for good, which disables `UpdateConfig`
+    RenounceOwnership {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    /// Returns the pool of a pair, the order of the denoms does not matter
+    Pool {
+        token1_denom: Denom,
+        token2_denom: Denom,
+    },
+    /// Lists all pools ordered by pair
+    Pools {
+        start_after: Option<(Denom, Denom)>,
+        limit: Option<u32>,
+    },
+    /// Lists all pools containing the given token ordered by pool address
+    PoolsByToken {
+        denom: Denom,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ConfigResponse {
+    pub owner: Option<String>,
+    pub pool_code_id: u64,
+    pub lp_token_code_id: u64,
+    pub protocol_fee_recipient: String,
+    pub protocol_fee_percent: Decimal,
+    pub lp_fee_percent: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolResponse {
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    pub pool_address: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolsResponse {
+    pub pools: Vec<PoolResponse>,
+}
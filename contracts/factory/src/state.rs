@@ -0,0 +1,52 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Addr, Decimal};
+use cw20::Denom;
+use cw_storage_plus::{Item, Map};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Config {
+    pub owner: Option<Addr>,
+    pub pool_code_id: u64,
+    pub lp_token_code_id: u64,
+    pub protocol_fee_recipient: Addr,
+    pub protocol_fee_percent: Decimal,
+    pub lp_fee_percent: Decimal,
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolInfo {
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    pub pool_address: Addr,
+}
+
+// Pools keyed by their sorted pair of denom keys, see `denom_key`
+pub const POOLS: Map<(&str, &str), PoolInfo> = Map::new("pools");
+// Pools keyed by each of their denom keys and the pool address
+pub const TOKEN_POOLS: Map<(&str, &Addr), PoolInfo> = Map::new("token_pools");
+
+// Pair of the pool that is being instantiated, consumed in the reply
+pub const PENDING_POOL: Item<(Denom, Denom)> = Item::new("pending_pool");
+
+/// Returns a storage key that is unique for a native denom or a cw20 contract
+pub fn denom_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(denom) => format!("native:{}", denom),
+        Denom::Cw20(addr) => format!("cw20:{}", addr),
+    }
+}
+
+/// Returns the denom keys of a pair in a canonical order, so both token orders map to one pool
+pub fn pair_key(token1_denom: &Denom, token2_denom: &Denom) -> (String, String) {
+    let key1 = denom_key(token1_denom);
+    let key2 = denom_key(token2_denom);
+    if key1 < key2 {
+        (key1, key2)
+    } else {
+        (key2, key1)
+    }
+}
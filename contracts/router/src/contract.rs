@@ -0,0 +1,364 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    attr, coins, from_binary, to_binary, Addr, Binary, BlockInfo, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Reply, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+};
+use cw2::set_contract_version;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom, Expiration};
+use wasmswap::msg::{
+    InfoResponse, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, TokenSelect,
+};
+
+use crate::error::ContractError;
+use crate::msg::{
+    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, SimulateSwapOperationsResponse,
+    SwapOperation,
+};
+use crate::state::{Route, ROUTE};
+
+// Version info for migration info
+pub const CONTRACT_NAME: &str = "crates.io:wasmswap-router";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const SWAP_REPLY_ID: u64 = 0;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::ExecuteSwapOperations {
+            operations,
+            min_output,
+            recipient,
+            expiration,
+        } => {
+            let first_operation = operations
+                .first()
+                .ok_or(ContractError::NoSwapOperations {})?;
+            let (input_denom, _) = get_operation_denoms(deps.as_ref(), first_operation)?;
+            // Funds the route does not swap would be stranded in the router
+            let input_amount = match &input_denom {
+                Denom::Native(denom) => match info.funds.as_slice() {
+                    [] => Uint128::zero(),
+                    [coin] if &coin.denom == denom => coin.amount,
+                    _ => return Err(ContractError::UnexpectedFunds {}),
+                },
+                // Cw20 routes are started with cw20 `Send`
+                Denom::Cw20(_) if !info.funds.is_empty() => {
+                    return Err(ContractError::UnexpectedFunds {})
+                }
+                Denom::Cw20(_) => Uint128::zero(),
+            };
+            if input_amount.is_zero() {
+                return Err(ContractError::InsufficientFunds {});
+            }
+
+            let recipient = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => info.sender,
+            };
+            execute_swap_operations(
+                deps,
+                env,
+                operations,
+                input_denom,
+                input_amount,
+                recipient,
+                min_output,
+                expiration,
+            )
+        }
+        ExecuteMsg::Receive(cw20_msg) => execute_receive(deps, env, info, cw20_msg),
+    }
+}
+
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    if !info.funds.is_empty() {
+        return Err(ContractError::UnexpectedFunds {});
+    }
+    let sender = deps.api.addr_validate(&cw20_msg.sender)?;
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::ExecuteSwapOperations {
+            operations,
+            min_output,
+            recipient,
+            expiration,
+        } => {
+            let recipient = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => sender,
+            };
+            execute_swap_operations(
+                deps,
+                env,
+                operations,
+                Denom::Cw20(info.sender),
+                cw20_msg.amount,
+                recipient,
+                min_output,
+                expiration,
+            )
+        }
+    }
+}
+
+fn check_expiration(
+    expiration: &Option<Expiration>,
+    block: &BlockInfo,
+) -> Result<(), ContractError> {
+    match expiration {
+        Some(e) => {
+            if e.is_expired(block) {
+                return Err(ContractError::MsgExpirationError {});
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_swap_operations(
+    deps: DepsMut,
+    env: Env,
+    operations: Vec<SwapOperation>,
+    input_denom: Denom,
+    input_amount: Uint128,
+    recipient: Addr,
+    min_output: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    if operations.is_empty() {
+        return Err(ContractError::NoSwapOperations {});
+    }
+
+    let route = Route {
+        operations,
+        index: 0,
+        recipient,
+        min_output,
+        expiration,
+        output_denom: input_denom.clone(),
+        output_balance_before: Uint128::zero(),
+    };
+    let swap_msg = dispatch_next_operation(deps, &env, route, input_denom, input_amount)?;
+
+    Ok(Response::new()
+        .add_submessage(swap_msg)
+        .add_attributes(vec![
+            attr("action", "execute_swap_operations"),
+            attr("input_amount", input_amount),
+        ]))
+}
+
+/// Returns the input and output denom of an operation
+fn get_operation_denoms(deps: Deps, operation: &SwapOperation) -> StdResult<(Denom, Denom)> {
+    let info: InfoResponse = deps
+        .querier
+        .query_wasm_smart(&operation.pool_address, &wasmswap::msg::QueryMsg::Info {})?;
+    Ok(match operation.input_token {
+        TokenSelect::Token1 => (info.token1_denom, info.token2_denom),
+        TokenSelect::Token2 => (info.token2_denom, info.token1_denom),
+    })
+}
+
+fn get_balance(deps: Deps, address: &Addr, denom: &Denom) -> StdResult<Uint128> {
+    match denom {
+        Denom::Native(denom) => Ok(deps.querier.query_balance(address, denom)?.amount),
+        Denom::Cw20(token_addr) => {
+            let resp: cw20::BalanceResponse = deps.querier.query_wasm_smart(
+                token_addr,
+                &cw20::Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )?;
+            Ok(resp.balance)
+        }
+    }
+}
+
+/// Sends the first remaining operation of the route to its pool as a submessage, the output is
+/// always sent back to the router and handled in the reply
+fn dispatch_next_operation(
+    deps: DepsMut,
+    env: &Env,
+    mut route: Route,
+    input_denom: Denom,
+    input_amount: Uint128,
+) -> Result<SubMsg, ContractError> {
+    let operation = route.operations.remove(0);
+    let pool_address = deps.api.addr_validate(&operation.pool_address)?;
+    let (operation_input_denom, output_denom) = get_operation_denoms(deps.as_ref(), &operation)?;
+    if operation_input_denom != input_denom {
+        return Err(ContractError::InvalidSwapOperation { index: route.index });
+    }
+
+    let min_token = operation.min_output.unwrap_or_default();
+    let swap_msg: CosmosMsg = match input_denom {
+        Denom::Native(denom) => WasmMsg::Execute {
+            contract_addr: pool_address.into_string(),
+            msg: to_binary(&wasmswap::msg::ExecuteMsg::SwapAndSendTo {
+                input_token: operation.input_token,
+                input_amount,
+                recipient: env.contract.address.to_string(),
+                min_token,
+                expiration: route.expiration,
+            })?,
+            funds: coins(input_amount.u128(), denom),
+        }
+        .into(),
+        Denom::Cw20(token_addr) => WasmMsg::Execute {
+            contract_addr: token_addr.into_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: pool_address.into_string(),
+                amount: input_amount,
+                msg: to_binary(&wasmswap::msg::Cw20HookMsg::SwapAndSendTo {
+                    recipient: env.contract.address.to_string(),
+                    min_token,
+                    expiration: route.expiration,
+                })?,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+    };
+
+    route.output_balance_before = get_balance(deps.as_ref(), &env.contract.address, &output_denom)?;
+    route.output_denom = output_denom;
+    ROUTE.save(deps.storage, &route)?;
+
+    Ok(SubMsg::reply_on_success(swap_msg, SWAP_REPLY_ID))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id != SWAP_REPLY_ID {
+        return Err(ContractError::UnknownReplyId { id: msg.id });
+    };
+
+    let mut route = ROUTE.load(deps.storage)?;
+    let output_amount = get_balance(deps.as_ref(), &env.contract.address, &route.output_denom)?
+        .checked_sub(route.output_balance_before)
+        .map_err(StdError::overflow)?;
+
+    if !route.operations.is_empty() {
+        route.index += 1;
+        let input_denom = route.output_denom.clone();
+        let swap_msg = dispatch_next_operation(deps, &env, route, input_denom, output_amount)?;
+        return Ok(Response::new().add_submessage(swap_msg));
+    }
+
+    ROUTE.remove(deps.storage);
+    if output_amount < route.min_output {
+        return Err(ContractError::MinOutputError {
+            min: route.min_output,
+            available: output_amount,
+        });
+    }
+
+    let transfer_msg: CosmosMsg = match route.output_denom {
+        Denom::Native(denom) => cosmwasm_std::BankMsg::Send {
+            to_address: route.recipient.to_string(),
+            amount: coins(output_amount.u128(), denom),
+        }
+        .into(),
+        Denom::Cw20(token_addr) => WasmMsg::Execute {
+            contract_addr: token_addr.into_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: route.recipient.to_string(),
+                amount: output_amount,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+    };
+
+    Ok(Response::new()
+        .add_message(transfer_msg)
+        .add_attributes(vec![
+            attr("output_amount", output_amount),
+            attr("recipient", route.recipient),
+        ]))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::SimulateSwapOperations {
+            operations,
+            input_amount,
+        } => to_binary(&query_simulate_swap_operations(
+            deps,
+            operations,
+            input_amount,
+        )?),
+    }
+}
+
+pub fn query_simulate_swap_operations(
+    deps: Deps,
+    operations: Vec<SwapOperation>,
+    input_amount: Uint128,
+) -> StdResult<SimulateSwapOperationsResponse> {
+    let mut previous_output_denom: Option<Denom> = None;
+    let mut amount = input_amount;
+    for (index, operation) in operations.iter().enumerate() {
+        let (input_denom, output_denom) = get_operation_denoms(deps, operation)?;
+        if matches!(&previous_output_denom, Some(denom) if denom != &input_denom) {
+            return Err(StdError::generic_err(
+                ContractError::InvalidSwapOperation {
+                    index: index as u64,
+                }
+                .to_string(),
+            ));
+        }
+
+        amount = match operation.input_token {
+            TokenSelect::Token1 => {
+                let resp: Token1ForToken2PriceResponse = deps.querier.query_wasm_smart(
+                    &operation.pool_address,
+                    &wasmswap::msg::QueryMsg::Token1ForToken2Price {
+                        token1_amount: amount,
+                    },
+                )?;
+                resp.token2_amount
+            }
+            TokenSelect::Token2 => {
+                let resp: Token2ForToken1PriceResponse = deps.querier.query_wasm_smart(
+                    &operation.pool_address,
+                    &wasmswap::msg::QueryMsg::Token2ForToken1Price {
+                        token2_amount: amount,
+                    },
+                )?;
+                resp.token1_amount
+            }
+        };
+        previous_output_denom = Some(output_denom);
+    }
+
+    Ok(SimulateSwapOperationsResponse {
+        output_amount: amount,
+    })
+}
@@ -0,0 +1,29 @@
+use cosmwasm_std::{StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Must provide at least one swap operation")]
+    NoSwapOperations {},
+
+    #[error("Swap operation {index} does not take the output of the previous operation as input")]
+    InvalidSwapOperation { index: u64 },
+
+    #[error("InsufficientFunds")]
+    InsufficientFunds {},
+
+    #[error("Only the native input token of the route may be sent")]
+    UnexpectedFunds {},
+
+    #[error("Min output error: min: {min}, available: {available}")]
+    MinOutputError { min: Uint128, available: Uint128 },
+
+    #[error("MsgExpirationError")]
+    MsgExpirationError {},
+
+    #[error("Uknown reply id: {id}")]
+    UnknownReplyId { id: u64 },
+}
@@ -0,0 +1,692 @@
+#![cfg(test)]
+
+use std::borrow::BorrowMut;
+
+use cosmwasm_std::{coins, to_binary, Addr, Coin, Decimal, Empty, Uint128};
+use cw20::{Cw20Coin, Cw20Contract, Cw20ExecuteMsg, Denom};
+use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+use std::str::FromStr;
+use wasmswap::msg::{InfoResponse, TokenSelect};
+
+use crate::error::ContractError;
+use crate::msg::{
+    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, SimulateSwapOperationsResponse,
+    SwapOperation,
+};
+
+fn mock_app() -> App {
+    App::default()
+}
+
+pub fn contract_router() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    )
+    .with_reply(crate::contract::reply);
+    Box::new(contract)
+}
+
+pub fn contract_amm() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        wasmswap::contract::execute,
+        wasmswap::contract::instantiate,
+        wasmswap::contract::query,
+    )
+    .with_reply(wasmswap::contract::reply)
+    .with_migrate(wasmswap::contract::migrate);
+    Box::new(contract)
+}
+
+pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    );
+    Box::new(contract)
+}
+
+fn create_cw20(router: &mut App, owner: &Addr, symbol: &str) -> Cw20Contract {
+    let cw20_id = router.store_code(contract_cw20());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: symbol.to_lowercase(),
+        symbol: symbol.to_string(),
+        decimals: 6,
+        initial_balances: vec![Cw20Coin {
+            address: owner.to_string(),
+            amount: Uint128::new(5_000_000_000),
+        }],
+        mint: None,
+        marketing: None,
+    };
+    let addr = router
+        .instantiate_contract(cw20_id, owner.clone(), &msg, &[], symbol, None)
+        .unwrap();
+    Cw20Contract(addr)
+}
+
+// Creates a pool and adds `amount` of both tokens as liquidity
+fn create_amm(
+    router: &mut App,
+    owner: &Addr,
+    token1_denom: Denom,
+    token2_denom: Denom,
+    amount: Uint128,
+) -> Addr {
+    let cw20_id = router.store_code(contract_cw20());
+    let amm_id = router.store_code(contract_amm());
+    let msg = wasmswap::msg::InstantiateMsg {
+        token1_denom: token1_denom.clone(),
+        token2_denom: token2_denom.clone(),
+        lp_token_code_id: cw20_id,
+        owner: Some(owner.to_string()),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+        protocol_fee_percent: Decimal::zero(),
+        protocol_fee_recipient: owner.to_string(),
+        pool_type: wasmswap::state::PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
+    };
+    let amm_addr = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap();
+
+    let mut funds = vec![];
+    for denom in [token1_denom, token2_denom] {
+        match denom {
+            Denom::Native(denom) => funds.push(Coin { denom, amount }),
+            Denom::Cw20(addr) => {
+                let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: amm_addr.to_string(),
+                    amount,
+                    expires: None,
+                };
+                router
+                    .execute_contract(owner.clone(), addr, &allowance_msg, &[])
+                    .unwrap();
+            }
+        }
+    }
+    funds.sort_by(|a, b| a.denom.cmp(&b.denom));
+
+    let add_liquidity_msg = wasmswap::msg::ExecuteMsg::AddLiquidity {
+        token1_amount: amount,
+        min_liquidity: Uint128::zero(),
+        max_token2: amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &add_liquidity_msg, &funds)
+        .unwrap();
+    amm_addr
+}
+
+fn create_router(router: &mut App, owner: &Addr) -> Addr {
+    let router_id = router.store_code(contract_router());
+    router
+        .instantiate_contract(
+            router_id,
+            owner.clone(),
+            &InstantiateMsg {},
+            &[],
+            "router",
+            None,
+        )
+        .unwrap()
+}
+
+fn get_info(router: &App, contract_addr: &Addr) -> InfoResponse {
+    router
+        .wrap()
+        .query_wasm_smart(contract_addr, &wasmswap::msg::QueryMsg::Info {})
+        .unwrap()
+}
+
+fn bank_balance(router: &App, addr: &Addr, denom: &str) -> Uint128 {
+    router.wrap().query_balance(addr, denom).unwrap().amount
+}
+
+#[test]
+fn multi_hop_swap() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let token_a = create_cw20(&mut router, &owner, "TOKENA");
+    let token_b = create_cw20(&mut router, &owner, "TOKENB");
+    let liquidity = Uint128::new(1_000_000);
+    let amm1 = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(token_a.addr()),
+        liquidity,
+    );
+    let amm2 = create_amm(
+        &mut router,
+        &owner,
+        Denom::Cw20(token_a.addr()),
+        Denom::Cw20(token_b.addr()),
+        liquidity,
+    );
+    let amm3 = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(IBC_TOKEN_DENOM.into()),
+        Denom::Cw20(token_b.addr()),
+        liquidity,
+    );
+
+    let swap_router = create_router(&mut router, &owner);
+
+    // juno -> A -> B -> atom
+    let operations = vec![
+        SwapOperation {
+            pool_address: amm1.to_string(),
+            input_token: TokenSelect::Token1,
+            min_output: None,
+        },
+        SwapOperation {
+            pool_address: amm2.to_string(),
+            input_token: TokenSelect::Token1,
+            min_output: Some(Uint128::new(9_000)),
+        },
+        SwapOperation {
+            pool_address: amm3.to_string(),
+            input_token: TokenSelect::Token2,
+            min_output: None,
+        },
+    ];
+
+    let simulation: SimulateSwapOperationsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &swap_router,
+            &QueryMsg::SimulateSwapOperations {
+                operations: operations.clone(),
+                input_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(simulation.output_amount, Uint128::new(9_622));
+
+    let buyer = Addr::unchecked("buyer");
+    let funds = coins(10_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &buyer, funds).unwrap()
+    });
+
+    // A hop minimum that can not be met reverts the whole route
+    let mut failing_operations = operations.clone();
+    failing_operations[1].min_output = Some(Uint128::new(9_746));
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: failing_operations,
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            buyer.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        wasmswap::error::ContractError::SwapMinError {
+            min: Uint128::new(9_746),
+            available: Uint128::new(9_745)
+        },
+        err.downcast().unwrap()
+    );
+    assert_eq!(
+        bank_balance(&router, &buyer, NATIVE_TOKEN_DENOM),
+        Uint128::new(10_000)
+    );
+    assert_eq!(get_info(&router, &amm1).token1_reserve, liquidity);
+
+    // The final minimum is checked by the router
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: operations.clone(),
+        min_output: Uint128::new(9_623),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            buyer.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MinOutputError {
+            min: Uint128::new(9_623),
+            available: Uint128::new(9_622)
+        },
+        err.downcast().unwrap()
+    );
+
+    let recipient = Addr::unchecked("recipient");
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations,
+        min_output: Uint128::new(9_622),
+        recipient: Some(recipient.to_string()),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            buyer.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    assert_eq!(
+        bank_balance(&router, &recipient, IBC_TOKEN_DENOM),
+        Uint128::new(9_622)
+    );
+    assert_eq!(
+        bank_balance(&router, &buyer, NATIVE_TOKEN_DENOM),
+        Uint128::zero()
+    );
+    let info = get_info(&router, &amm1);
+    assert_eq!(info.token1_reserve, Uint128::new(1_010_000));
+    assert_eq!(info.token2_reserve, Uint128::new(990_129));
+    let info = get_info(&router, &amm2);
+    assert_eq!(info.token1_reserve, Uint128::new(1_009_871));
+    assert_eq!(info.token2_reserve, Uint128::new(990_255));
+    let info = get_info(&router, &amm3);
+    assert_eq!(info.token1_reserve, Uint128::new(990_378));
+    assert_eq!(info.token2_reserve, Uint128::new(1_009_745));
+
+    // Nothing is left in the router
+    assert_eq!(
+        token_a.balance(&router, swap_router.clone()).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        token_b.balance(&router, swap_router.clone()).unwrap(),
+        Uint128::zero()
+    );
+
+    // Cw20 input through Send, A -> B -> atom
+    let operations = vec![
+        SwapOperation {
+            pool_address: amm2.to_string(),
+            input_token: TokenSelect::Token1,
+            min_output: None,
+        },
+        SwapOperation {
+            pool_address: amm3.to_string(),
+            input_token: TokenSelect::Token2,
+            min_output: None,
+        },
+    ];
+    let simulation: SimulateSwapOperationsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &swap_router,
+            &QueryMsg::SimulateSwapOperations {
+                operations: operations.clone(),
+                input_amount: Uint128::new(5_000),
+            },
+        )
+        .unwrap();
+
+    let owner_balance = bank_balance(&router, &owner, IBC_TOKEN_DENOM);
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: swap_router.to_string(),
+        amount: Uint128::new(5_000),
+        msg: to_binary(&Cw20HookMsg::ExecuteSwapOperations {
+            operations,
+            min_output: simulation.output_amount,
+            recipient: None,
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(owner.clone(), token_a.addr(), &send_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&router, &owner, IBC_TOKEN_DENOM),
+        owner_balance + simulation.output_amount
+    );
+
+    // Operations have to be chained, juno -> A can not be followed by atom -> B
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: vec![
+            SwapOperation {
+                pool_address: amm1.to_string(),
+                input_token: TokenSelect::Token1,
+                min_output: None,
+            },
+            SwapOperation {
+                pool_address: amm3.to_string(),
+                input_token: TokenSelect::Token1,
+                min_output: None,
+            },
+        ],
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidSwapOperation { index: 1 },
+        err.downcast().unwrap()
+    );
+
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: vec![],
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(owner, swap_router, &msg, &coins(10_000, NATIVE_TOKEN_DENOM))
+        .unwrap_err();
+    assert_eq!(ContractError::NoSwapOperations {}, err.downcast().unwrap());
+}
+
+#[test]
+fn route_funds() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let token_a = create_cw20(&mut router, &owner, "TOKENA");
+    let liquidity = Uint128::new(1_000_000);
+    let amm = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(token_a.addr()),
+        liquidity,
+    );
+    let swap_router = create_router(&mut router, &owner);
+
+    let swap_msg = |input_token: TokenSelect| ExecuteMsg::ExecuteSwapOperations {
+        operations: vec![SwapOperation {
+            pool_address: amm.to_string(),
+            input_token,
+            min_output: None,
+        }],
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+
+    // Native input must be the only coin sent
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &swap_msg(TokenSelect::Token1),
+            &[
+                Coin::new(1_000, IBC_TOKEN_DENOM),
+                Coin::new(10_000, NATIVE_TOKEN_DENOM),
+            ],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::UnexpectedFunds {}, err.downcast().unwrap());
+
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &swap_msg(TokenSelect::Token1),
+            &coins(10_000, IBC_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::UnexpectedFunds {}, err.downcast().unwrap());
+
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &swap_msg(TokenSelect::Token1),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::InsufficientFunds {}, err.downcast().unwrap());
+
+    // Cw20 input is sent with cw20 `Send`, native funds are rejected
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &swap_msg(TokenSelect::Token2),
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::UnexpectedFunds {}, err.downcast().unwrap());
+
+    router
+        .execute_contract(
+            owner.clone(),
+            swap_router.clone(),
+            &swap_msg(TokenSelect::Token1),
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    assert_eq!(
+        bank_balance(&router, &swap_router, NATIVE_TOKEN_DENOM),
+        Uint128::zero()
+    );
+    assert_eq!(
+        bank_balance(&router, &swap_router, IBC_TOKEN_DENOM),
+        Uint128::zero()
+    );
+    assert_eq!(
+        get_info(&router, &amm).token1_reserve,
+        Uint128::new(1_010_000)
+    );
+}
+
+#[test]
+fn failing_hop_reverts_route() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let token_a = create_cw20(&mut router, &owner, "TOKENA");
+    let token_b = create_cw20(&mut router, &owner, "TOKENB");
+    let liquidity = Uint128::new(1_000_000);
+    let amms = [
+        create_amm(
+            &mut router,
+            &owner,
+            Denom::Native(NATIVE_TOKEN_DENOM.into()),
+            Denom::Cw20(token_a.addr()),
+            liquidity,
+        ),
+        create_amm(
+            &mut router,
+            &owner,
+            Denom::Cw20(token_a.addr()),
+            Denom::Cw20(token_b.addr()),
+            liquidity,
+        ),
+        create_amm(
+            &mut router,
+            &owner,
+            Denom::Native(IBC_TOKEN_DENOM.into()),
+            Denom::Cw20(token_b.addr()),
+            liquidity,
+        ),
+        create_amm(
+            &mut router,
+            &owner,
+            Denom::Native(IBC_TOKEN_DENOM.into()),
+            Denom::Native(NATIVE_TOKEN_DENOM.into()),
+            liquidity,
+        ),
+    ];
+    let swap_router = create_router(&mut router, &owner);
+
+    // juno -> A -> B -> atom -> juno
+    let input_tokens = [
+        TokenSelect::Token1,
+        TokenSelect::Token1,
+        TokenSelect::Token2,
+        TokenSelect::Token1,
+    ];
+    let mut operations: Vec<SwapOperation> = amms
+        .iter()
+        .zip(input_tokens)
+        .map(|(amm, input_token)| SwapOperation {
+            pool_address: amm.to_string(),
+            input_token,
+            min_output: None,
+        })
+        .collect();
+    let simulation: SimulateSwapOperationsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &swap_router,
+            &QueryMsg::SimulateSwapOperations {
+                operations: operations.clone(),
+                input_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+
+    let buyer = Addr::unchecked("buyer");
+    let funds = coins(10_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &buyer, funds).unwrap()
+    });
+    let reserves = |router: &App| -> Vec<(Uint128, Uint128)> {
+        amms.iter()
+            .map(|amm| {
+                let info = get_info(router, amm);
+                (info.token1_reserve, info.token2_reserve)
+            })
+            .collect()
+    };
+    let reserves_before = reserves(&router);
+
+    // The last hop fails after three successful ones, nothing of the route is kept
+    operations[3].min_output = Some(simulation.output_amount + Uint128::new(1));
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: operations.clone(),
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            buyer.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        wasmswap::error::ContractError::SwapMinError {
+            min: simulation.output_amount + Uint128::new(1),
+            available: simulation.output_amount
+        },
+        err.downcast().unwrap()
+    );
+    assert_eq!(reserves(&router), reserves_before);
+    assert_eq!(
+        bank_balance(&router, &buyer, NATIVE_TOKEN_DENOM),
+        Uint128::new(10_000)
+    );
+
+    operations[3].min_output = Some(simulation.output_amount);
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations,
+        min_output: simulation.output_amount,
+        recipient: None,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            buyer.clone(),
+            swap_router.clone(),
+            &msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    assert_eq!(
+        bank_balance(&router, &buyer, NATIVE_TOKEN_DENOM),
+        simulation.output_amount
+    );
+    assert_eq!(
+        bank_balance(&router, &swap_router, IBC_TOKEN_DENOM),
+        Uint128::zero()
+    );
+    assert_eq!(
+        token_a.balance(&router, swap_router.clone()).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        token_b.balance(&router, swap_router).unwrap(),
+        Uint128::zero()
+    );
+}
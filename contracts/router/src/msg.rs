@@ -0,0 +1,62 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::Uint128;
+
+use cw20::{Cw20ReceiveMsg, Expiration};
+use wasmswap::msg::TokenSelect;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {}
+
+/// A single hop of a route
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapOperation {
+    pub pool_address: String,
+    /// Pool token that is sold in this hop, it must be the output of the previous hop
+    pub input_token: TokenSelect,
+    /// Optional minimum output of this hop, enforced by the pool
+    pub min_output: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Swaps the attached native tokens through all operations in order, the funds must be a
+    /// single coin of the input token
+    ExecuteSwapOperations {
+        operations: Vec<SwapOperation>,
+        min_output: Uint128,
+        recipient: Option<String>,
+        expiration: Option<Expiration>,
+    },
+    /// Entry point for cw20 `Send`, the embedded msg must be a `Cw20HookMsg`
+    Receive(Cw20ReceiveMsg),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    /// Swaps the received cw20 tokens through all operations in order
+    ExecuteSwapOperations {
+        operations: Vec<SwapOperation>,
+        min_output: Uint128,
+        recipient: Option<String>,
+        expiration: Option<Expiration>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Returns the output of a route at current pool prices
+    SimulateSwapOperations {
+        operations: Vec<SwapOperation>,
+        input_amount: Uint128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SimulateSwapOperationsResponse {
+    pub output_amount: Uint128,
+}
@@ -0,0 +1,25 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Addr, Uint128};
+use cw20::{Denom, Expiration};
+use cw_storage_plus::Item;
+
+use crate::msg::SwapOperation;
+
+/// Route that is being executed, it only lives for the duration of one transaction
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Route {
+    /// Operations that have not been dispatched yet
+    pub operations: Vec<SwapOperation>,
+    /// Index of the operation that is currently executed
+    pub index: u64,
+    pub recipient: Addr,
+    pub min_output: Uint128,
+    pub expiration: Option<Expiration>,
+    /// Output token of the current operation and the router balance of it before the swap
+    pub output_denom: Denom,
+    pub output_balance_before: Uint128,
+}
+
+pub const ROUTE: Item<Route> = Item::new("route");
@@ -5,10 +5,16 @@ use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use cw20::BalanceResponse;
 use wasmswap::msg::{
-    ExecuteMsg, InfoResponse, InstantiateMsg, MigrateMsg, QueryMsg, Token1ForToken2PriceResponse,
-    Token2ForToken1PriceResponse,
+    AmpResponse, AssetPricesResponse, AssetsResponse, CurrentFeeResponse, Cw20HookMsg,
+    DynamicFeeResponse, ExecuteMsg, FeeOnTransferResponse, FlashSwapReceiverMsg, GuardianResponse,
+    InfoResponse, InstantiateMsg, MigrateMsg, OrdersResponse, OwnershipProposalResponse,
+    PendingConfigResponse, PendingRewardsResponse, QueryMsg, ReserveDriftResponse,
+    ReverseSimulateSwapResponse, RewardSchedulesResponse, RolesResponse, SimulateSwapResponse,
+    StakerResponse, StakingConfigResponse, Token1ForExactToken2PriceResponse,
+    Token1ForToken2PriceResponse, Token2ForExactToken1PriceResponse, Token2ForToken1PriceResponse,
+    TwapResponse, WeightsResponse, ZapInLiquidityResponse, ZapOutOutputResponse,
 };
-use wasmswap::state::Token;
+use wasmswap::state::{LimitOrder, PauseFlags, Token};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -18,11 +24,39 @@ fn main() {
 
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(Cw20HookMsg), &out_dir);
+    export_schema(&schema_for!(FlashSwapReceiverMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(Token), &out_dir);
     export_schema(&schema_for!(BalanceResponse), &out_dir);
     export_schema(&schema_for!(InfoResponse), &out_dir);
     export_schema(&schema_for!(Token1ForToken2PriceResponse), &out_dir);
     export_schema(&schema_for!(Token2ForToken1PriceResponse), &out_dir);
+    export_schema(&schema_for!(Token1ForExactToken2PriceResponse), &out_dir);
+    export_schema(&schema_for!(Token2ForExactToken1PriceResponse), &out_dir);
     export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(TwapResponse), &out_dir);
+    export_schema(&schema_for!(AmpResponse), &out_dir);
+    export_schema(&schema_for!(WeightsResponse), &out_dir);
+    export_schema(&schema_for!(SimulateSwapResponse), &out_dir);
+    export_schema(&schema_for!(ReverseSimulateSwapResponse), &out_dir);
+    export_schema(&schema_for!(ZapInLiquidityResponse), &out_dir);
+    export_schema(&schema_for!(ZapOutOutputResponse), &out_dir);
+    export_schema(&schema_for!(OwnershipProposalResponse), &out_dir);
+    export_schema(&schema_for!(PendingConfigResponse), &out_dir);
+    export_schema(&schema_for!(CurrentFeeResponse), &out_dir);
+    export_schema(&schema_for!(DynamicFeeResponse), &out_dir);
+    export_schema(&schema_for!(StakingConfigResponse), &out_dir);
+    export_schema(&schema_for!(StakerResponse), &out_dir);
+    export_schema(&schema_for!(PendingRewardsResponse), &out_dir);
+    export_schema(&schema_for!(RewardSchedulesResponse), &out_dir);
+    export_schema(&schema_for!(LimitOrder), &out_dir);
+    export_schema(&schema_for!(OrdersResponse), &out_dir);
+    export_schema(&schema_for!(ReserveDriftResponse), &out_dir);
+    export_schema(&schema_for!(FeeOnTransferResponse), &out_dir);
+    export_schema(&schema_for!(AssetsResponse), &out_dir);
+    export_schema(&schema_for!(AssetPricesResponse), &out_dir);
+    export_schema(&schema_for!(PauseFlags), &out_dir);
+    export_schema(&schema_for!(GuardianResponse), &out_dir);
+    export_schema(&schema_for!(RolesResponse), &out_dir);
 }
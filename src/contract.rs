@@ -1,33 +1,90 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, entry_point, to_binary, Addr, Binary, BlockInfo, Coin, CosmosMsg, Decimal, Deps, DepsMut,
-    Env, MessageInfo, Reply, Response, StdError, StdResult, SubMsg, Uint128, Uint256, Uint512,
-    WasmMsg,
+    attr, from_binary, to_binary, Addr, Binary, BlockInfo, Coin, CosmosMsg, Decimal, Decimal256,
+    Deps, DepsMut, Empty, Env, MessageInfo, Order, Reply, Response, StdError, StdResult, Storage,
+    SubMsg, Uint128, Uint256, Uint512, WasmMsg,
 };
 use cw0::parse_reply_instantiate_data;
 use cw2::set_contract_version;
 use cw20::Denom::Cw20;
-use cw20::{Cw20ExecuteMsg, Denom, Expiration, MinterResponse};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom, Expiration, MinterResponse};
 use cw20_base::contract::query_balance;
+use cw_storage_plus::Bound;
 use std::convert::TryInto;
 use std::str::FromStr;
 
+use crate::dynamic_fee::{accumulate_volatility, decayed_volatility, get_dynamic_fee};
 use crate::error::ContractError;
 use crate::msg::{
-    ExecuteMsg, FeeResponse, InfoResponse, InstantiateMsg, MigrateMsg, QueryMsg,
-    Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, TokenSelect,
+    AmpResponse, AssetAmount, AssetPricesResponse, AssetsResponse, CurrentFeeResponse, Cw20HookMsg,
+    DynamicFeeResponse, ExecuteMsg, FeeOnTransferResponse, FeeResponse, FlashSwapCallbackMsg,
+    GuardianResponse, InfoResponse, InstantiateMsg, MigrateMsg, OrdersResponse,
+    OwnershipProposalResponse, PendingConfigResponse, PendingRewardsResponse, QueryMsg,
+    ReserveDriftResponse, ReverseSimulateSwapResponse, RewardAmount, RewardSchedulesResponse,
+    RolesResponse, SimulateSwapResponse, StakerResponse, StakingConfigResponse,
+    Token1ForExactToken2PriceResponse, Token1ForToken2PriceResponse,
+    Token2ForExactToken1PriceResponse, Token2ForToken1PriceResponse, TokenDrift, TokenSelect,
+    TwapResponse, WeightsResponse, ZapInLiquidityResponse, ZapOutOutputResponse,
 };
-use crate::state::{Fees, Token, FEES, FROZEN, LP_TOKEN, OWNER, TOKEN1, TOKEN2};
+use crate::multi_asset::{geometric_mean, get_deposit_liquidity, get_withdraw_liquidity};
+use crate::stableswap::{compute_d, compute_price_reserves, compute_y};
+use crate::staking::{accrue_rewards, accrue_staker_rewards, fund_rewards};
+use crate::state::{
+    denom_key, order_side_key, AmpConfig, DynamicFeeConfig, Fees, FlashSwap, GuardianConfig,
+    GuardianPause, LimitOrder, OwnershipProposal, PauseFlags, PendingConfig, PendingDeposit,
+    PoolOperation, PoolType, PriceCumulative, PriceObservation, PriceObservationIndex,
+    RewardSchedule, Role, StakerReward, StakingConfig, Token, Unbonding, Volatility, Weights, AMP,
+    ASSETS, BONDED, CONFIG_TIMELOCK, DYNAMIC_FEE, ESCROWED_BALANCES, FEES, GUARDIAN,
+    GUARDIAN_PAUSE, K_LAST, LP_TOKEN, ORDERS, ORDER_COUNT, OWNER, OWNERSHIP_PROPOSAL, OWNER_ORDERS,
+    PAUSE_FLAGS, PENDING_CONFIG, PENDING_DEPOSIT, PENDING_FLASH_SWAP, POOL_TYPE,
+    PRICE_OBSERVATIONS, PRICE_OBSERVATION_INDEX, PRICE_ORDERS, REWARD_SCHEDULES, ROLES,
+    STAKER_REWARDS, STAKING_CONFIG, TOKEN1, TOKEN2, TOTAL_BONDED, UNBONDINGS, VOLATILITY, WEIGHTS,
+};
+use crate::weighted::{compute_invariant, pow};
 
 // Version info for migration info
 pub const CONTRACT_NAME: &str = "crates.io:wasmswap";
 pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const INSTANTIATE_LP_TOKEN_REPLY_ID: u64 = 0;
+const FLASH_SWAP_REPLY_ID: u64 = 1;
+const FEE_ON_TRANSFER_DEPOSIT_REPLY_ID: u64 = 2;
+
+// Liquidity locked in the pool by the first deposit
+const MINIMUM_LIQUIDITY: Uint128 = Uint128::new(1_000);
 
 const FEE_SCALE_FACTOR: Uint128 = Uint128::new(10_000);
 const MAX_FEE_PERCENT: &str = "1";
 const FEE_DECIMAL_PRECISION: Uint128 = Uint128::new(10u128.pow(20));
 
+const MAX_AMP: u64 = 1_000_000;
+const MAX_AMP_CHANGE: u64 = 10;
+const MIN_RAMP_TIME: u64 = 86_400;
+
+const MIN_WEIGHT_PERCENT: u64 = 2;
+// Weighted pool swaps may add at most half of the input reserve
+const MAX_WEIGHTED_IN_RATIO_PERCENT: u64 = 50;
+
+// Config updates can be queued for at most 30 days
+const MAX_CONFIG_TIMELOCK: u64 = 30 * 86_400;
+// Guardian pauses last at most 7 days unless the owner extends them
+const MAX_GUARDIAN_PAUSE_DURATION: u64 = 7 * 86_400;
+
+// The product of at most four reserves fits in 512 bits
+const MAX_ASSETS: u32 = 4;
+
+// Every reward schedule is updated on each staking action, this bounds the gas used
+const MAX_REWARD_DENOMS: u32 = 10;
+
+// Price observations kept for TWAPs, at most one is recorded per block
+const PRICE_OBSERVATION_CAPACITY: u32 = 1_000;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+// Percent of the output of a filled limit order paid to the executor
+const ORDER_EXECUTOR_TIP_PERCENT: &str = "0.1";
+
 // Note, you can use StdResult in some functions where you do not
 // make use of the custom errors
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -39,17 +96,49 @@ pub fn instantiate(
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    let token1 = Token {
-        reserve: Uint128::zero(),
-        denom: msg.token1_denom.clone(),
+    // Two assets hold the same invariant as a constant product pool, which keeps the two token
+    // messages working
+    let pool_type = match msg.pool_type {
+        PoolType::MultiAsset if msg.extra_denoms.is_empty() => PoolType::ConstantProduct,
+        pool_type => pool_type,
     };
-    TOKEN1.save(deps.storage, &token1)?;
+    if pool_type != PoolType::MultiAsset && !msg.extra_denoms.is_empty() {
+        return Err(ContractError::ExtraDenomsUnsupported {});
+    }
 
-    let token2 = Token {
-        denom: msg.token2_denom.clone(),
-        reserve: Uint128::zero(),
-    };
-    TOKEN2.save(deps.storage, &token2)?;
+    if pool_type == PoolType::MultiAsset {
+        let mut denoms = vec![msg.token1_denom.clone(), msg.token2_denom.clone()];
+        denoms.extend(msg.extra_denoms);
+        if denoms.len() > MAX_ASSETS as usize {
+            return Err(ContractError::TooManyAssets { max: MAX_ASSETS });
+        }
+        for denom in denoms {
+            let key = denom_key(&denom);
+            if ASSETS.has(deps.storage, &key) {
+                return Err(ContractError::DuplicateAsset { denom: key });
+            }
+            let asset = Token {
+                reserve: Uint128::zero(),
+                denom,
+                fee_on_transfer: false,
+            };
+            ASSETS.save(deps.storage, &key, &asset)?;
+        }
+    } else {
+        let token1 = Token {
+            reserve: Uint128::zero(),
+            denom: msg.token1_denom.clone(),
+            fee_on_transfer: false,
+        };
+        TOKEN1.save(deps.storage, &token1)?;
+
+        let token2 = Token {
+            denom: msg.token2_denom.clone(),
+            reserve: Uint128::zero(),
+            fee_on_transfer: false,
+        };
+        TOKEN2.save(deps.storage, &token2)?;
+    }
 
     let owner = msg.owner.map(|h| deps.api.addr_validate(&h)).transpose()?;
     OWNER.save(deps.storage, &owner)?;
@@ -68,11 +157,45 @@ pub fn instantiate(
         lp_fee_percent: msg.lp_fee_percent,
         protocol_fee_percent: msg.protocol_fee_percent,
         protocol_fee_recipient,
+        fee_switch: false,
     };
     FEES.save(deps.storage, &fees)?;
 
     // Depositing is not frozen by default
-    FROZEN.save(deps.storage, &false)?;
+    PAUSE_FLAGS.save(deps.storage, &PauseFlags::default())?;
+
+    POOL_TYPE.save(deps.storage, &pool_type)?;
+    if pool_type == PoolType::Stable {
+        let amp = msg.amp.ok_or(ContractError::MissingAmp {})?;
+        validate_amp(amp)?;
+        let now = env.block.time.seconds();
+        AMP.save(
+            deps.storage,
+            &AmpConfig {
+                initial_amp: amp,
+                initial_time: now,
+                future_amp: amp,
+                future_time: now,
+            },
+        )?;
+    }
+    if pool_type == PoolType::Weighted {
+        let weights = msg.weights.ok_or(ContractError::MissingWeights {})?;
+        validate_weights(&weights)?;
+        WEIGHTS.save(deps.storage, &weights)?;
+    }
+
+    // Price observations start at instantiation
+    save_price_observation(
+        deps.storage,
+        &PriceObservation {
+            time: env.block.time.seconds(),
+            cumulative: PriceCumulative {
+                token1_price_cumulative: Decimal256::zero(),
+                token2_price_cumulative: Decimal256::zero(),
+            },
+        },
+    )?;
 
     let instantiate_lp_token_msg = WasmMsg::Instantiate {
         code_id: msg.lp_token_code_id,
@@ -106,6 +229,18 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    // Reserves are stale until the flash swap reply settles them
+    if PENDING_FLASH_SWAP.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::FlashSwapInProgress {});
+    }
+    if PENDING_DEPOSIT.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::DepositInProgress {});
+    }
+    // Multi asset pools hold their assets in `ASSETS` and only take the asset messages
+    if is_two_token_msg(&msg) && POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset {
+        return Err(ContractError::TwoTokenPoolRequired {});
+    }
+
     match msg {
         ExecuteMsg::AddLiquidity {
             token1_amount,
@@ -113,9 +248,7 @@ pub fn execute(
             max_token2,
             expiration,
         } => {
-            if FROZEN.load(deps.storage)? {
-                return Err(ContractError::FrozenPool {});
-            }
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::AddLiquidity])?;
             execute_add_liquidity(
                 deps,
                 &info,
@@ -124,6 +257,7 @@ pub fn execute(
                 token1_amount,
                 max_token2,
                 expiration,
+                None,
             )
         }
         ExecuteMsg::RemoveLiquidity {
@@ -131,7 +265,10 @@ pub fn execute(
             min_token1,
             min_token2,
             expiration,
-        } => execute_remove_liquidity(deps, info, env, amount, min_token1, min_token2, expiration),
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::RemoveLiquidity])?;
+            execute_remove_liquidity(deps, info, env, amount, min_token1, min_token2, expiration)
+        }
         ExecuteMsg::Swap {
             input_token,
             input_amount,
@@ -139,9 +276,7 @@ pub fn execute(
             expiration,
             ..
         } => {
-            if FROZEN.load(deps.storage)? {
-                return Err(ContractError::FrozenPool {});
-            }
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
             execute_swap(
                 deps,
                 &info,
@@ -151,6 +286,66 @@ pub fn execute(
                 info.sender.to_string(),
                 min_output,
                 expiration,
+                false,
+            )
+        }
+        ExecuteMsg::SwapExactOut {
+            output_token,
+            output_amount,
+            max_input,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
+            execute_swap_exact_out(
+                deps,
+                info,
+                env,
+                output_token,
+                output_amount,
+                max_input,
+                expiration,
+            )
+        }
+        ExecuteMsg::ZapIn {
+            input_token,
+            amount,
+            min_liquidity,
+            expiration,
+        } => {
+            check_not_paused(
+                deps.storage,
+                &env.block,
+                &[PoolOperation::AddLiquidity, PoolOperation::Swap],
+            )?;
+            execute_zap_in(
+                deps,
+                info,
+                env,
+                input_token,
+                amount,
+                min_liquidity,
+                expiration,
+            )
+        }
+        ExecuteMsg::ZapOut {
+            amount,
+            output_token,
+            min_output,
+            expiration,
+        } => {
+            check_not_paused(
+                deps.storage,
+                &env.block,
+                &[PoolOperation::RemoveLiquidity, PoolOperation::Swap],
+            )?;
+            execute_zap_out(
+                deps,
+                info,
+                env,
+                amount,
+                output_token,
+                min_output,
+                expiration,
             )
         }
         ExecuteMsg::PassThroughSwap {
@@ -160,9 +355,11 @@ pub fn execute(
             output_min_token,
             expiration,
         } => {
-            if FROZEN.load(deps.storage)? {
-                return Err(ContractError::FrozenPool {});
-            }
+            check_not_paused(
+                deps.storage,
+                &env.block,
+                &[PoolOperation::Swap, PoolOperation::PassThroughSwap],
+            )?;
             execute_pass_through_swap(
                 deps,
                 info,
@@ -172,6 +369,7 @@ pub fn execute(
                 input_token_amount,
                 output_min_token,
                 expiration,
+                false,
             )
         }
         ExecuteMsg::SwapAndSendTo {
@@ -181,9 +379,7 @@ pub fn execute(
             min_token,
             expiration,
         } => {
-            if FROZEN.load(deps.storage)? {
-                return Err(ContractError::FrozenPool {});
-            }
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
             execute_swap(
                 deps,
                 &info,
@@ -193,22 +389,334 @@ pub fn execute(
                 recipient,
                 min_token,
                 expiration,
+                false,
             )
         }
         ExecuteMsg::UpdateConfig {
-            owner,
             protocol_fee_recipient,
             lp_fee_percent,
             protocol_fee_percent,
+            fee_switch,
+            config_timelock,
         } => execute_update_config(
             deps,
             info,
-            owner,
+            env,
             lp_fee_percent,
             protocol_fee_percent,
             protocol_fee_recipient,
+            fee_switch,
+            config_timelock,
         ),
+        ExecuteMsg::ApplyPendingConfig {} => execute_apply_pending_config(deps, env),
+        ExecuteMsg::CancelPendingConfig {} => execute_cancel_pending_config(deps, info),
+        ExecuteMsg::ProposeNewOwner { owner, expiry } => {
+            execute_propose_new_owner(deps, info, env, owner, expiry)
+        }
+        ExecuteMsg::AcceptOwnership {} => execute_accept_ownership(deps, info, env),
+        ExecuteMsg::CancelOwnershipProposal {} => execute_cancel_ownership_proposal(deps, info),
+        ExecuteMsg::RenounceOwnership {} => execute_renounce_ownership(deps, info),
+        ExecuteMsg::GrantRole { role, address } => execute_grant_role(deps, info, role, address),
+        ExecuteMsg::RevokeRole { role, address } => execute_revoke_role(deps, info, role, address),
+        ExecuteMsg::RenounceRole { role } => execute_renounce_role(deps, info, role),
+        ExecuteMsg::UpdateDynamicFee { dynamic_fee } => {
+            execute_update_dynamic_fee(deps, info, env, dynamic_fee)
+        }
         ExecuteMsg::FreezeDeposits { freeze } => execute_freeze_deposits(deps, info.sender, freeze),
+        ExecuteMsg::UpdatePauseFlags { pause_flags } => {
+            execute_update_pause_flags(deps, info.sender, pause_flags)
+        }
+        ExecuteMsg::UpdateGuardian {
+            guardian,
+            max_pause_duration,
+        } => execute_update_guardian(deps, info.sender, guardian, max_pause_duration),
+        ExecuteMsg::UpdateFeeOnTransfer {
+            token,
+            fee_on_transfer,
+        } => execute_update_fee_on_transfer(deps, info.sender, token, fee_on_transfer),
+        ExecuteMsg::GuardianPause { pause_flags } => {
+            execute_guardian_pause(deps, info.sender, env, pause_flags)
+        }
+        ExecuteMsg::ExtendGuardianPause { expires_at } => {
+            execute_extend_guardian_pause(deps, info.sender, env, expires_at)
+        }
+        ExecuteMsg::Receive(cw20_msg) => execute_receive(deps, env, info, cw20_msg),
+        ExecuteMsg::RampAmp {
+            future_amp,
+            future_time,
+        } => execute_ramp_amp(deps, info, env, future_amp, future_time),
+        ExecuteMsg::StopRampAmp {} => execute_stop_ramp_amp(deps, info, env),
+        ExecuteMsg::FlashSwap {
+            token1_amount,
+            token2_amount,
+            recipient,
+            msg,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
+            execute_flash_swap(
+                deps,
+                info,
+                env,
+                token1_amount,
+                token2_amount,
+                recipient,
+                msg,
+            )
+        }
+        ExecuteMsg::UpdateStakingConfig { unbonding_period } => {
+            execute_update_staking_config(deps, info, unbonding_period)
+        }
+        ExecuteMsg::FundRewards {
+            denom,
+            amount,
+            duration,
+        } => execute_fund_rewards(deps, info, env, denom, amount, duration),
+        ExecuteMsg::Unbond { amount } => execute_unbond(deps, info, env, amount),
+        ExecuteMsg::WithdrawUnbonded {} => execute_withdraw_unbonded(deps, info, env),
+        ExecuteMsg::ClaimRewards {} => execute_claim_rewards(deps, info, env),
+        ExecuteMsg::PlaceOrder {
+            input_token,
+            input_amount,
+            min_output,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
+            execute_place_order(
+                deps,
+                &info,
+                env,
+                input_token,
+                input_amount,
+                min_output,
+                expiration,
+                false,
+            )
+        }
+        ExecuteMsg::CancelOrder { order_id } => execute_cancel_order(deps, info, order_id),
+        ExecuteMsg::ExecuteOrders { limit } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
+            execute_fill_orders(deps, info, env, limit)
+        }
+        ExecuteMsg::Sync {} => execute_sync(deps, env),
+        ExecuteMsg::Skim { recipient } => execute_skim(deps, env, recipient),
+        ExecuteMsg::SwapAssets {
+            input_denom,
+            output_denom,
+            input_amount,
+            min_output,
+            recipient,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::Swap])?;
+            execute_swap_assets(
+                deps,
+                info,
+                env,
+                input_denom,
+                output_denom,
+                input_amount,
+                min_output,
+                recipient,
+                expiration,
+            )
+        }
+        ExecuteMsg::AddAssets {
+            amounts,
+            min_liquidity,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::AddLiquidity])?;
+            execute_add_assets(deps, info, env, amounts, min_liquidity, expiration)
+        }
+        ExecuteMsg::RemoveAssets {
+            amount,
+            min_amounts,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::RemoveLiquidity])?;
+            execute_remove_assets(deps, info, env, amount, min_amounts, expiration)
+        }
+        ExecuteMsg::RemoveAssetsImbalanced {
+            amounts,
+            max_liquidity,
+            expiration,
+        } => {
+            check_not_paused(deps.storage, &env.block, &[PoolOperation::RemoveLiquidity])?;
+            execute_remove_assets_imbalanced(deps, info, env, amounts, max_liquidity, expiration)
+        }
+    }
+}
+
+// Messages that load `TOKEN1` and `TOKEN2`
+fn is_two_token_msg(msg: &ExecuteMsg) -> bool {
+    matches!(
+        msg,
+        ExecuteMsg::AddLiquidity { .. }
+            | ExecuteMsg::RemoveLiquidity { .. }
+            | ExecuteMsg::Swap { .. }
+            | ExecuteMsg::SwapExactOut { .. }
+            | ExecuteMsg::ZapIn { .. }
+            | ExecuteMsg::ZapOut { .. }
+            | ExecuteMsg::PassThroughSwap { .. }
+            | ExecuteMsg::SwapAndSendTo { .. }
+            | ExecuteMsg::UpdateFeeOnTransfer { .. }
+            | ExecuteMsg::RampAmp { .. }
+            | ExecuteMsg::StopRampAmp {}
+            | ExecuteMsg::FlashSwap { .. }
+            | ExecuteMsg::PlaceOrder { .. }
+            | ExecuteMsg::CancelOrder { .. }
+            | ExecuteMsg::ExecuteOrders { .. }
+            | ExecuteMsg::Sync {}
+            | ExecuteMsg::Skim { .. }
+    )
+}
+
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    // LP tokens of this pool can only be bonded
+    if info.sender == LP_TOKEN.load(deps.storage)? {
+        return match from_binary(&cw20_msg.msg)? {
+            Cw20HookMsg::Bond {} => {
+                let staker = deps.api.addr_validate(&cw20_msg.sender)?;
+                execute_bond(deps, env, staker, cw20_msg.amount)
+            }
+            _ => Err(ContractError::InvalidCw20Token {
+                token: info.sender.into_string(),
+            }),
+        };
+    }
+
+    // Multi asset pools take cw20 assets through an allowance
+    if POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset {
+        return Err(ContractError::TwoTokenPoolRequired {});
+    }
+
+    // The message is sent by the cw20 contract, so it must be one of the pool tokens
+    let token_addr = info.sender.clone();
+    let received_denom = Denom::Cw20(token_addr.clone());
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let received_token = if token1.denom == received_denom {
+        check_no_fee_on_transfer(&token1)?;
+        TokenSelect::Token1
+    } else if token2.denom == received_denom {
+        check_no_fee_on_transfer(&token2)?;
+        TokenSelect::Token2
+    } else {
+        return Err(ContractError::InvalidCw20Token {
+            token: info.sender.into_string(),
+        });
+    };
+
+    let hook_msg: Cw20HookMsg = from_binary(&cw20_msg.msg)?;
+    let operations: &[PoolOperation] = match hook_msg {
+        Cw20HookMsg::AddLiquidity { .. } => &[PoolOperation::AddLiquidity],
+        Cw20HookMsg::PassThroughSwap { .. } => {
+            &[PoolOperation::Swap, PoolOperation::PassThroughSwap]
+        }
+        Cw20HookMsg::Bond {} => &[],
+        _ => &[PoolOperation::Swap],
+    };
+    check_not_paused(deps.storage, &env.block, operations)?;
+
+    // Act on behalf of the account that sent the cw20 tokens
+    let info = MessageInfo {
+        sender: deps.api.addr_validate(&cw20_msg.sender)?,
+        funds: vec![],
+    };
+
+    match hook_msg {
+        Cw20HookMsg::Swap {
+            min_output,
+            expiration,
+        } => execute_swap(
+            deps,
+            &info,
+            cw20_msg.amount,
+            env,
+            received_token,
+            info.sender.to_string(),
+            min_output,
+            expiration,
+            true,
+        ),
+        Cw20HookMsg::SwapAndSendTo {
+            recipient,
+            min_token,
+            expiration,
+        } => execute_swap(
+            deps,
+            &info,
+            cw20_msg.amount,
+            env,
+            received_token,
+            recipient,
+            min_token,
+            expiration,
+            true,
+        ),
+        Cw20HookMsg::PassThroughSwap {
+            output_amm_address,
+            output_min_token,
+            expiration,
+        } => execute_pass_through_swap(
+            deps,
+            info,
+            env,
+            output_amm_address,
+            received_token,
+            cw20_msg.amount,
+            output_min_token,
+            expiration,
+            true,
+        ),
+        Cw20HookMsg::AddLiquidity {
+            token1_amount,
+            min_liquidity,
+            max_token2,
+            expiration,
+        } => {
+            let expected = match received_token {
+                TokenSelect::Token1 => token1_amount,
+                TokenSelect::Token2 => max_token2,
+            };
+            if cw20_msg.amount != expected {
+                return Err(ContractError::InvalidCw20Amount {
+                    received: cw20_msg.amount,
+                    expected,
+                });
+            }
+            execute_add_liquidity(
+                deps,
+                &info,
+                env,
+                min_liquidity,
+                token1_amount,
+                max_token2,
+                expiration,
+                Some(received_token),
+            )
+        }
+        Cw20HookMsg::PlaceOrder {
+            min_output,
+            expiration,
+        } => execute_place_order(
+            deps,
+            &info,
+            env,
+            received_token,
+            cw20_msg.amount,
+            min_output,
+            expiration,
+            true,
+        ),
+        Cw20HookMsg::Bond {} => Err(ContractError::InvalidBondToken {
+            token: token_addr.into_string(),
+        }),
     }
 }
 
@@ -217,58 +725,422 @@ fn execute_freeze_deposits(
     sender: Addr,
     freeze: bool,
 ) -> Result<Response, ContractError> {
-    if let Some(owner) = OWNER.load(deps.storage)? {
-        if sender != owner {
-            return Err(ContractError::UnauthorizedPoolFreeze {});
-        }
-    } else {
+    if !has_role(deps.storage, &Role::Pauser, &sender)? {
         return Err(ContractError::UnauthorizedPoolFreeze {});
     }
 
-    FROZEN.save(deps.storage, &freeze)?;
+    let mut pause_flags = PAUSE_FLAGS.load(deps.storage)?;
+    pause_flags.freeze(freeze);
+    PAUSE_FLAGS.save(deps.storage, &pause_flags)?;
     Ok(Response::new().add_attribute("action", "freezing-contracts"))
 }
 
-fn check_expiration(
-    expiration: &Option<Expiration>,
-    block: &BlockInfo,
-) -> Result<(), ContractError> {
-    match expiration {
-        Some(e) => {
-            if e.is_expired(block) {
-                return Err(ContractError::MsgExpirationError {});
-            }
-            Ok(())
-        }
-        None => Ok(()),
+fn execute_update_pause_flags(
+    deps: DepsMut,
+    sender: Addr,
+    pause_flags: PauseFlags,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::Pauser, &sender)? {
+        return Err(ContractError::UnauthorizedPoolFreeze {});
     }
+
+    // The owner flags replace whatever the guardian paused
+    PAUSE_FLAGS.save(deps.storage, &pause_flags)?;
+    GUARDIAN_PAUSE.remove(deps.storage);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_pause_flags"),
+        attr("add_liquidity", pause_flags.add_liquidity.to_string()),
+        attr("remove_liquidity", pause_flags.remove_liquidity.to_string()),
+        attr("swap", pause_flags.swap.to_string()),
+        attr(
+            "pass_through_swap",
+            pause_flags.pass_through_swap.to_string(),
+        ),
+    ]))
 }
 
-fn get_lp_token_amount_to_mint(
-    token1_amount: Uint128,
-    liquidity_supply: Uint128,
-    token1_reserve: Uint128,
-) -> Result<Uint128, ContractError> {
-    if liquidity_supply == Uint128::zero() {
-        Ok(token1_amount)
-    } else {
-        Ok(token1_amount
-            .checked_mul(liquidity_supply)
-            .map_err(StdError::overflow)?
-            .checked_div(token1_reserve)
-            .map_err(StdError::divide_by_zero)?)
+fn execute_update_guardian(
+    deps: DepsMut,
+    sender: Addr,
+    guardian: Option<String>,
+    max_pause_duration: u64,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::ConfigAdmin, &sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if max_pause_duration > MAX_GUARDIAN_PAUSE_DURATION {
+        return Err(ContractError::GuardianPauseTooLong {
+            max: MAX_GUARDIAN_PAUSE_DURATION,
+        });
+    }
+
+    match guardian {
+        Some(guardian) => {
+            let guardian = deps.api.addr_validate(&guardian)?;
+            GUARDIAN.save(
+                deps.storage,
+                &GuardianConfig {
+                    guardian: guardian.clone(),
+                    max_pause_duration,
+                },
+            )?;
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "update_guardian"),
+                attr("guardian", guardian),
+                attr("max_pause_duration", max_pause_duration.to_string()),
+            ]))
+        }
+        None => {
+            GUARDIAN.remove(deps.storage);
+            Ok(Response::new().add_attribute("action", "remove_guardian"))
+        }
     }
 }
 
-fn get_token2_amount_required(
-    max_token: Uint128,
-    token1_amount: Uint128,
-    liquidity_supply: Uint128,
-    token2_reserve: Uint128,
-    token1_reserve: Uint128,
-) -> Result<Uint128, StdError> {
-    if liquidity_supply == Uint128::zero() {
-        Ok(max_token)
+fn execute_update_fee_on_transfer(
+    deps: DepsMut,
+    sender: Addr,
+    token: TokenSelect,
+    fee_on_transfer: bool,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::ConfigAdmin, &sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    let token_item = match token {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    };
+    let mut pool_token = token_item.load(deps.storage)?;
+    if fee_on_transfer && matches!(pool_token.denom, Denom::Native(_)) {
+        return Err(ContractError::NativeFeeOnTransfer {});
+    }
+    pool_token.fee_on_transfer = fee_on_transfer;
+    token_item.save(deps.storage, &pool_token)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_fee_on_transfer"),
+        attr("fee_on_transfer", fee_on_transfer.to_string()),
+    ]))
+}
+
+/// Fee on transfer tokens can only be deposited where the pool measures what arrived
+fn check_no_fee_on_transfer(token: &Token) -> Result<(), ContractError> {
+    if token.fee_on_transfer {
+        return Err(ContractError::FeeOnTransferUnsupported {});
+    }
+    Ok(())
+}
+
+fn execute_guardian_pause(
+    deps: DepsMut,
+    sender: Addr,
+    env: Env,
+    pause_flags: PauseFlags,
+) -> Result<Response, ContractError> {
+    let guardian = GUARDIAN
+        .may_load(deps.storage)?
+        .ok_or(ContractError::Unauthorized {})?;
+    if sender != guardian.guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // Pausing more operations does not extend an active pause, only the owner can. An expired
+    // pause is kept until the pauser resets it, so the guardian can not chain pauses.
+    let now = env.block.time.seconds();
+    let pause = match GUARDIAN_PAUSE.may_load(deps.storage)? {
+        Some(pause) if pause.is_active(now) => GuardianPause {
+            pause_flags: pause.pause_flags.union(&pause_flags),
+            expires_at: pause.expires_at,
+        },
+        Some(_) => return Err(ContractError::GuardianPauseUsed {}),
+        None => GuardianPause {
+            pause_flags,
+            expires_at: now + guardian.max_pause_duration,
+        },
+    };
+    GUARDIAN_PAUSE.save(deps.storage, &pause)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "guardian_pause"),
+        attr("add_liquidity", pause.pause_flags.add_liquidity.to_string()),
+        attr(
+            "remove_liquidity",
+            pause.pause_flags.remove_liquidity.to_string(),
+        ),
+        attr("swap", pause.pause_flags.swap.to_string()),
+        attr(
+            "pass_through_swap",
+            pause.pause_flags.pass_through_swap.to_string(),
+        ),
+        attr("expires_at", pause.expires_at.to_string()),
+    ]))
+}
+
+fn execute_extend_guardian_pause(
+    deps: DepsMut,
+    sender: Addr,
+    env: Env,
+    expires_at: u64,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::Pauser, &sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let now = env.block.time.seconds();
+    let mut pause = GUARDIAN_PAUSE
+        .may_load(deps.storage)?
+        .filter(|pause| pause.is_active(now))
+        .ok_or(ContractError::NoGuardianPause {})?;
+    if expires_at <= now {
+        return Err(ContractError::InvalidGuardianPauseExpiry {});
+    }
+    pause.expires_at = expires_at;
+    GUARDIAN_PAUSE.save(deps.storage, &pause)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "extend_guardian_pause"),
+        attr("expires_at", expires_at.to_string()),
+    ]))
+}
+
+/// Operations paused by the owner or by a guardian pause that has not expired
+fn load_pause_flags(storage: &dyn Storage, block: &BlockInfo) -> StdResult<PauseFlags> {
+    let pause_flags = PAUSE_FLAGS.load(storage)?;
+    match GUARDIAN_PAUSE.may_load(storage)? {
+        Some(pause) if pause.is_active(block.time.seconds()) => {
+            Ok(pause_flags.union(&pause.pause_flags))
+        }
+        _ => Ok(pause_flags),
+    }
+}
+
+/// Fails with the first of the given operations that is paused
+fn check_not_paused(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    operations: &[PoolOperation],
+) -> Result<(), ContractError> {
+    let pause_flags = load_pause_flags(storage, block)?;
+    match operations
+        .iter()
+        .find(|operation| pause_flags.is_paused(operation))
+    {
+        Some(operation) => Err(ContractError::OperationPaused {
+            operation: operation.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn check_expiration(
+    expiration: &Option<Expiration>,
+    block: &BlockInfo,
+) -> Result<(), ContractError> {
+    match expiration {
+        Some(e) => {
+            if e.is_expired(block) {
+                return Err(ContractError::MsgExpirationError {});
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Adds the prices of the given reserves, weighted by the elapsed seconds, to the cumulative prices
+fn accumulate_prices(
+    cumulative: PriceCumulative,
+    token1_reserve: Uint256,
+    token2_reserve: Uint256,
+    elapsed: u64,
+) -> PriceCumulative {
+    // Prices are undefined without liquidity
+    if token1_reserve.is_zero() || token2_reserve.is_zero() {
+        return cumulative;
+    }
+
+    let elapsed = Uint256::from(elapsed);
+    PriceCumulative {
+        token1_price_cumulative: cumulative.token1_price_cumulative
+            + Decimal256::from_ratio(token2_reserve * elapsed, token1_reserve),
+        token2_price_cumulative: cumulative.token2_price_cumulative
+            + Decimal256::from_ratio(token1_reserve * elapsed, token2_reserve),
+    }
+}
+
+/// Reserves whose ratio is the spot price of the pool
+fn get_price_reserves(storage: &dyn Storage, now: u64) -> StdResult<(Uint256, Uint256)> {
+    let token1 = TOKEN1.load(storage)?;
+    let token2 = TOKEN2.load(storage)?;
+    scale_price_reserves(storage, now, token1.reserve, token2.reserve)
+}
+
+/// Scales the given reserves so their ratio is the spot price. A weighted pool prices token1 at
+/// (token2_reserve / token2_weight) / (token1_reserve / token1_weight), a stable pool at the
+/// slope of the StableSwap curve for the amplification at `now`.
+fn scale_price_reserves(
+    storage: &dyn Storage,
+    now: u64,
+    token1_reserve: Uint128,
+    token2_reserve: Uint128,
+) -> StdResult<(Uint256, Uint256)> {
+    match POOL_TYPE.load(storage)? {
+        PoolType::Weighted => {
+            let weights = WEIGHTS.load(storage)?;
+            Ok((
+                token1_reserve.full_mul(weights.token2_weight),
+                token2_reserve.full_mul(weights.token1_weight),
+            ))
+        }
+        PoolType::Stable => {
+            let amp = AMP.load(storage)?.current_amp(now);
+            let (token1_reserve, token2_reserve) =
+                compute_price_reserves(amp, token1_reserve, token2_reserve)?;
+            Ok((token1_reserve.into(), token2_reserve.into()))
+        }
+        _ => Ok((token1_reserve.into(), token2_reserve.into())),
+    }
+}
+
+/// Spot price of `input_token` denominated in the other token for the given reserves
+fn get_spot_price(
+    storage: &dyn Storage,
+    now: u64,
+    input_token: &TokenSelect,
+    token1_reserve: Uint128,
+    token2_reserve: Uint128,
+) -> StdResult<Decimal256> {
+    if token1_reserve.is_zero() || token2_reserve.is_zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    }
+    let (token1_reserve, token2_reserve) =
+        scale_price_reserves(storage, now, token1_reserve, token2_reserve)?;
+    Ok(match input_token {
+        TokenSelect::Token1 => Decimal256::from_ratio(token2_reserve, token1_reserve),
+        TokenSelect::Token2 => Decimal256::from_ratio(token1_reserve, token2_reserve),
+    })
+}
+
+fn get_last_price_observation(storage: &dyn Storage) -> StdResult<Option<PriceObservation>> {
+    PRICE_OBSERVATION_INDEX
+        .may_load(storage)?
+        .map(|index| PRICE_OBSERVATIONS.load(storage, index.latest))
+        .transpose()
+}
+
+/// Writes the observation to the slot after the latest one, overwriting the oldest observation
+/// once the ring buffer is full
+fn save_price_observation(
+    storage: &mut dyn Storage,
+    observation: &PriceObservation,
+) -> StdResult<()> {
+    let index = match PRICE_OBSERVATION_INDEX.may_load(storage)? {
+        Some(index) => PriceObservationIndex {
+            latest: (index.latest + 1) % PRICE_OBSERVATION_CAPACITY,
+            len: (index.len + 1).min(PRICE_OBSERVATION_CAPACITY),
+        },
+        None => PriceObservationIndex { latest: 0, len: 1 },
+    };
+    PRICE_OBSERVATION_INDEX.save(storage, &index)?;
+    PRICE_OBSERVATIONS.save(storage, index.latest, observation)
+}
+
+/// Observation at `position` counted from the oldest one kept
+fn load_price_observation(
+    storage: &dyn Storage,
+    index: &PriceObservationIndex,
+    position: u32,
+) -> StdResult<PriceObservation> {
+    let oldest =
+        (index.latest + 1 + PRICE_OBSERVATION_CAPACITY - index.len) % PRICE_OBSERVATION_CAPACITY;
+    PRICE_OBSERVATIONS.load(storage, (oldest + position) % PRICE_OBSERVATION_CAPACITY)
+}
+
+/// Records the cumulative prices before the first reserve change of a block, so the prices
+/// reflect the reserves at the end of previous blocks and can not be moved within a block.
+fn update_price_accumulators(storage: &mut dyn Storage, block: &BlockInfo) -> StdResult<()> {
+    let now = block.time.seconds();
+    let cumulative = match get_last_price_observation(storage)? {
+        Some(last) if last.time >= now => return Ok(()),
+        Some(last) => {
+            let (token1_reserve, token2_reserve) = get_price_reserves(storage, now)?;
+            accumulate_prices(
+                last.cumulative,
+                token1_reserve,
+                token2_reserve,
+                now - last.time,
+            )
+        }
+        // Pools migrated from a version without price observations start now
+        None => PriceCumulative {
+            token1_price_cumulative: Decimal256::zero(),
+            token2_price_cumulative: Decimal256::zero(),
+        },
+    };
+    save_price_observation(
+        storage,
+        &PriceObservation {
+            time: now,
+            cumulative,
+        },
+    )
+}
+
+/// Token weights of a weighted pool, `None` for pools that weigh both tokens equally
+fn load_weights(storage: &dyn Storage) -> StdResult<Option<Weights>> {
+    match POOL_TYPE.load(storage)? {
+        PoolType::Weighted => WEIGHTS.load(storage).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Liquidity minted to the depositor. The first deposit mints the weighted invariant
+/// token1^(w1 / W) * token2^(w2 / W), sqrt(token1 * token2) for equal weights, of which
+/// `MINIMUM_LIQUIDITY` is locked in the pool so the share price can not be inflated. Later
+/// deposits are proportional to the reserves, which keeps the price of every pool type unchanged.
+fn get_lp_token_amount_to_mint(
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    liquidity_supply: Uint128,
+    token1_reserve: Uint128,
+    weights: Option<&Weights>,
+) -> Result<Uint128, ContractError> {
+    if liquidity_supply == Uint128::zero() {
+        let liquidity: Uint128 = match weights {
+            Some(weights) => compute_invariant(
+                token1_amount,
+                token2_amount,
+                weights.token1_weight,
+                weights.token2_weight,
+            )?,
+            None => isqrt(token1_amount.full_mul(token2_amount))
+                .try_into()
+                .map_err(StdError::from)?,
+        };
+        if liquidity <= MINIMUM_LIQUIDITY {
+            return Err(ContractError::InsufficientInitialLiquidity {
+                minimum: MINIMUM_LIQUIDITY,
+            });
+        }
+        Ok(liquidity - MINIMUM_LIQUIDITY)
+    } else {
+        Ok(token1_amount
+            .checked_mul(liquidity_supply)
+            .map_err(StdError::overflow)?
+            .checked_div(token1_reserve)
+            .map_err(StdError::divide_by_zero)?)
+    }
+}
+
+fn get_token2_amount_required(
+    max_token: Uint128,
+    token1_amount: Uint128,
+    liquidity_supply: Uint128,
+    token2_reserve: Uint128,
+    token1_reserve: Uint128,
+) -> Result<Uint128, StdError> {
+    if liquidity_supply == Uint128::zero() {
+        Ok(max_token)
     } else {
         Ok(token1_amount
             .checked_mul(token2_reserve)
@@ -280,6 +1152,7 @@ fn get_token2_amount_required(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_add_liquidity(
     deps: DepsMut,
     info: &MessageInfo,
@@ -288,8 +1161,10 @@ pub fn execute_add_liquidity(
     token1_amount: Uint128,
     max_token2: Uint128,
     expiration: Option<Expiration>,
+    received_token: Option<TokenSelect>,
 ) -> Result<Response, ContractError> {
     check_expiration(&expiration, &env.block)?;
+    update_price_accumulators(deps.storage, &env.block)?;
 
     let token1 = TOKEN1.load(deps.storage)?;
     let token2 = TOKEN2.load(deps.storage)?;
@@ -300,9 +1175,9 @@ pub fn execute_add_liquidity(
     validate_input_amount(&info.funds, max_token2, &token2.denom)?;
 
     let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
-    let liquidity_amount =
-        get_lp_token_amount_to_mint(token1_amount, lp_token_supply, token1.reserve)?;
-
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
     let token2_amount = get_token2_amount_required(
         max_token2,
         token1_amount,
@@ -311,6 +1186,15 @@ pub fn execute_add_liquidity(
         token1.reserve,
     )?;
 
+    let weights = load_weights(deps.storage)?;
+    let liquidity_amount = get_lp_token_amount_to_mint(
+        token1_amount,
+        token2_amount,
+        lp_token_supply,
+        token1.reserve,
+        weights.as_ref(),
+    )?;
+
     if liquidity_amount < min_liquidity {
         return Err(ContractError::MinLiquidityError {
             min_liquidity,
@@ -325,75 +1209,475 @@ pub fn execute_add_liquidity(
         });
     }
 
-    // Generate cw20 transfer messages if necessary
-    let mut transfer_msgs: Vec<CosmosMsg> = vec![];
-    if let Cw20(addr) = token1.denom {
-        transfer_msgs.push(get_cw20_transfer_from_msg(
-            &info.sender,
-            &env.contract.address,
-            &addr,
+    // Fee on transfer tokens are never received through `Send`. Less than the requested amounts
+    // arrives, so the liquidity checked above is an upper bound of what is minted in the reply
+    // to their inbound transfer.
+    if token1.fee_on_transfer || token2.fee_on_transfer {
+        return execute_fee_on_transfer_add_liquidity(
+            deps,
+            info,
+            env,
+            &token1,
+            &token2,
             token1_amount,
-        )?)
-    }
-    if let Cw20(addr) = token2.denom.clone() {
-        transfer_msgs.push(get_cw20_transfer_from_msg(
-            &info.sender,
-            &env.contract.address,
-            &addr,
             token2_amount,
-        )?)
+            max_token2,
+            min_liquidity,
+            received_token,
+        );
     }
 
-    // Refund token 2 if is a native token and not all is spent
-    if let Denom::Native(denom) = token2.denom {
-        if token2_amount < max_token2 {
-            transfer_msgs.push(get_bank_transfer_to_msg(
+    let mut transfer_msgs = get_deposit_transfer_msgs(
+        info,
+        &env,
+        &token1,
+        &token2,
+        token1_amount,
+        token2_amount,
+        received_token.clone(),
+    )?;
+    transfer_msgs.extend(get_token2_refund_msg(
+        &info.sender,
+        &token2,
+        max_token2 - token2_amount,
+        received_token,
+    )?);
+    let mint_msgs = deposit_liquidity(
+        deps.storage,
+        &env,
+        &info.sender,
+        &lp_token_addr,
+        lp_token_supply,
+        token1_amount,
+        token2_amount,
+        liquidity_amount,
+    )?;
+    Ok(Response::new()
+        .add_messages(transfer_msgs)
+        .add_messages(protocol_fee_mint_msg)
+        .add_messages(mint_msgs)
+        .add_attributes(vec![
+            attr("token1_amount", token1_amount),
+            attr("token2_amount", token2_amount),
+            attr("liquidity_received", liquidity_amount),
+        ]))
+}
+
+/// Generates the cw20 transfers of a deposit, tokens received through a cw20 `Send` are already
+/// held by the contract
+fn get_deposit_transfer_msgs(
+    info: &MessageInfo,
+    env: &Env,
+    token1: &Token,
+    token2: &Token,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    received_token: Option<TokenSelect>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    if let Cw20(addr) = &token1.denom {
+        if received_token != Some(TokenSelect::Token1) {
+            msgs.push(get_cw20_transfer_from_msg(
                 &info.sender,
-                &denom,
-                max_token2 - token2_amount,
-            ))
+                &env.contract.address,
+                addr,
+                token1_amount,
+            )?)
+        }
+    }
+    if let Cw20(addr) = &token2.denom {
+        if received_token != Some(TokenSelect::Token2) {
+            msgs.push(get_cw20_transfer_from_msg(
+                &info.sender,
+                &env.contract.address,
+                addr,
+                token2_amount,
+            )?)
         }
     }
+    Ok(msgs)
+}
 
-    TOKEN1.update(deps.storage, |mut token1| -> Result<_, ContractError> {
+/// Refunds token 2 if it was sent up front and not all is spent
+fn get_token2_refund_msg(
+    sender: &Addr,
+    token2: &Token,
+    refund_amount: Uint128,
+    received_token: Option<TokenSelect>,
+) -> StdResult<Option<CosmosMsg>> {
+    if refund_amount.is_zero() {
+        return Ok(None);
+    }
+    Ok(match &token2.denom {
+        Denom::Native(denom) => Some(get_bank_transfer_to_msg(sender, denom, refund_amount)),
+        Denom::Cw20(addr) if received_token == Some(TokenSelect::Token2) => {
+            Some(get_cw20_transfer_to_msg(sender, addr, refund_amount)?)
+        }
+        Denom::Cw20(_) => None,
+    })
+}
+
+/// Adds a deposit to the reserves and mints its liquidity. The minimum liquidity is minted to the
+/// pool itself on the first deposit and can never be withdrawn.
+#[allow(clippy::too_many_arguments)]
+fn deposit_liquidity(
+    storage: &mut dyn Storage,
+    env: &Env,
+    recipient: &Addr,
+    lp_token_addr: &Addr,
+    lp_token_supply: Uint128,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    liquidity_amount: Uint128,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    TOKEN1.update(storage, |mut token1| -> Result<_, ContractError> {
         token1.reserve += token1_amount;
         Ok(token1)
     })?;
-    TOKEN2.update(deps.storage, |mut token2| -> Result<_, ContractError> {
+    TOKEN2.update(storage, |mut token2| -> Result<_, ContractError> {
         token2.reserve += token2_amount;
         Ok(token2)
     })?;
+    update_k_last(storage)?;
+
+    let mut msgs = vec![];
+    if lp_token_supply.is_zero() {
+        msgs.push(mint_lp_tokens(
+            &env.contract.address,
+            MINIMUM_LIQUIDITY,
+            lp_token_addr,
+        )?);
+    }
+    msgs.push(mint_lp_tokens(recipient, liquidity_amount, lp_token_addr)?);
+    Ok(msgs)
+}
+
+/// Pulls a deposit with fee on transfer tokens into the pool and records the balances of those
+/// tokens. The reply to the last inbound transfer mints the liquidity for what arrived.
+#[allow(clippy::too_many_arguments)]
+fn execute_fee_on_transfer_add_liquidity(
+    deps: DepsMut,
+    info: &MessageInfo,
+    env: Env,
+    token1: &Token,
+    token2: &Token,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    max_token2: Uint128,
+    min_liquidity: Uint128,
+    received_token: Option<TokenSelect>,
+) -> Result<Response, ContractError> {
+    let measured_balance = |token: &Token| -> StdResult<Option<Uint128>> {
+        if token.fee_on_transfer {
+            get_pool_balance(deps.as_ref(), &env, &token.denom).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    let pending_deposit = PendingDeposit::AddLiquidity {
+        sender: info.sender.clone(),
+        token1_amount,
+        token2_amount,
+        min_liquidity,
+        token1_balance: measured_balance(token1)?,
+        token2_balance: measured_balance(token2)?,
+    };
+    PENDING_DEPOSIT.save(deps.storage, &pending_deposit)?;
+
+    let mut transfer_msgs: Vec<SubMsg> = get_deposit_transfer_msgs(
+        info,
+        &env,
+        token1,
+        token2,
+        token1_amount,
+        token2_amount,
+        received_token.clone(),
+    )?
+    .into_iter()
+    .map(SubMsg::new)
+    .collect();
+    // A flagged token is always transferred, so the last transfer settles the deposit
+    if let Some(last) = transfer_msgs.last_mut() {
+        *last = SubMsg::reply_on_success(last.msg.clone(), FEE_ON_TRANSFER_DEPOSIT_REPLY_ID);
+    }
+    let refund_msg = get_token2_refund_msg(
+        &info.sender,
+        token2,
+        max_token2 - token2_amount,
+        received_token,
+    )?;
 
-    let mint_msg = mint_lp_tokens(&info.sender, liquidity_amount, &lp_token_addr)?;
     Ok(Response::new()
-        .add_messages(transfer_msgs)
-        .add_message(mint_msg)
+        .add_submessages(transfer_msgs)
+        .add_messages(refund_msg)
         .add_attributes(vec![
+            attr("action", "fee_on_transfer_add_liquidity"),
             attr("token1_amount", token1_amount),
             attr("token2_amount", token2_amount),
-            attr("liquidity_received", liquidity_amount),
         ]))
 }
 
-fn get_lp_token_supply(deps: Deps, lp_token_addr: &Addr) -> StdResult<Uint128> {
-    let resp: cw20::TokenInfoResponse = deps
-        .querier
-        .query_wasm_smart(lp_token_addr, &cw20_base::msg::QueryMsg::TokenInfo {})?;
-    Ok(resp.total_supply)
-}
-
-fn mint_lp_tokens(
-    recipient: &Addr,
-    liquidity_amount: Uint128,
-    lp_token_address: &Addr,
-) -> StdResult<CosmosMsg> {
-    let mint_msg = cw20_base::msg::ExecuteMsg::Mint {
-        recipient: recipient.into(),
-        amount: liquidity_amount,
+/// Mints the liquidity of a fee on transfer deposit for the amounts that arrived. Measured
+/// tokens may arrive in a different ratio than the reserves, so the smaller share is minted.
+#[allow(clippy::too_many_arguments)]
+fn settle_fee_on_transfer_add_liquidity(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    min_liquidity: Uint128,
+    token1_balance: Option<Uint128>,
+    token2_balance: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let received_amount = |token: &Token, amount: Uint128, balance: Option<Uint128>| match balance {
+        Some(balance) => get_pool_balance(deps.as_ref(), &env, &token.denom)?
+            .checked_sub(balance)
+            .map_err(StdError::overflow),
+        None => Ok(amount),
     };
-    Ok(WasmMsg::Execute {
-        contract_addr: lp_token_address.to_string(),
-        msg: to_binary(&mint_msg)?,
+    let token1_amount = received_amount(&token1, token1_amount, token1_balance)?;
+    let token2_amount = received_amount(&token2, token2_amount, token2_balance)?;
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+    let weights = load_weights(deps.storage)?;
+    let liquidity_amount = get_lp_token_amount_to_mint(
+        token1_amount,
+        token2_amount,
+        lp_token_supply,
+        token1.reserve,
+        weights.as_ref(),
+    )?;
+    let liquidity_amount = if lp_token_supply.is_zero() {
+        liquidity_amount
+    } else {
+        liquidity_amount.min(get_lp_token_amount_to_mint(
+            token2_amount,
+            token1_amount,
+            lp_token_supply,
+            token2.reserve,
+            None,
+        )?)
+    };
+
+    if liquidity_amount < min_liquidity {
+        return Err(ContractError::MinLiquidityError {
+            min_liquidity,
+            liquidity_available: liquidity_amount,
+        });
+    }
+
+    let mint_msgs = deposit_liquidity(
+        deps.storage,
+        &env,
+        &sender,
+        &lp_token_addr,
+        lp_token_supply,
+        token1_amount,
+        token2_amount,
+        liquidity_amount,
+    )?;
+    Ok(Response::new()
+        .add_messages(protocol_fee_mint_msg)
+        .add_messages(mint_msgs)
+        .add_attributes(vec![
+            attr("token1_amount", token1_amount),
+            attr("token2_amount", token2_amount),
+            attr("liquidity_received", liquidity_amount),
+        ]))
+}
+
+struct ZapIn {
+    swap_amount: Uint128,
+    protocol_fee_amount: Uint128,
+    liquidity_amount: Uint128,
+}
+
+/// Splits a single sided deposit into a swap and a deposit of the remaining input and the swap
+/// output. The swap amount is the largest one that leaves at least as much input as the post
+/// swap reserve ratio requires, found by bisection so it holds for every pool type.
+fn get_zap_in(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    input_token_enum: &TokenSelect,
+    amount: Uint128,
+    lp_token_supply: Uint128,
+) -> StdResult<ZapIn> {
+    let (input_token, output_token) = match input_token_enum {
+        TokenSelect::Token1 => (TOKEN1.load(storage)?, TOKEN2.load(storage)?),
+        TokenSelect::Token2 => (TOKEN2.load(storage)?, TOKEN1.load(storage)?),
+    };
+    if lp_token_supply.is_zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    }
+
+    let fees = FEES.load(storage)?;
+    let total_fee_percent = get_total_fee_percent(storage, block, &fees)?;
+    // Reserves after swapping `swap_amount` and the swap output
+    let swap = |swap_amount: Uint128| -> StdResult<(Uint128, Uint128, Uint128)> {
+        let output_amount = get_swap_output(
+            storage,
+            block,
+            input_token_enum,
+            swap_amount,
+            input_token.reserve,
+            output_token.reserve,
+            total_fee_percent,
+        )?;
+        let protocol_fee_amount =
+            get_protocol_fee_amount(swap_amount, fees.transferred_protocol_fee_percent())?;
+        let input_reserve = input_token.reserve + swap_amount - protocol_fee_amount;
+        let output_reserve = output_token.reserve - output_amount;
+        Ok((input_reserve, output_reserve, output_amount))
+    };
+
+    let mut low = Uint128::zero();
+    let mut high = amount;
+    while low < high {
+        let mid = low + (high - low + Uint128::new(1)) / Uint128::new(2);
+        let (input_reserve, output_reserve, output_amount) = swap(mid)?;
+        // (amount - mid) / input_reserve >= output_amount / output_reserve
+        if (amount - mid).full_mul(output_reserve) >= output_amount.full_mul(input_reserve) {
+            low = mid;
+        } else {
+            high = mid - Uint128::new(1);
+        }
+    }
+
+    let swap_amount = low;
+    let (input_reserve, output_reserve, output_amount) = swap(swap_amount)?;
+    let input_liquidity = (amount - swap_amount).multiply_ratio(lp_token_supply, input_reserve);
+    let output_liquidity = output_amount.multiply_ratio(lp_token_supply, output_reserve);
+    Ok(ZapIn {
+        swap_amount,
+        protocol_fee_amount: get_protocol_fee_amount(
+            swap_amount,
+            fees.transferred_protocol_fee_percent(),
+        )?,
+        liquidity_amount: input_liquidity.min(output_liquidity),
+    })
+}
+
+pub fn execute_zap_in(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    input_token_enum: TokenSelect,
+    amount: Uint128,
+    min_liquidity: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let input_token_item = match input_token_enum {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    };
+    let input_token = input_token_item.load(deps.storage)?;
+    check_no_fee_on_transfer(&input_token)?;
+    validate_input_amount(&info.funds, amount, &input_token.denom)?;
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+    let zap = get_zap_in(
+        deps.storage,
+        &env.block,
+        &input_token_enum,
+        amount,
+        lp_token_supply,
+    )?;
+    if zap.liquidity_amount < min_liquidity {
+        return Err(ContractError::MinLiquidityError {
+            min_liquidity,
+            liquidity_available: zap.liquidity_amount,
+        });
+    }
+
+    let k_before = TOKEN1
+        .load(deps.storage)?
+        .reserve
+        .full_mul(TOKEN2.load(deps.storage)?.reserve);
+    let amount_minus_protocol_fee = amount - zap.protocol_fee_amount;
+    let mut msgs: Vec<CosmosMsg> = protocol_fee_mint_msg;
+    if let Denom::Cw20(addr) = &input_token.denom {
+        msgs.push(get_cw20_transfer_from_msg(
+            &info.sender,
+            &env.contract.address,
+            addr,
+            amount_minus_protocol_fee,
+        )?)
+    }
+
+    // Send protocol fee of the swapped part to protocol fee recipient
+    let fees = FEES.load(deps.storage)?;
+    if !zap.protocol_fee_amount.is_zero() {
+        msgs.push(get_fee_transfer_msg(
+            &info.sender,
+            &fees.protocol_fee_recipient,
+            &input_token.denom,
+            zap.protocol_fee_amount,
+            false,
+        )?)
+    }
+
+    // The swap output is deposited right away, so only the input reserve changes
+    input_token_item.update(deps.storage, |mut token| -> Result<_, ContractError> {
+        token.reserve = token
+            .reserve
+            .checked_add(amount_minus_protocol_fee)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+    update_k_last_for_zap(
+        deps.storage,
+        k_before,
+        lp_token_supply,
+        lp_token_supply + zap.liquidity_amount,
+    )?;
+
+    msgs.push(mint_lp_tokens(
+        &info.sender,
+        zap.liquidity_amount,
+        &lp_token_addr,
+    )?);
+    update_volatility(deps.storage, &env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("input_amount", amount),
+        attr("swap_amount", zap.swap_amount),
+        attr("liquidity_received", zap.liquidity_amount),
+    ]))
+}
+
+fn get_lp_token_supply(deps: Deps, lp_token_addr: &Addr) -> StdResult<Uint128> {
+    let resp: cw20::TokenInfoResponse = deps
+        .querier
+        .query_wasm_smart(lp_token_addr, &cw20_base::msg::QueryMsg::TokenInfo {})?;
+    Ok(resp.total_supply)
+}
+
+fn mint_lp_tokens(
+    recipient: &Addr,
+    liquidity_amount: Uint128,
+    lp_token_address: &Addr,
+) -> StdResult<CosmosMsg> {
+    let mint_msg = cw20_base::msg::ExecuteMsg::Mint {
+        recipient: recipient.into(),
+        amount: liquidity_amount,
+    };
+    Ok(WasmMsg::Execute {
+        contract_addr: lp_token_address.to_string(),
+        msg: to_binary(&mint_msg)?,
         funds: vec![],
     }
     .into())
@@ -473,574 +1757,3575 @@ fn get_cw20_increase_allowance_msg(
     Ok(exec_allowance.into())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_update_config(
     deps: DepsMut,
     info: MessageInfo,
-    new_owner: Option<String>,
+    env: Env,
     lp_fee_percent: Decimal,
     protocol_fee_percent: Decimal,
     protocol_fee_recipient: String,
+    fee_switch: Option<bool>,
+    config_timelock: Option<u64>,
 ) -> Result<Response, ContractError> {
-    let owner = OWNER.load(deps.storage)?;
-    if Some(info.sender) != owner {
+    if !has_role(deps.storage, &Role::FeeManager, &info.sender)? {
         return Err(ContractError::Unauthorized {});
     }
 
-    let new_owner_addr = new_owner
-        .as_ref()
-        .map(|h| deps.api.addr_validate(h))
-        .transpose()?;
-    OWNER.save(deps.storage, &new_owner_addr)?;
-
-    let total_fee_percent = lp_fee_percent + protocol_fee_percent;
-    let max_fee_percent = Decimal::from_str(MAX_FEE_PERCENT)?;
-    if total_fee_percent > max_fee_percent {
-        return Err(ContractError::FeesTooHigh {
-            max_fee_percent,
-            total_fee_percent,
-        });
-    }
-
+    // Redirecting the protocol fees is a config admin decision, not part of fee management
+    let mut config = load_next_config(deps.storage, &env.block)?;
     let protocol_fee_recipient = deps.api.addr_validate(&protocol_fee_recipient)?;
-    let updated_fees = Fees {
-        protocol_fee_recipient: protocol_fee_recipient.clone(),
+    if protocol_fee_recipient != config.fees.protocol_fee_recipient
+        && !has_role(deps.storage, &Role::ConfigAdmin, &info.sender)?
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+    config.fees = Fees {
+        protocol_fee_recipient,
         lp_fee_percent,
         protocol_fee_percent,
+        fee_switch: fee_switch.unwrap_or(config.fees.fee_switch),
     };
-    FEES.save(deps.storage, &updated_fees)?;
-
-    let new_owner = new_owner.unwrap_or_default();
-    Ok(Response::new().add_attributes(vec![
-        attr("new_owner", new_owner),
-        attr("lp_fee_percent", lp_fee_percent.to_string()),
-        attr("protocol_fee_percent", protocol_fee_percent.to_string()),
-        attr("protocol_fee_recipient", protocol_fee_recipient.to_string()),
-    ]))
+    if let Some(config_timelock) = config_timelock {
+        config.config_timelock = config_timelock;
+    }
+    queue_config(deps, &env, config)
 }
 
-pub fn execute_remove_liquidity(
-    deps: DepsMut,
-    info: MessageInfo,
-    env: Env,
-    amount: Uint128,
-    min_token1: Uint128,
-    min_token2: Uint128,
-    expiration: Option<Expiration>,
-) -> Result<Response, ContractError> {
-    check_expiration(&expiration, &env.block)?;
+/// Config with all queued updates, the applied config if nothing is queued
+fn load_next_config(storage: &dyn Storage, block: &BlockInfo) -> StdResult<PendingConfig> {
+    match PENDING_CONFIG.may_load(storage)? {
+        Some(config) => Ok(config),
+        None => Ok(PendingConfig {
+            fees: FEES.load(storage)?,
+            dynamic_fee: DYNAMIC_FEE.may_load(storage)?,
+            config_timelock: CONFIG_TIMELOCK.may_load(storage)?.unwrap_or_default(),
+            effective_time: block.time.seconds(),
+        }),
+    }
+}
 
-    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
-    let balance = get_token_balance(deps.as_ref(), &lp_token_addr, &info.sender)?;
-    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
-    let token1 = TOKEN1.load(deps.storage)?;
-    let token2 = TOKEN2.load(deps.storage)?;
+fn validate_config(storage: &dyn Storage, config: &PendingConfig) -> Result<(), ContractError> {
+    if config.fees.fee_switch && POOL_TYPE.load(storage)? != PoolType::ConstantProduct {
+        return Err(ContractError::FeeSwitchUnsupported {});
+    }
+    validate_max_total_fee(config.fees.lp_fee_percent + config.fees.protocol_fee_percent)?;
 
-    if amount > balance {
-        return Err(ContractError::InsufficientLiquidityError {
-            requested: amount,
-            available: balance,
-        });
+    // The dynamic fee replaces the lp fee, so its maximum must fit the protocol fee as well
+    if let Some(dynamic_fee) = &config.dynamic_fee {
+        // The fee switch measures the protocol share of fee growth with the static lp fee
+        if config.fees.fee_switch {
+            return Err(ContractError::FeeSwitchWithDynamicFee {});
+        }
+        // Volatility is tracked on the price of token1 in token2
+        if POOL_TYPE.load(storage)? == PoolType::MultiAsset {
+            return Err(ContractError::TwoTokenPoolRequired {});
+        }
+        if dynamic_fee.min_fee_percent > dynamic_fee.max_fee_percent
+            || dynamic_fee.max_fee_volatility.is_zero()
+            || dynamic_fee.volatility_window == 0
+        {
+            return Err(ContractError::InvalidDynamicFee {});
+        }
+        validate_max_total_fee(dynamic_fee.max_fee_percent + config.fees.protocol_fee_percent)?;
     }
 
-    let token1_amount = amount
-        .checked_mul(token1.reserve)
-        .map_err(StdError::overflow)?
-        .checked_div(lp_token_supply)
-        .map_err(StdError::divide_by_zero)?;
-    if token1_amount < min_token1 {
-        return Err(ContractError::MinToken1Error {
-            requested: min_token1,
-            available: token1_amount,
+    if config.config_timelock > MAX_CONFIG_TIMELOCK {
+        return Err(ContractError::ConfigTimelockTooLong {
+            max: MAX_CONFIG_TIMELOCK,
         });
     }
+    Ok(())
+}
 
-    let token2_amount = amount
-        .checked_mul(token2.reserve)
-        .map_err(StdError::overflow)?
-        .checked_div(lp_token_supply)
-        .map_err(StdError::divide_by_zero)?;
-    if token2_amount < min_token2 {
-        return Err(ContractError::MinToken2Error {
-            requested: min_token2,
-            available: token2_amount,
-        });
+/// Queues a config update for the current timelock, replacing an earlier pending update, or
+/// applies it right away if the pool has no timelock
+fn queue_config(
+    deps: DepsMut,
+    env: &Env,
+    mut config: PendingConfig,
+) -> Result<Response, ContractError> {
+    validate_config(deps.storage, &config)?;
+
+    let config_timelock = CONFIG_TIMELOCK.may_load(deps.storage)?.unwrap_or_default();
+    if config_timelock == 0 {
+        PENDING_CONFIG.remove(deps.storage);
+        return apply_config(deps, env, config);
     }
 
-    TOKEN1.update(deps.storage, |mut token1| -> Result<_, ContractError> {
-        token1.reserve = token1
-            .reserve
-            .checked_sub(token1_amount)
-            .map_err(StdError::overflow)?;
-        Ok(token1)
-    })?;
+    config.effective_time = env.block.time.seconds() + config_timelock;
+    PENDING_CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "queue_config"),
+        attr("effective_time", config.effective_time.to_string()),
+    ]))
+}
 
-    TOKEN2.update(deps.storage, |mut token2| -> Result<_, ContractError> {
-        token2.reserve = token2
-            .reserve
-            .checked_sub(token2_amount)
-            .map_err(StdError::overflow)?;
-        Ok(token2)
-    })?;
+fn apply_config(
+    deps: DepsMut,
+    env: &Env,
+    config: PendingConfig,
+) -> Result<Response, ContractError> {
+    // Fees accrued so far belong to the current recipient at the current fee percents
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, _) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
 
-    let token1_transfer_msg = match token1.denom {
-        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, token1_amount)?,
-        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, token1_amount),
-    };
-    let token2_transfer_msg = match token2.denom {
-        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, token2_amount)?,
-        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, token2_amount),
-    };
+    let fees = config.fees;
+    FEES.save(deps.storage, &fees)?;
+    if fees.fee_switch {
+        update_k_last(deps.storage)?;
+    } else {
+        K_LAST.remove(deps.storage);
+    }
 
-    let lp_token_burn_msg = get_burn_msg(&lp_token_addr, &info.sender, amount)?;
+    match &config.dynamic_fee {
+        Some(dynamic_fee) => {
+            // Keep the accumulated volatility when only the bounds change
+            if VOLATILITY.may_load(deps.storage)?.is_none() {
+                let volatility = Volatility {
+                    accumulator: Decimal256::zero(),
+                    last_price: get_pool_price(deps.storage, env.block.time.seconds())?
+                        .unwrap_or_default(),
+                    last_update_time: env.block.time.seconds(),
+                };
+                VOLATILITY.save(deps.storage, &volatility)?;
+            }
+            DYNAMIC_FEE.save(deps.storage, dynamic_fee)?;
+        }
+        None => {
+            DYNAMIC_FEE.remove(deps.storage);
+            VOLATILITY.remove(deps.storage);
+        }
+    }
+    CONFIG_TIMELOCK.save(deps.storage, &config.config_timelock)?;
 
     Ok(Response::new()
-        .add_messages(vec![
-            token1_transfer_msg,
-            token2_transfer_msg,
-            lp_token_burn_msg,
-        ])
+        .add_messages(protocol_fee_mint_msg)
         .add_attributes(vec![
-            attr("liquidity_burned", amount),
-            attr("token1_returned", token1_amount),
-            attr("token2_returned", token2_amount),
+            attr("action", "apply_config"),
+            attr("lp_fee_percent", fees.lp_fee_percent.to_string()),
+            attr(
+                "protocol_fee_percent",
+                fees.protocol_fee_percent.to_string(),
+            ),
+            attr("protocol_fee_recipient", fees.protocol_fee_recipient),
+            attr("fee_switch", fees.fee_switch.to_string()),
+            attr("dynamic_fee", config.dynamic_fee.is_some().to_string()),
         ]))
 }
 
-fn get_burn_msg(contract: &Addr, owner: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
-    let msg = cw20_base::msg::ExecuteMsg::BurnFrom {
-        owner: owner.to_string(),
-        amount,
-    };
-    Ok(WasmMsg::Execute {
-        contract_addr: contract.to_string(),
-        msg: to_binary(&msg)?,
-        funds: vec![],
+pub fn execute_apply_pending_config(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config = PENDING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingConfig {})?;
+    if env.block.time.seconds() < config.effective_time {
+        return Err(ContractError::PendingConfigNotEffective {
+            effective_time: config.effective_time,
+        });
     }
-    .into())
+    PENDING_CONFIG.remove(deps.storage);
+    apply_config(deps, &env, config)
 }
 
-fn get_cw20_transfer_to_msg(
-    recipient: &Addr,
-    token_addr: &Addr,
-    token_amount: Uint128,
-) -> StdResult<CosmosMsg> {
-    // create transfer cw20 msg
-    let transfer_cw20_msg = Cw20ExecuteMsg::Transfer {
-        recipient: recipient.into(),
-        amount: token_amount,
-    };
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: token_addr.into(),
-        msg: to_binary(&transfer_cw20_msg)?,
-        funds: vec![],
-    };
-    let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
-    Ok(cw20_transfer_cosmos_msg)
+pub fn execute_cancel_pending_config(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::FeeManager, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if PENDING_CONFIG.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingConfig {});
+    }
+    PENDING_CONFIG.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "cancel_pending_config"))
 }
 
-fn get_bank_transfer_to_msg(recipient: &Addr, denom: &str, native_amount: Uint128) -> CosmosMsg {
-    let transfer_bank_msg = cosmwasm_std::BankMsg::Send {
-        to_address: recipient.into(),
-        amount: vec![Coin {
-            denom: denom.to_string(),
-            amount: native_amount,
-        }],
-    };
+pub fn execute_propose_new_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    new_owner: String,
+    expiry: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let owner = OWNER.load(deps.storage)?;
+    if Some(info.sender) != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    check_expiration(&expiry, &env.block)?;
 
-    let transfer_bank_cosmos_msg: CosmosMsg = transfer_bank_msg.into();
-    transfer_bank_cosmos_msg
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    OWNERSHIP_PROPOSAL.save(
+        deps.storage,
+        &OwnershipProposal {
+            owner: new_owner.clone(),
+            expiry,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_new_owner"),
+        attr("new_owner", new_owner),
+    ]))
 }
 
-fn get_fee_transfer_msg(
-    sender: &Addr,
-    recipient: &Addr,
-    fee_denom: &Denom,
-    amount: Uint128,
-) -> StdResult<CosmosMsg> {
-    match fee_denom {
-        Denom::Cw20(addr) => get_cw20_transfer_from_msg(sender, recipient, addr, amount),
-        Denom::Native(denom) => Ok(get_bank_transfer_to_msg(recipient, denom, amount)),
+pub fn execute_accept_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let proposal = OWNERSHIP_PROPOSAL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoOwnershipProposal {})?;
+    if info.sender != proposal.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if check_expiration(&proposal.expiry, &env.block).is_err() {
+        return Err(ContractError::OwnershipProposalExpired {});
     }
+
+    OWNER.save(deps.storage, &Some(proposal.owner.clone()))?;
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_ownership"),
+        attr("owner", proposal.owner),
+    ]))
 }
 
-fn fee_decimal_to_uint128(decimal: Decimal) -> StdResult<Uint128> {
-    let result: Uint128 = decimal
-        .atomics()
-        .checked_mul(FEE_SCALE_FACTOR)
-        .map_err(StdError::overflow)?;
+pub fn execute_cancel_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let owner = OWNER.load(deps.storage)?;
+    if Some(info.sender) != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if OWNERSHIP_PROPOSAL.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoOwnershipProposal {});
+    }
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
 
-    Ok(result / FEE_DECIMAL_PRECISION)
+    Ok(Response::new().add_attribute("action", "cancel_ownership_proposal"))
 }
 
-fn get_input_price(
-    input_amount: Uint128,
-    input_reserve: Uint128,
-    output_reserve: Uint128,
-    fee_percent: Decimal,
-) -> StdResult<Uint128> {
-    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
-        return Err(StdError::generic_err("No liquidity"));
-    };
+pub fn execute_renounce_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let owner = OWNER.load(deps.storage)?;
+    if Some(info.sender) != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    OWNER.save(deps.storage, &None)?;
+    OWNERSHIP_PROPOSAL.remove(deps.storage);
 
-    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
-    let fee_reduction_percent = FEE_SCALE_FACTOR - fee_percent;
-    let input_amount_with_fee = Uint512::from(input_amount.full_mul(fee_reduction_percent));
-    let numerator = input_amount_with_fee
-        .checked_mul(Uint512::from(output_reserve))
-        .map_err(StdError::overflow)?;
-    let denominator = Uint512::from(input_reserve)
-        .checked_mul(Uint512::from(FEE_SCALE_FACTOR))
-        .map_err(StdError::overflow)?
-        .checked_add(input_amount_with_fee)
-        .map_err(StdError::overflow)?;
+    Ok(Response::new().add_attribute("action", "renounce_ownership"))
+}
 
-    Ok(numerator
-        .checked_div(denominator)
-        .map_err(StdError::divide_by_zero)?
-        .try_into()?)
+/// Whether the address is the owner, who holds every role, or was granted the role
+fn has_role(storage: &dyn Storage, role: &Role, address: &Addr) -> StdResult<bool> {
+    if OWNER.load(storage)?.as_ref() == Some(address) {
+        return Ok(true);
+    }
+    Ok(match role {
+        Role::Owner => false,
+        _ => ROLES.has(storage, (role.key(), address)),
+    })
 }
 
-fn get_protocol_fee_amount(input_amount: Uint128, fee_percent: Decimal) -> StdResult<Uint128> {
-    if fee_percent.is_zero() {
-        return Ok(Uint128::zero());
+pub fn execute_grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::Owner, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if role == Role::Owner {
+        return Err(ContractError::OwnerRoleNotGrantable {});
     }
 
-    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
-    Ok(input_amount
-        .full_mul(fee_percent)
-        .checked_div(Uint256::from(FEE_SCALE_FACTOR))
-        .map_err(StdError::divide_by_zero)?
-        .try_into()?)
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.save(deps.storage, (role.key(), &address), &Empty {})?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "grant_role"),
+        attr("role", role.key()),
+        attr("address", address),
+    ]))
 }
 
-fn get_amount_for_denom(coins: &[Coin], denom: &str) -> Coin {
-    let amount: Uint128 = coins
-        .iter()
-        .filter(|c| c.denom == denom)
-        .map(|c| c.amount)
-        .sum();
-    Coin {
-        amount,
-        denom: denom.to_string(),
+pub fn execute_revoke_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::Owner, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
     }
+    let address = deps.api.addr_validate(&address)?;
+    remove_role(deps.storage, role, address, "revoke_role")
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn execute_swap(
+pub fn execute_renounce_role(
     deps: DepsMut,
-    info: &MessageInfo,
-    input_amount: Uint128,
-    _env: Env,
-    input_token_enum: TokenSelect,
-    recipient: String,
-    min_token: Uint128,
-    expiration: Option<Expiration>,
+    info: MessageInfo,
+    role: Role,
 ) -> Result<Response, ContractError> {
-    check_expiration(&expiration, &_env.block)?;
+    remove_role(deps.storage, role, info.sender, "renounce_role")
+}
 
-    let input_token_item = match input_token_enum {
-        TokenSelect::Token1 => TOKEN1,
-        TokenSelect::Token2 => TOKEN2,
-    };
-    let input_token = input_token_item.load(deps.storage)?;
-    let output_token_item = match input_token_enum {
-        TokenSelect::Token1 => TOKEN2,
-        TokenSelect::Token2 => TOKEN1,
-    };
-    let output_token = output_token_item.load(deps.storage)?;
+fn remove_role(
+    storage: &mut dyn Storage,
+    role: Role,
+    address: Addr,
+    action: &str,
+) -> Result<Response, ContractError> {
+    if role == Role::Owner {
+        return Err(ContractError::OwnerRoleNotGrantable {});
+    }
+    if !ROLES.has(storage, (role.key(), &address)) {
+        return Err(ContractError::MissingRole {
+            role,
+            address: address.into_string(),
+        });
+    }
 
-    // validate input_amount if native input token
-    validate_input_amount(&info.funds, input_amount, &input_token.denom)?;
+    ROLES.remove(storage, (role.key(), &address));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", action),
+        attr("role", role.key()),
+        attr("address", address),
+    ]))
+}
 
-    let fees = FEES.load(deps.storage)?;
-    let total_fee_percent = fees.lp_fee_percent + fees.protocol_fee_percent;
-    let token_bought = get_input_price(
-        input_amount,
-        input_token.reserve,
-        output_token.reserve,
-        total_fee_percent,
-    )?;
+pub fn execute_update_dynamic_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    dynamic_fee: Option<DynamicFeeConfig>,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::FeeManager, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    if min_token > token_bought {
-        return Err(ContractError::SwapMinError {
-            min: min_token,
-            available: token_bought,
+    let mut config = load_next_config(deps.storage, &env.block)?;
+    config.dynamic_fee = dynamic_fee;
+    queue_config(deps, &env, config)
+}
+
+fn validate_max_total_fee(total_fee_percent: Decimal) -> Result<(), ContractError> {
+    let max_fee_percent = Decimal::from_str(MAX_FEE_PERCENT)?;
+    if total_fee_percent > max_fee_percent {
+        return Err(ContractError::FeesTooHigh {
+            max_fee_percent,
+            total_fee_percent,
         });
     }
-    // Calculate fees
-    let protocol_fee_amount = get_protocol_fee_amount(input_amount, fees.protocol_fee_percent)?;
-    let input_amount_minus_protocol_fee = input_amount - protocol_fee_amount;
+    Ok(())
+}
 
-    let mut msgs = match input_token.denom.clone() {
-        Denom::Cw20(addr) => vec![get_cw20_transfer_from_msg(
-            &info.sender,
-            &_env.contract.address,
-            &addr,
-            input_amount_minus_protocol_fee,
-        )?],
-        Denom::Native(_) => vec![],
-    };
+fn validate_amp(amp: u64) -> Result<(), ContractError> {
+    if amp == 0 || amp > MAX_AMP {
+        return Err(ContractError::InvalidAmp {
+            amp,
+            max_amp: MAX_AMP,
+        });
+    }
+    Ok(())
+}
 
-    // Send protocol fee to protocol fee recipient
-    if !protocol_fee_amount.is_zero() {
-        msgs.push(get_fee_transfer_msg(
-            &info.sender,
-            &fees.protocol_fee_recipient,
-            &input_token.denom,
-            protocol_fee_amount,
-        )?)
+fn validate_weights(weights: &Weights) -> Result<(), ContractError> {
+    if weights.token1_weight == 0 || weights.token2_weight == 0 {
+        return Err(ContractError::ZeroWeight {});
+    }
+    let total_weight = weights.token1_weight as u128 + weights.token2_weight as u128;
+    let min_weight = weights.token1_weight.min(weights.token2_weight) as u128;
+    if min_weight * 100 < total_weight * MIN_WEIGHT_PERCENT as u128 {
+        return Err(ContractError::InvalidWeights {
+            min_weight_percent: MIN_WEIGHT_PERCENT,
+        });
     }
+    Ok(())
+}
 
-    let recipient = deps.api.addr_validate(&recipient)?;
-    // Create transfer to message
-    msgs.push(match output_token.denom {
-        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&recipient, &addr, token_bought)?,
-        Denom::Native(denom) => get_bank_transfer_to_msg(&recipient, &denom, token_bought),
-    });
+pub fn execute_ramp_amp(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    future_amp: u64,
+    future_time: u64,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::ConfigAdmin, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if POOL_TYPE.load(deps.storage)? != PoolType::Stable {
+        return Err(ContractError::NotStablePool {});
+    }
+    validate_amp(future_amp)?;
 
-    input_token_item.update(
-        deps.storage,
-        |mut input_token| -> Result<_, ContractError> {
-            input_token.reserve = input_token
-                .reserve
-                .checked_add(input_amount_minus_protocol_fee)
-                .map_err(StdError::overflow)?;
-            Ok(input_token)
-        },
-    )?;
+    let now = env.block.time.seconds();
+    let current_amp = AMP.load(deps.storage)?.current_amp(now);
+    let too_fast = future_time < now + MIN_RAMP_TIME;
+    let too_large =
+        future_amp > current_amp * MAX_AMP_CHANGE || future_amp * MAX_AMP_CHANGE < current_amp;
+    if too_fast || too_large {
+        return Err(ContractError::InvalidAmpRamp {
+            min_ramp_time: MIN_RAMP_TIME,
+            max_change: MAX_AMP_CHANGE,
+        });
+    }
 
-    output_token_item.update(
+    AMP.save(
         deps.storage,
-        |mut output_token| -> Result<_, ContractError> {
-            output_token.reserve = output_token
-                .reserve
-                .checked_sub(token_bought)
-                .map_err(StdError::overflow)?;
-            Ok(output_token)
+        &AmpConfig {
+            initial_amp: current_amp,
+            initial_time: now,
+            future_amp,
+            future_time,
         },
     )?;
 
-    Ok(Response::new().add_messages(msgs).add_attributes(vec![
-        attr("native_sold", input_amount),
-        attr("token_bought", token_bought),
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "ramp_amp"),
+        attr("initial_amp", current_amp.to_string()),
+        attr("future_amp", future_amp.to_string()),
+        attr("future_time", future_time.to_string()),
     ]))
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn execute_pass_through_swap(
+pub fn execute_stop_ramp_amp(
     deps: DepsMut,
     info: MessageInfo,
-    _env: Env,
-    output_amm_address: String,
-    input_token_enum: TokenSelect,
-    input_token_amount: Uint128,
-    output_min_token: Uint128,
-    expiration: Option<Expiration>,
+    env: Env,
 ) -> Result<Response, ContractError> {
-    check_expiration(&expiration, &_env.block)?;
+    if !has_role(deps.storage, &Role::ConfigAdmin, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if POOL_TYPE.load(deps.storage)? != PoolType::Stable {
+        return Err(ContractError::NotStablePool {});
+    }
 
-    let input_token_state = match input_token_enum {
-        TokenSelect::Token1 => TOKEN1,
-        TokenSelect::Token2 => TOKEN2,
-    };
-    let input_token = input_token_state.load(deps.storage)?;
-    let transfer_token_state = match input_token_enum {
-        TokenSelect::Token1 => TOKEN2,
-        TokenSelect::Token2 => TOKEN1,
-    };
-    let transfer_token = transfer_token_state.load(deps.storage)?;
+    let now = env.block.time.seconds();
+    let current_amp = AMP.load(deps.storage)?.current_amp(now);
+    AMP.save(
+        deps.storage,
+        &AmpConfig {
+            initial_amp: current_amp,
+            initial_time: now,
+            future_amp: current_amp,
+            future_time: now,
+        },
+    )?;
 
-    validate_input_amount(&info.funds, input_token_amount, &input_token.denom)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "stop_ramp_amp"),
+        attr("amp", current_amp.to_string()),
+    ]))
+}
 
-    let fees = FEES.load(deps.storage)?;
-    let total_fee_percent = fees.lp_fee_percent + fees.protocol_fee_percent;
-    let amount_to_transfer = get_input_price(
-        input_token_amount,
-        input_token.reserve,
-        transfer_token.reserve,
-        total_fee_percent,
-    )?;
+pub fn execute_remove_liquidity(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+    min_token1: Uint128,
+    min_token2: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    update_price_accumulators(deps.storage, &env.block)?;
 
-    // Calculate fees
-    let protocol_fee_amount =
-        get_protocol_fee_amount(input_token_amount, fees.protocol_fee_percent)?;
-    let input_amount_minus_protocol_fee = input_token_amount - protocol_fee_amount;
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let balance = get_token_balance(deps.as_ref(), &lp_token_addr, &info.sender)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
 
-    // Transfer input amount - protocol fee to contract
-    let mut msgs: Vec<CosmosMsg> = vec![];
-    if let Denom::Cw20(addr) = &input_token.denom {
-        msgs.push(get_cw20_transfer_from_msg(
-            &info.sender,
-            &_env.contract.address,
-            addr,
-            input_amount_minus_protocol_fee,
-        )?)
-    };
+    if amount > balance {
+        return Err(ContractError::InsufficientLiquidityError {
+            requested: amount,
+            available: balance,
+        });
+    }
 
-    // Send protocol fee to protocol fee recipient
-    if !protocol_fee_amount.is_zero() {
-        msgs.push(get_fee_transfer_msg(
-            &info.sender,
-            &fees.protocol_fee_recipient,
-            &input_token.denom,
-            protocol_fee_amount,
-        )?)
+    let token1_amount = amount
+        .checked_mul(token1.reserve)
+        .map_err(StdError::overflow)?
+        .checked_div(lp_token_supply)
+        .map_err(StdError::divide_by_zero)?;
+    if token1_amount < min_token1 {
+        return Err(ContractError::MinToken1Error {
+            requested: min_token1,
+            available: token1_amount,
+        });
     }
 
-    let output_amm_address = deps.api.addr_validate(&output_amm_address)?;
+    let token2_amount = amount
+        .checked_mul(token2.reserve)
+        .map_err(StdError::overflow)?
+        .checked_div(lp_token_supply)
+        .map_err(StdError::divide_by_zero)?;
+    if token2_amount < min_token2 {
+        return Err(ContractError::MinToken2Error {
+            requested: min_token2,
+            available: token2_amount,
+        });
+    }
 
-    // Increase allowance of output contract is transfer token is cw20
-    if let Denom::Cw20(addr) = &transfer_token.denom {
-        msgs.push(get_cw20_increase_allowance_msg(
-            addr,
-            &output_amm_address,
-            amount_to_transfer,
-            Some(Expiration::AtHeight(_env.block.height + 1)),
-        )?)
+    TOKEN1.update(deps.storage, |mut token1| -> Result<_, ContractError> {
+        token1.reserve = token1
+            .reserve
+            .checked_sub(token1_amount)
+            .map_err(StdError::overflow)?;
+        Ok(token1)
+    })?;
+
+    TOKEN2.update(deps.storage, |mut token2| -> Result<_, ContractError> {
+        token2.reserve = token2
+            .reserve
+            .checked_sub(token2_amount)
+            .map_err(StdError::overflow)?;
+        Ok(token2)
+    })?;
+    update_k_last(deps.storage)?;
+
+    let token1_transfer_msg = match token1.denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, token1_amount)?,
+        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, token1_amount),
+    };
+    let token2_transfer_msg = match token2.denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, token2_amount)?,
+        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, token2_amount),
     };
 
-    let resp: InfoResponse = deps
-        .querier
-        .query_wasm_smart(&output_amm_address, &QueryMsg::Info {})?;
+    let lp_token_burn_msg = get_burn_msg(&lp_token_addr, &info.sender, amount)?;
 
-    let transfer_input_token_enum = if transfer_token.denom == resp.token1_denom {
-        Ok(TokenSelect::Token1)
-    } else if transfer_token.denom == resp.token2_denom {
-        Ok(TokenSelect::Token2)
-    } else {
-        Err(ContractError::InvalidOutputPool {})
-    }?;
+    Ok(Response::new()
+        .add_messages(protocol_fee_mint_msg)
+        .add_messages(vec![
+            token1_transfer_msg,
+            token2_transfer_msg,
+            lp_token_burn_msg,
+        ])
+        .add_attributes(vec![
+            attr("liquidity_burned", amount),
+            attr("token1_returned", token1_amount),
+            attr("token2_returned", token2_amount),
+        ]))
+}
 
-    let swap_msg = ExecuteMsg::SwapAndSendTo {
-        input_token: transfer_input_token_enum,
-        input_amount: amount_to_transfer,
-        recipient: info.sender.to_string(),
-        min_token: output_min_token,
-        expiration,
+struct ZapOut {
+    /// Output token withdrawn from the pool
+    withdrawn_amount: Uint128,
+    /// Other token withdrawn and swapped back into the pool
+    swap_amount: Uint128,
+    swap_output: Uint128,
+    protocol_fee_amount: Uint128,
+}
+
+impl ZapOut {
+    fn output_amount(&self) -> Uint128 {
+        self.withdrawn_amount + self.swap_output
+    }
+}
+
+/// Withdraws `amount` liquidity and swaps the side that is not wanted against the reserves
+/// left after the withdrawal
+fn get_zap_out(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    output_token_enum: &TokenSelect,
+    amount: Uint128,
+    lp_token_supply: Uint128,
+) -> StdResult<ZapOut> {
+    let (input_token_enum, input_token, output_token) = match output_token_enum {
+        TokenSelect::Token1 => (
+            TokenSelect::Token2,
+            TOKEN2.load(storage)?,
+            TOKEN1.load(storage)?,
+        ),
+        TokenSelect::Token2 => (
+            TokenSelect::Token1,
+            TOKEN1.load(storage)?,
+            TOKEN2.load(storage)?,
+        ),
     };
 
-    msgs.push(
-        WasmMsg::Execute {
-            contract_addr: output_amm_address.into(),
-            msg: to_binary(&swap_msg)?,
-            funds: match transfer_token.denom {
-                Denom::Cw20(_) => vec![],
-                Denom::Native(denom) => vec![Coin {
-                    denom,
-                    amount: amount_to_transfer,
-                }],
-            },
-        }
-        .into(),
-    );
+    let withdrawn_amount = amount
+        .checked_mul(output_token.reserve)
+        .map_err(StdError::overflow)?
+        .checked_div(lp_token_supply)
+        .map_err(StdError::divide_by_zero)?;
+    let swap_amount = amount
+        .checked_mul(input_token.reserve)
+        .map_err(StdError::overflow)?
+        .checked_div(lp_token_supply)
+        .map_err(StdError::divide_by_zero)?;
 
-    input_token_state.update(deps.storage, |mut token| -> Result<_, ContractError> {
-        // Add input amount - protocol fee to input token reserve
+    let fees = FEES.load(storage)?;
+    let total_fee_percent = get_total_fee_percent(storage, block, &fees)?;
+    let swap_output = get_swap_output(
+        storage,
+        block,
+        &input_token_enum,
+        swap_amount,
+        input_token.reserve - swap_amount,
+        output_token.reserve - withdrawn_amount,
+        total_fee_percent,
+    )?;
+
+    Ok(ZapOut {
+        withdrawn_amount,
+        swap_amount,
+        swap_output,
+        protocol_fee_amount: get_protocol_fee_amount(
+            swap_amount,
+            fees.transferred_protocol_fee_percent(),
+        )?,
+    })
+}
+
+pub fn execute_zap_out(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+    output_token_enum: TokenSelect,
+    min_output: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let balance = get_token_balance(deps.as_ref(), &lp_token_addr, &info.sender)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+    if amount > balance {
+        return Err(ContractError::InsufficientLiquidityError {
+            requested: amount,
+            available: balance,
+        });
+    }
+
+    let zap = get_zap_out(
+        deps.storage,
+        &env.block,
+        &output_token_enum,
+        amount,
+        lp_token_supply,
+    )?;
+    let output_amount = zap.output_amount();
+    if output_amount < min_output {
+        return Err(ContractError::SwapMinError {
+            min: min_output,
+            available: output_amount,
+        });
+    }
+
+    let k_before = TOKEN1
+        .load(deps.storage)?
+        .reserve
+        .full_mul(TOKEN2.load(deps.storage)?.reserve);
+    let (input_token_item, output_token_item) = match output_token_enum {
+        TokenSelect::Token1 => (TOKEN2, TOKEN1),
+        TokenSelect::Token2 => (TOKEN1, TOKEN2),
+    };
+    // The swapped side goes back into the pool minus the protocol fee
+    let input_token = input_token_item.update(deps.storage, |mut token| -> StdResult<_> {
         token.reserve = token
             .reserve
-            .checked_add(input_amount_minus_protocol_fee)
+            .checked_sub(zap.protocol_fee_amount)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+    let output_token = output_token_item.update(deps.storage, |mut token| -> StdResult<_> {
+        token.reserve = token
+            .reserve
+            .checked_sub(output_amount)
             .map_err(StdError::overflow)?;
         Ok(token)
     })?;
+    update_k_last_for_zap(
+        deps.storage,
+        k_before,
+        lp_token_supply,
+        lp_token_supply - amount,
+    )?;
+
+    let mut msgs = protocol_fee_mint_msg;
+    msgs.push(get_burn_msg(&lp_token_addr, &info.sender, amount)?);
+    if !zap.protocol_fee_amount.is_zero() {
+        let fees = FEES.load(deps.storage)?;
+        msgs.push(get_fee_transfer_msg(
+            &env.contract.address,
+            &fees.protocol_fee_recipient,
+            &input_token.denom,
+            zap.protocol_fee_amount,
+            true,
+        )?);
+    }
+    msgs.push(match output_token.denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, output_amount)?,
+        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, output_amount),
+    });
+
+    update_volatility(deps.storage, &env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("liquidity_burned", amount),
+        attr("swap_amount", zap.swap_amount),
+        attr("output_amount", output_amount),
+    ]))
+}
+
+fn get_burn_msg(contract: &Addr, owner: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    let msg = cw20_base::msg::ExecuteMsg::BurnFrom {
+        owner: owner.to_string(),
+        amount,
+    };
+    Ok(WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg: to_binary(&msg)?,
+        funds: vec![],
+    }
+    .into())
+}
+
+fn get_cw20_transfer_to_msg(
+    recipient: &Addr,
+    token_addr: &Addr,
+    token_amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    // create transfer cw20 msg
+    let transfer_cw20_msg = Cw20ExecuteMsg::Transfer {
+        recipient: recipient.into(),
+        amount: token_amount,
+    };
+    let exec_cw20_transfer = WasmMsg::Execute {
+        contract_addr: token_addr.into(),
+        msg: to_binary(&transfer_cw20_msg)?,
+        funds: vec![],
+    };
+    let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
+    Ok(cw20_transfer_cosmos_msg)
+}
+
+fn get_bank_transfer_to_msg(recipient: &Addr, denom: &str, native_amount: Uint128) -> CosmosMsg {
+    let transfer_bank_msg = cosmwasm_std::BankMsg::Send {
+        to_address: recipient.into(),
+        amount: vec![Coin {
+            denom: denom.to_string(),
+            amount: native_amount,
+        }],
+    };
+
+    let transfer_bank_cosmos_msg: CosmosMsg = transfer_bank_msg.into();
+    transfer_bank_cosmos_msg
+}
+
+fn get_transfer_to_msg(recipient: &Addr, denom: &Denom, amount: Uint128) -> StdResult<CosmosMsg> {
+    match denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(recipient, addr, amount),
+        Denom::Native(denom) => Ok(get_bank_transfer_to_msg(recipient, denom, amount)),
+    }
+}
+
+fn get_fee_transfer_msg(
+    sender: &Addr,
+    recipient: &Addr,
+    fee_denom: &Denom,
+    amount: Uint128,
+    fee_received: bool,
+) -> StdResult<CosmosMsg> {
+    match fee_denom {
+        // Cw20 fees received through `Send` are already held by the contract
+        Denom::Cw20(addr) if fee_received => get_cw20_transfer_to_msg(recipient, addr, amount),
+        Denom::Cw20(addr) => get_cw20_transfer_from_msg(sender, recipient, addr, amount),
+        Denom::Native(denom) => Ok(get_bank_transfer_to_msg(recipient, denom, amount)),
+    }
+}
+
+fn fee_decimal_to_uint128(decimal: Decimal) -> StdResult<Uint128> {
+    let result: Uint128 = decimal
+        .atomics()
+        .checked_mul(FEE_SCALE_FACTOR)
+        .map_err(StdError::overflow)?;
+
+    Ok(result / FEE_DECIMAL_PRECISION)
+}
+
+fn get_input_price(
+    input_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+
+    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
+    let fee_reduction_percent = FEE_SCALE_FACTOR - fee_percent;
+    let input_amount_with_fee = Uint512::from(input_amount.full_mul(fee_reduction_percent));
+    let numerator = input_amount_with_fee
+        .checked_mul(Uint512::from(output_reserve))
+        .map_err(StdError::overflow)?;
+    let denominator = Uint512::from(input_reserve)
+        .checked_mul(Uint512::from(FEE_SCALE_FACTOR))
+        .map_err(StdError::overflow)?
+        .checked_add(input_amount_with_fee)
+        .map_err(StdError::overflow)?;
+
+    Ok(numerator
+        .checked_div(denominator)
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?)
+}
+
+fn get_input_amount_with_fee(input_amount: Uint128, fee_percent: Decimal) -> StdResult<Uint128> {
+    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
+    let fee_reduction_percent = FEE_SCALE_FACTOR - fee_percent;
+    Ok(input_amount
+        .full_mul(fee_reduction_percent)
+        .checked_div(Uint256::from(FEE_SCALE_FACTOR))
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?)
+}
+
+fn get_stable_input_price(
+    input_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+    amp: u64,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+
+    let input_amount_with_fee = get_input_amount_with_fee(input_amount, fee_percent)?;
+    let d = compute_d(amp, input_reserve, output_reserve)?;
+    let new_input_reserve = input_reserve
+        .checked_add(input_amount_with_fee)
+        .map_err(StdError::overflow)?;
+    let new_output_reserve = compute_y(amp, new_input_reserve, d)?;
+
+    // Round in favor of the pool
+    Ok(output_reserve
+        .checked_sub(new_output_reserve)
+        .unwrap_or_default()
+        .saturating_sub(Uint128::new(1)))
+}
+
+fn get_weighted_input_price(
+    input_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+    input_weight: u64,
+    output_weight: u64,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+
+    let input_amount_with_fee = get_input_amount_with_fee(input_amount, fee_percent)?;
+    if input_amount_with_fee.full_mul(100u64)
+        > input_reserve.full_mul(MAX_WEIGHTED_IN_RATIO_PERCENT)
+    {
+        return Err(StdError::generic_err(format!(
+            "Swap input exceeds {}% of the input reserve",
+            MAX_WEIGHTED_IN_RATIO_PERCENT
+        )));
+    }
+
+    // output = output_reserve * (1 - (input_reserve / new_input_reserve)^(input_weight / output_weight))
+    let new_input_reserve = input_reserve.full_mul(1u64) + Uint256::from(input_amount_with_fee);
+    let base = Decimal256::from_ratio(input_reserve, new_input_reserve);
+    let exp = Decimal256::from_ratio(input_weight, output_weight);
+    let output_ratio = Decimal256::one() - pow(base, exp)?;
+    let output: Uint128 = (Uint256::from(output_reserve) * output_ratio).try_into()?;
+
+    // Round in favor of the pool
+    Ok(output.saturating_sub(Uint128::new(1)))
+}
+
+/// Output of a swap for the curve of this pool
+fn get_swap_output(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    input_token: &TokenSelect,
+    input_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+) -> StdResult<Uint128> {
+    match POOL_TYPE.load(storage)? {
+        PoolType::ConstantProduct | PoolType::MultiAsset => {
+            get_input_price(input_amount, input_reserve, output_reserve, fee_percent)
+        }
+        PoolType::Stable => {
+            let amp = AMP.load(storage)?.current_amp(block.time.seconds());
+            get_stable_input_price(
+                input_amount,
+                input_reserve,
+                output_reserve,
+                fee_percent,
+                amp,
+            )
+        }
+        PoolType::Weighted => {
+            let weights = WEIGHTS.load(storage)?;
+            let (input_weight, output_weight) = match input_token {
+                TokenSelect::Token1 => (weights.token1_weight, weights.token2_weight),
+                TokenSelect::Token2 => (weights.token2_weight, weights.token1_weight),
+            };
+            get_weighted_input_price(
+                input_amount,
+                input_reserve,
+                output_reserve,
+                fee_percent,
+                input_weight,
+                output_weight,
+            )
+        }
+    }
+}
+
+fn check_output_amount(output_amount: Uint128, output_reserve: Uint128) -> StdResult<()> {
+    if output_amount >= output_reserve {
+        return Err(StdError::generic_err(format!(
+            "Output amount {} must be less than the output reserve {}",
+            output_amount, output_reserve
+        )));
+    }
+    Ok(())
+}
+
+/// Inverse of `get_input_amount_with_fee`, rounded up
+fn get_input_amount_before_fee(
+    input_amount_with_fee: Uint128,
+    fee_percent: Decimal,
+) -> StdResult<Uint128> {
+    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
+    let fee_reduction_percent = Uint256::from(FEE_SCALE_FACTOR - fee_percent);
+    Ok(input_amount_with_fee
+        .full_mul(FEE_SCALE_FACTOR)
+        .checked_add(fee_reduction_percent - Uint256::from(1u8))
+        .map_err(StdError::overflow)?
+        .checked_div(fee_reduction_percent)
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?)
+}
+
+fn get_output_price(
+    output_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+    check_output_amount(output_amount, output_reserve)?;
+
+    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
+    let fee_reduction_percent = FEE_SCALE_FACTOR - fee_percent;
+    let numerator = Uint512::from(input_reserve.full_mul(output_amount))
+        .checked_mul(Uint512::from(FEE_SCALE_FACTOR))
+        .map_err(StdError::overflow)?;
+    let denominator =
+        Uint512::from((output_reserve - output_amount).full_mul(fee_reduction_percent));
+
+    // Round in favor of the pool
+    let input_amount: Uint128 = numerator
+        .checked_div(denominator)
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?;
+    input_amount
+        .checked_add(Uint128::new(1))
+        .map_err(StdError::overflow)
+}
+
+fn get_stable_output_price(
+    output_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+    amp: u64,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+    check_output_amount(output_amount, output_reserve)?;
+
+    let d = compute_d(amp, input_reserve, output_reserve)?;
+    let new_input_reserve = compute_y(amp, output_reserve - output_amount, d)?;
+
+    // Round in favor of the pool
+    let input_amount_with_fee = new_input_reserve
+        .saturating_sub(input_reserve)
+        .checked_add(Uint128::new(1))
+        .map_err(StdError::overflow)?;
+    get_input_amount_before_fee(input_amount_with_fee, fee_percent)
+}
+
+fn get_weighted_output_price(
+    output_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+    input_weight: u64,
+    output_weight: u64,
+) -> StdResult<Uint128> {
+    if input_reserve == Uint128::zero() || output_reserve == Uint128::zero() {
+        return Err(StdError::generic_err("No liquidity"));
+    };
+    check_output_amount(output_amount, output_reserve)?;
+
+    // input = input_reserve * ((output_reserve / new_output_reserve)^(output_weight / input_weight) - 1),
+    // computed from the inverse power as input_reserve * (1 - power) / power
+    let base = Decimal256::from_ratio(output_reserve - output_amount, output_reserve);
+    let exp = Decimal256::from_ratio(output_weight, input_weight);
+    let power = pow(base, exp)?;
+    if power.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Output amount {} must be less than the output reserve {}",
+            output_amount, output_reserve
+        )));
+    }
+    let input_amount_with_fee: Uint128 = Uint256::from(input_reserve)
+        .checked_mul((Decimal256::one() - power).atomics())
+        .map_err(StdError::overflow)?
+        .checked_div(power.atomics())
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?;
+
+    // Round in favor of the pool
+    let input_amount_with_fee = input_amount_with_fee
+        .checked_add(Uint128::new(1))
+        .map_err(StdError::overflow)?;
+    if input_amount_with_fee.full_mul(100u64)
+        > input_reserve.full_mul(MAX_WEIGHTED_IN_RATIO_PERCENT)
+    {
+        return Err(StdError::generic_err(format!(
+            "Swap input exceeds {}% of the input reserve",
+            MAX_WEIGHTED_IN_RATIO_PERCENT
+        )));
+    }
+    get_input_amount_before_fee(input_amount_with_fee, fee_percent)
+}
+
+/// Input required for an exact output with the curve of this pool
+fn get_swap_input(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    input_token: &TokenSelect,
+    output_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+    fee_percent: Decimal,
+) -> StdResult<Uint128> {
+    match POOL_TYPE.load(storage)? {
+        PoolType::ConstantProduct | PoolType::MultiAsset => {
+            get_output_price(output_amount, input_reserve, output_reserve, fee_percent)
+        }
+        PoolType::Stable => {
+            let amp = AMP.load(storage)?.current_amp(block.time.seconds());
+            get_stable_output_price(
+                output_amount,
+                input_reserve,
+                output_reserve,
+                fee_percent,
+                amp,
+            )
+        }
+        PoolType::Weighted => {
+            let weights = WEIGHTS.load(storage)?;
+            let (input_weight, output_weight) = match input_token {
+                TokenSelect::Token1 => (weights.token1_weight, weights.token2_weight),
+                TokenSelect::Token2 => (weights.token2_weight, weights.token1_weight),
+            };
+            get_weighted_output_price(
+                output_amount,
+                input_reserve,
+                output_reserve,
+                fee_percent,
+                input_weight,
+                output_weight,
+            )
+        }
+    }
+}
+
+/// Lp fee charged in the current block, the dynamic fee for the decayed volatility when dynamic
+/// fees are enabled
+fn get_lp_fee_percent(storage: &dyn Storage, block: &BlockInfo, fees: &Fees) -> StdResult<Decimal> {
+    match DYNAMIC_FEE.may_load(storage)? {
+        Some(config) => {
+            let volatility = VOLATILITY.load(storage)?;
+            let accumulator = decayed_volatility(&volatility, &config, block.time.seconds());
+            get_dynamic_fee(&config, accumulator)
+        }
+        None => Ok(fees.lp_fee_percent),
+    }
+}
+
+fn get_total_fee_percent(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    fees: &Fees,
+) -> StdResult<Decimal> {
+    Ok(get_lp_fee_percent(storage, block, fees)? + fees.protocol_fee_percent)
+}
+
+fn get_pool_price(storage: &dyn Storage, now: u64) -> StdResult<Option<Decimal256>> {
+    let (token1_reserve, token2_reserve) = get_price_reserves(storage, now)?;
+    if token1_reserve.is_zero() {
+        return Ok(None);
+    }
+    Ok(Some(Decimal256::from_ratio(token2_reserve, token1_reserve)))
+}
+
+/// Adds the price movement of a swap to the volatility accumulator, must run after the
+/// reserves are updated
+fn update_volatility(storage: &mut dyn Storage, block: &BlockInfo) -> StdResult<()> {
+    let config = match DYNAMIC_FEE.may_load(storage)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    if let Some(price) = get_pool_price(storage, block.time.seconds())? {
+        let mut volatility = VOLATILITY.load(storage)?;
+        accumulate_volatility(&mut volatility, &config, price, block.time.seconds());
+        VOLATILITY.save(storage, &volatility)?;
+    }
+    Ok(())
+}
+
+fn get_protocol_fee_amount(input_amount: Uint128, fee_percent: Decimal) -> StdResult<Uint128> {
+    if fee_percent.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let fee_percent = fee_decimal_to_uint128(fee_percent)?;
+    Ok(input_amount
+        .full_mul(fee_percent)
+        .checked_div(Uint256::from(FEE_SCALE_FACTOR))
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?)
+}
+
+/// Integer square root rounded down
+fn isqrt(n: Uint256) -> Uint256 {
+    if n.is_zero() {
+        return n;
+    }
+
+    let two = Uint256::from(2u8);
+    let mut x = n;
+    let mut y = x / two + Uint256::from(1u8);
+    while y < x {
+        x = y;
+        y = (x + n / x) / two;
+    }
+    x
+}
+
+/// Liquidity minted to the protocol for its share of the swap fees that grew sqrt(k) since
+/// `k_last`, as in Uniswap v2. The protocol share is protocol_fee / (lp_fee + protocol_fee),
+/// so the minted amount s satisfies s / (supply + s) = share * (sqrt(k) - sqrt(k_last)) / sqrt(k).
+fn get_protocol_fee_liquidity_amount(
+    fees: &Fees,
+    token1_reserve: Uint128,
+    token2_reserve: Uint128,
+    k_last: Uint256,
+    lp_token_supply: Uint128,
+) -> StdResult<Uint128> {
+    if k_last.is_zero() || fees.protocol_fee_percent.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let root_k = isqrt(token1_reserve.full_mul(token2_reserve));
+    let root_k_last = isqrt(k_last);
+    if root_k <= root_k_last {
+        return Ok(Uint128::zero());
+    }
+
+    let protocol_fee = Uint512::from(fee_decimal_to_uint128(fees.protocol_fee_percent)?);
+    let lp_fee = Uint512::from(fee_decimal_to_uint128(fees.lp_fee_percent)?);
+    let numerator =
+        Uint512::from(lp_token_supply) * Uint512::from(root_k - root_k_last) * protocol_fee;
+    let denominator = lp_fee * Uint512::from(root_k) + protocol_fee * Uint512::from(root_k_last);
+    Ok((numerator / denominator).try_into()?)
+}
+
+/// Protocol fee liquidity accrued since the last liquidity change, zero unless the fee
+/// switch is on
+fn get_protocol_fee_liquidity(
+    storage: &dyn Storage,
+    lp_token_supply: Uint128,
+) -> StdResult<Uint128> {
+    let fees = FEES.load(storage)?;
+    if !fees.fee_switch {
+        return Ok(Uint128::zero());
+    }
+
+    let k_last = K_LAST.may_load(storage)?.unwrap_or_default();
+    get_protocol_fee_liquidity_amount(
+        &fees,
+        TOKEN1.load(storage)?.reserve,
+        TOKEN2.load(storage)?.reserve,
+        k_last,
+        lp_token_supply,
+    )
+}
+
+/// Mints the accrued protocol fee liquidity to the protocol fee recipient. Returns the mint
+/// msg and the minted amount, which the queried LP token supply does not include yet.
+fn mint_protocol_fee(
+    storage: &dyn Storage,
+    lp_token_addr: &Addr,
+    lp_token_supply: Uint128,
+) -> StdResult<(Vec<CosmosMsg>, Uint128)> {
+    let liquidity = get_protocol_fee_liquidity(storage, lp_token_supply)?;
+    if liquidity.is_zero() {
+        return Ok((vec![], liquidity));
+    }
+
+    let fees = FEES.load(storage)?;
+    let mint_msg = mint_lp_tokens(&fees.protocol_fee_recipient, liquidity, lp_token_addr)?;
+    Ok((vec![mint_msg], liquidity))
+}
+
+/// Records the reserves after a liquidity change as the base of the next protocol fee mint
+fn update_k_last(storage: &mut dyn Storage) -> StdResult<()> {
+    if FEES.load(storage)?.fee_switch {
+        let k = TOKEN1
+            .load(storage)?
+            .reserve
+            .full_mul(TOKEN2.load(storage)?.reserve);
+        K_LAST.save(storage, &k)?;
+    }
+    Ok(())
+}
+
+/// Records the reserves before a zap, scaled by its liquidity change, as the base of the next
+/// protocol fee mint. The fee of the zap's internal swap stays in the reserves, so it is left
+/// to accrue to the protocol like the fee of any other swap.
+fn update_k_last_for_zap(
+    storage: &mut dyn Storage,
+    k_before: Uint256,
+    lp_token_supply_before: Uint128,
+    lp_token_supply_after: Uint128,
+) -> StdResult<()> {
+    if !FEES.load(storage)?.fee_switch {
+        return Ok(());
+    }
+
+    let root_k: Uint256 = (Uint512::from(isqrt(k_before)) * Uint512::from(lp_token_supply_after)
+        / Uint512::from(lp_token_supply_before))
+    .try_into()?;
+    K_LAST.save(
+        storage,
+        &root_k.checked_mul(root_k).map_err(StdError::overflow)?,
+    )?;
+    Ok(())
+}
+
+fn get_amount_for_denom(coins: &[Coin], denom: &str) -> Coin {
+    let amount: Uint128 = coins
+        .iter()
+        .filter(|c| c.denom == denom)
+        .map(|c| c.amount)
+        .sum();
+    Coin {
+        amount,
+        denom: denom.to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_swap(
+    deps: DepsMut,
+    info: &MessageInfo,
+    input_amount: Uint128,
+    _env: Env,
+    input_token_enum: TokenSelect,
+    recipient: String,
+    min_token: Uint128,
+    expiration: Option<Expiration>,
+    input_received: bool,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &_env.block)?;
+    update_price_accumulators(deps.storage, &_env.block)?;
+
+    let input_token_item = match input_token_enum {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    };
+    let input_token = input_token_item.load(deps.storage)?;
+    let output_token_item = match input_token_enum {
+        TokenSelect::Token1 => TOKEN2,
+        TokenSelect::Token2 => TOKEN1,
+    };
+    let output_token = output_token_item.load(deps.storage)?;
+
+    // validate input_amount if native input token
+    validate_input_amount(&info.funds, input_amount, &input_token.denom)?;
+
+    // The swap is priced in the reply to the inbound transfer with the amount that arrived
+    if input_token.fee_on_transfer && !input_received {
+        return execute_fee_on_transfer_swap(
+            deps,
+            info,
+            _env,
+            input_token_enum,
+            &input_token,
+            input_amount,
+            recipient,
+            min_token,
+        );
+    }
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &_env.block, &fees)?;
+    let token_bought = get_swap_output(
+        deps.storage,
+        &_env.block,
+        &input_token_enum,
+        input_amount,
+        input_token.reserve,
+        output_token.reserve,
+        total_fee_percent,
+    )?;
+
+    if min_token > token_bought {
+        return Err(ContractError::SwapMinError {
+            min: min_token,
+            available: token_bought,
+        });
+    }
+    // Calculate fees
+    let protocol_fee_amount =
+        get_protocol_fee_amount(input_amount, fees.transferred_protocol_fee_percent())?;
+    let input_amount_minus_protocol_fee = input_amount - protocol_fee_amount;
+
+    let mut msgs = match input_token.denom.clone() {
+        Denom::Cw20(addr) if !input_received => vec![get_cw20_transfer_from_msg(
+            &info.sender,
+            &_env.contract.address,
+            &addr,
+            input_amount_minus_protocol_fee,
+        )?],
+        _ => vec![],
+    };
+
+    // Send protocol fee to protocol fee recipient
+    if !protocol_fee_amount.is_zero() {
+        msgs.push(get_fee_transfer_msg(
+            &info.sender,
+            &fees.protocol_fee_recipient,
+            &input_token.denom,
+            protocol_fee_amount,
+            input_received,
+        )?)
+    }
+
+    let recipient = deps.api.addr_validate(&recipient)?;
+    // Create transfer to message
+    msgs.push(match output_token.denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&recipient, &addr, token_bought)?,
+        Denom::Native(denom) => get_bank_transfer_to_msg(&recipient, &denom, token_bought),
+    });
+
+    input_token_item.update(
+        deps.storage,
+        |mut input_token| -> Result<_, ContractError> {
+            input_token.reserve = input_token
+                .reserve
+                .checked_add(input_amount_minus_protocol_fee)
+                .map_err(StdError::overflow)?;
+            Ok(input_token)
+        },
+    )?;
+
+    output_token_item.update(
+        deps.storage,
+        |mut output_token| -> Result<_, ContractError> {
+            output_token.reserve = output_token
+                .reserve
+                .checked_sub(token_bought)
+                .map_err(StdError::overflow)?;
+            Ok(output_token)
+        },
+    )?;
+
+    update_volatility(deps.storage, &_env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("native_sold", input_amount),
+        attr("token_bought", token_bought),
+    ]))
+}
+
+/// Pulls fee on transfer input tokens into the pool and records the pool balance before
+#[allow(clippy::too_many_arguments)]
+fn execute_fee_on_transfer_swap(
+    deps: DepsMut,
+    info: &MessageInfo,
+    env: Env,
+    input_token_enum: TokenSelect,
+    input_token: &Token,
+    input_amount: Uint128,
+    recipient: String,
+    min_token: Uint128,
+) -> Result<Response, ContractError> {
+    let addr = match &input_token.denom {
+        Denom::Cw20(addr) => addr,
+        Denom::Native(_) => return Err(ContractError::NativeFeeOnTransfer {}),
+    };
+    let pending_deposit = PendingDeposit::Swap {
+        sender: info.sender.clone(),
+        input_token: input_token_enum,
+        recipient: deps.api.addr_validate(&recipient)?,
+        min_token,
+        balance: get_pool_balance(deps.as_ref(), &env, &input_token.denom)?,
+    };
+    PENDING_DEPOSIT.save(deps.storage, &pending_deposit)?;
+
+    let transfer_msg =
+        get_cw20_transfer_from_msg(&info.sender, &env.contract.address, addr, input_amount)?;
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            transfer_msg,
+            FEE_ON_TRANSFER_DEPOSIT_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "fee_on_transfer_swap"),
+            attr("input_amount", input_amount),
+        ]))
+}
+
+/// Settles a fee on transfer deposit with the amounts that arrived in the pool
+fn handle_fee_on_transfer_deposit_reply(
+    deps: DepsMut,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let pending_deposit = PENDING_DEPOSIT.load(deps.storage)?;
+    PENDING_DEPOSIT.remove(deps.storage);
+
+    match pending_deposit {
+        PendingDeposit::Swap {
+            sender,
+            input_token,
+            recipient,
+            min_token,
+            balance,
+        } => {
+            let denom = match input_token {
+                TokenSelect::Token1 => TOKEN1,
+                TokenSelect::Token2 => TOKEN2,
+            }
+            .load(deps.storage)?
+            .denom;
+            let input_amount = get_pool_balance(deps.as_ref(), &env, &denom)?
+                .checked_sub(balance)
+                .map_err(StdError::overflow)?;
+            let info = MessageInfo {
+                sender,
+                funds: vec![],
+            };
+            execute_swap(
+                deps,
+                &info,
+                input_amount,
+                env,
+                input_token,
+                recipient.into_string(),
+                min_token,
+                None,
+                true,
+            )
+        }
+        PendingDeposit::AddLiquidity {
+            sender,
+            token1_amount,
+            token2_amount,
+            min_liquidity,
+            token1_balance,
+            token2_balance,
+        } => settle_fee_on_transfer_add_liquidity(
+            deps,
+            env,
+            sender,
+            token1_amount,
+            token2_amount,
+            min_liquidity,
+            token1_balance,
+            token2_balance,
+        ),
+    }
+}
+
+pub fn execute_swap_exact_out(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    output_token_enum: TokenSelect,
+    output_amount: Uint128,
+    max_input: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let (input_token_enum, input_token_item, output_token_item) = match output_token_enum {
+        TokenSelect::Token1 => (TokenSelect::Token2, TOKEN2, TOKEN1),
+        TokenSelect::Token2 => (TokenSelect::Token1, TOKEN1, TOKEN2),
+    };
+    let input_token = input_token_item.load(deps.storage)?;
+    check_no_fee_on_transfer(&input_token)?;
+    let output_token = output_token_item.load(deps.storage)?;
+
+    // Native input is sent up to the max input, the rest is refunded
+    validate_input_amount(&info.funds, max_input, &input_token.denom)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let input_amount = get_swap_input(
+        deps.storage,
+        &env.block,
+        &input_token_enum,
+        output_amount,
+        input_token.reserve,
+        output_token.reserve,
+        total_fee_percent,
+    )?;
+
+    if input_amount > max_input {
+        return Err(ContractError::SwapMaxInputError {
+            max_input,
+            required: input_amount,
+        });
+    }
+    // Calculate fees
+    let protocol_fee_amount =
+        get_protocol_fee_amount(input_amount, fees.transferred_protocol_fee_percent())?;
+    let input_amount_minus_protocol_fee = input_amount - protocol_fee_amount;
+
+    let mut msgs = match &input_token.denom {
+        Denom::Cw20(addr) => vec![get_cw20_transfer_from_msg(
+            &info.sender,
+            &env.contract.address,
+            addr,
+            input_amount_minus_protocol_fee,
+        )?],
+        Denom::Native(denom) => {
+            let refund_amount = max_input - input_amount;
+            if refund_amount.is_zero() {
+                vec![]
+            } else {
+                vec![get_bank_transfer_to_msg(&info.sender, denom, refund_amount)]
+            }
+        }
+    };
+
+    // Send protocol fee to protocol fee recipient
+    if !protocol_fee_amount.is_zero() {
+        msgs.push(get_fee_transfer_msg(
+            &info.sender,
+            &fees.protocol_fee_recipient,
+            &input_token.denom,
+            protocol_fee_amount,
+            false,
+        )?)
+    }
+
+    msgs.push(match output_token.denom {
+        Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, &addr, output_amount)?,
+        Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, &denom, output_amount),
+    });
+
+    input_token_item.update(deps.storage, |mut token| -> Result<_, ContractError> {
+        token.reserve = token
+            .reserve
+            .checked_add(input_amount_minus_protocol_fee)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+
+    output_token_item.update(deps.storage, |mut token| -> Result<_, ContractError> {
+        token.reserve = token
+            .reserve
+            .checked_sub(output_amount)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+
+    update_volatility(deps.storage, &env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("input_amount", input_amount),
+        attr("output_amount", output_amount),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_pass_through_swap(
+    deps: DepsMut,
+    info: MessageInfo,
+    _env: Env,
+    output_amm_address: String,
+    input_token_enum: TokenSelect,
+    input_token_amount: Uint128,
+    output_min_token: Uint128,
+    expiration: Option<Expiration>,
+    input_received: bool,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &_env.block)?;
+    update_price_accumulators(deps.storage, &_env.block)?;
+
+    let input_token_state = match input_token_enum {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    };
+    let input_token = input_token_state.load(deps.storage)?;
+    check_no_fee_on_transfer(&input_token)?;
+    let transfer_token_state = match input_token_enum {
+        TokenSelect::Token1 => TOKEN2,
+        TokenSelect::Token2 => TOKEN1,
+    };
+    let transfer_token = transfer_token_state.load(deps.storage)?;
+
+    validate_input_amount(&info.funds, input_token_amount, &input_token.denom)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &_env.block, &fees)?;
+    let amount_to_transfer = get_swap_output(
+        deps.storage,
+        &_env.block,
+        &input_token_enum,
+        input_token_amount,
+        input_token.reserve,
+        transfer_token.reserve,
+        total_fee_percent,
+    )?;
+
+    // Calculate fees
+    let protocol_fee_amount =
+        get_protocol_fee_amount(input_token_amount, fees.transferred_protocol_fee_percent())?;
+    let input_amount_minus_protocol_fee = input_token_amount - protocol_fee_amount;
+
+    // Transfer input amount - protocol fee to contract
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    if let Denom::Cw20(addr) = &input_token.denom {
+        if !input_received {
+            msgs.push(get_cw20_transfer_from_msg(
+                &info.sender,
+                &_env.contract.address,
+                addr,
+                input_amount_minus_protocol_fee,
+            )?)
+        }
+    };
+
+    // Send protocol fee to protocol fee recipient
+    if !protocol_fee_amount.is_zero() {
+        msgs.push(get_fee_transfer_msg(
+            &info.sender,
+            &fees.protocol_fee_recipient,
+            &input_token.denom,
+            protocol_fee_amount,
+            input_received,
+        )?)
+    }
+
+    let output_amm_address = deps.api.addr_validate(&output_amm_address)?;
+
+    // Increase allowance of output contract is transfer token is cw20
+    if let Denom::Cw20(addr) = &transfer_token.denom {
+        msgs.push(get_cw20_increase_allowance_msg(
+            addr,
+            &output_amm_address,
+            amount_to_transfer,
+            Some(Expiration::AtHeight(_env.block.height + 1)),
+        )?)
+    };
+
+    let resp: InfoResponse = deps
+        .querier
+        .query_wasm_smart(&output_amm_address, &QueryMsg::Info {})?;
+
+    let transfer_input_token_enum = if transfer_token.denom == resp.token1_denom {
+        Ok(TokenSelect::Token1)
+    } else if transfer_token.denom == resp.token2_denom {
+        Ok(TokenSelect::Token2)
+    } else {
+        Err(ContractError::InvalidOutputPool {})
+    }?;
+
+    let swap_msg = ExecuteMsg::SwapAndSendTo {
+        input_token: transfer_input_token_enum,
+        input_amount: amount_to_transfer,
+        recipient: info.sender.to_string(),
+        min_token: output_min_token,
+        expiration,
+    };
+
+    msgs.push(
+        WasmMsg::Execute {
+            contract_addr: output_amm_address.into(),
+            msg: to_binary(&swap_msg)?,
+            funds: match transfer_token.denom {
+                Denom::Cw20(_) => vec![],
+                Denom::Native(denom) => vec![Coin {
+                    denom,
+                    amount: amount_to_transfer,
+                }],
+            },
+        }
+        .into(),
+    );
+
+    input_token_state.update(deps.storage, |mut token| -> Result<_, ContractError> {
+        // Add input amount - protocol fee to input token reserve
+        token.reserve = token
+            .reserve
+            .checked_add(input_amount_minus_protocol_fee)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+
+    transfer_token_state.update(deps.storage, |mut token| -> Result<_, ContractError> {
+        token.reserve = token
+            .reserve
+            .checked_sub(amount_to_transfer)
+            .map_err(StdError::overflow)?;
+        Ok(token)
+    })?;
+
+    update_volatility(deps.storage, &_env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("input_token_amount", input_token_amount),
+        attr("native_transferred", amount_to_transfer),
+    ]))
+}
+
+pub fn execute_flash_swap(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    recipient: String,
+    msg: Binary,
+) -> Result<Response, ContractError> {
+    if POOL_TYPE.load(deps.storage)? != PoolType::ConstantProduct {
+        return Err(ContractError::FlashSwapUnsupported {});
+    }
+    if token1_amount.is_zero() && token2_amount.is_zero() {
+        return Err(ContractError::EmptyFlashSwap {});
+    }
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let token1_balance = get_pool_balance(deps.as_ref(), &env, &TOKEN1.load(deps.storage)?.denom)?;
+    let token2_balance = get_pool_balance(deps.as_ref(), &env, &TOKEN2.load(deps.storage)?.denom)?;
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for (token, amount) in [
+        (TOKEN1.load(deps.storage)?, token1_amount),
+        (TOKEN2.load(deps.storage)?, token2_amount),
+    ] {
+        if amount.is_zero() {
+            continue;
+        }
+        if amount >= token.reserve {
+            return Err(ContractError::InsufficientLiquidityError {
+                requested: amount,
+                available: token.reserve,
+            });
+        }
+        msgs.push(match token.denom {
+            Denom::Cw20(addr) => get_cw20_transfer_to_msg(&recipient, &addr, amount)?,
+            Denom::Native(denom) => get_bank_transfer_to_msg(&recipient, &denom, amount),
+        });
+    }
+
+    PENDING_FLASH_SWAP.save(
+        deps.storage,
+        &FlashSwap {
+            token1_amount,
+            token2_amount,
+            token1_balance,
+            token2_balance,
+        },
+    )?;
+
+    let callback_msg = FlashSwapCallbackMsg {
+        sender: info.sender.into_string(),
+        token1_amount,
+        token2_amount,
+        msg,
+    }
+    .into_cosmos_msg(&recipient)?;
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_submessage(SubMsg::reply_on_success(callback_msg, FLASH_SWAP_REPLY_ID))
+        .add_attributes(vec![
+            attr("action", "flash_swap"),
+            attr("token1_amount", token1_amount),
+            attr("token2_amount", token2_amount),
+        ]))
+}
+
+fn get_pool_balance(deps: Deps, env: &Env, denom: &Denom) -> StdResult<Uint128> {
+    match denom {
+        Denom::Cw20(addr) => get_token_balance(deps, addr, &env.contract.address),
+        Denom::Native(denom) => Ok(deps
+            .querier
+            .query_balance(&env.contract.address, denom)?
+            .amount),
+    }
+}
+
+/// Reserve, escrowed tokens and pool balance of a pool token
+fn get_token_drift(deps: Deps, env: &Env, token: &Token) -> StdResult<TokenDrift> {
+    let escrowed = ESCROWED_BALANCES
+        .may_load(deps.storage, &denom_key(&token.denom))?
+        .unwrap_or_default();
+    let balance = get_pool_balance(deps, env, &token.denom)?;
+    let accounted = token.reserve + escrowed;
+    Ok(TokenDrift {
+        reserve: token.reserve,
+        escrowed,
+        balance,
+        excess: balance.saturating_sub(accounted),
+        deficit: accounted.saturating_sub(balance),
+    })
+}
+
+pub fn execute_sync(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let mut attrs = vec![attr("action", "sync")];
+    for (token_item, name) in [(TOKEN1, "token1_reserve"), (TOKEN2, "token2_reserve")] {
+        let mut token = token_item.load(deps.storage)?;
+        let drift = get_token_drift(deps.as_ref(), &env, &token)?;
+        token.reserve = drift.balance.saturating_sub(drift.escrowed);
+        token_item.save(deps.storage, &token)?;
+        attrs.push(attr(name, token.reserve));
+    }
+    update_volatility(deps.storage, &env.block)?;
+
+    Ok(Response::new().add_attributes(attrs))
+}
+
+pub fn execute_skim(deps: DepsMut, env: Env, recipient: String) -> Result<Response, ContractError> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+
+    let mut msgs = vec![];
+    let mut attrs = vec![attr("action", "skim"), attr("recipient", &recipient)];
+    for (token_item, name) in [(TOKEN1, "token1_amount"), (TOKEN2, "token2_amount")] {
+        let token = token_item.load(deps.storage)?;
+        let drift = get_token_drift(deps.as_ref(), &env, &token)?;
+        if !drift.excess.is_zero() {
+            msgs.push(get_transfer_to_msg(&recipient, &token.denom, drift.excess)?);
+        }
+        attrs.push(attr(name, drift.excess));
+    }
+
+    Ok(Response::new().add_messages(msgs).add_attributes(attrs))
+}
+
+/// Assets of the pool, `TOKEN1` and `TOKEN2` unless it is a multi asset pool
+fn load_assets(storage: &dyn Storage) -> StdResult<Vec<Token>> {
+    if POOL_TYPE.load(storage)? != PoolType::MultiAsset {
+        return Ok(vec![TOKEN1.load(storage)?, TOKEN2.load(storage)?]);
+    }
+    ASSETS
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect()
+}
+
+fn save_assets(storage: &mut dyn Storage, assets: &[Token]) -> StdResult<()> {
+    if POOL_TYPE.load(storage)? != PoolType::MultiAsset {
+        TOKEN1.save(storage, &assets[0])?;
+        return TOKEN2.save(storage, &assets[1]);
+    }
+    for asset in assets {
+        ASSETS.save(storage, &denom_key(&asset.denom), asset)?;
+    }
+    Ok(())
+}
+
+/// Assets of a pool the asset messages support, which share the constant product invariant
+fn load_pool_assets(storage: &dyn Storage) -> Result<Vec<Token>, ContractError> {
+    match POOL_TYPE.load(storage)? {
+        PoolType::ConstantProduct | PoolType::MultiAsset => Ok(load_assets(storage)?),
+        _ => Err(ContractError::MultiAssetUnsupported {}),
+    }
+}
+
+fn find_asset(assets: &[Token], denom: &Denom) -> Result<usize, ContractError> {
+    assets
+        .iter()
+        .position(|asset| &asset.denom == denom)
+        .ok_or_else(|| ContractError::UnknownAsset {
+            denom: denom_key(denom),
+        })
+}
+
+/// Amount of every asset in the order of `assets`, zero for the ones not listed
+fn get_asset_amounts(
+    assets: &[Token],
+    asset_amounts: &[AssetAmount],
+) -> Result<Vec<Uint128>, ContractError> {
+    let mut amounts = vec![None; assets.len()];
+    for asset_amount in asset_amounts {
+        let index = find_asset(assets, &asset_amount.denom)?;
+        if amounts[index].replace(asset_amount.amount).is_some() {
+            return Err(ContractError::DuplicateAsset {
+                denom: denom_key(&asset_amount.denom),
+            });
+        }
+    }
+    Ok(amounts.into_iter().map(Option::unwrap_or_default).collect())
+}
+
+/// Total fee as a fraction, charged on the imbalanced part of deposits and withdrawals
+fn get_total_fee_fraction(storage: &dyn Storage, block: &BlockInfo) -> StdResult<Decimal256> {
+    let fees = FEES.load(storage)?;
+    let fee_percent = fee_decimal_to_uint128(get_total_fee_percent(storage, block, &fees)?)?;
+    Ok(Decimal256::from_ratio(fee_percent, FEE_SCALE_FACTOR))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_swap_assets(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    input_denom: Denom,
+    output_denom: Denom,
+    input_amount: Uint128,
+    min_output: Uint128,
+    recipient: Option<String>,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    let mut assets = load_pool_assets(deps.storage)?;
+    let multi_asset = POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset;
+
+    let input_index = find_asset(&assets, &input_denom)?;
+    let output_index = find_asset(&assets, &output_denom)?;
+    if input_index == output_index {
+        return Err(ContractError::DuplicateAsset {
+            denom: denom_key(&input_denom),
+        });
+    }
+    check_no_fee_on_transfer(&assets[input_index])?;
+    validate_input_amount(&info.funds, input_amount, &input_denom)?;
+    if !multi_asset {
+        update_price_accumulators(deps.storage, &env.block)?;
+    }
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let output_amount = get_input_price(
+        input_amount,
+        assets[input_index].reserve,
+        assets[output_index].reserve,
+        total_fee_percent,
+    )?;
+    if min_output > output_amount {
+        return Err(ContractError::SwapMinError {
+            min: min_output,
+            available: output_amount,
+        });
+    }
+
+    let protocol_fee_amount =
+        get_protocol_fee_amount(input_amount, fees.transferred_protocol_fee_percent())?;
+    let input_amount_minus_protocol_fee = input_amount - protocol_fee_amount;
+
+    let mut msgs = match &input_denom {
+        Denom::Cw20(addr) => vec![get_cw20_transfer_from_msg(
+            &info.sender,
+            &env.contract.address,
+            addr,
+            input_amount_minus_protocol_fee,
+        )?],
+        Denom::Native(_) => vec![],
+    };
+    if !protocol_fee_amount.is_zero() {
+        msgs.push(get_fee_transfer_msg(
+            &info.sender,
+            &fees.protocol_fee_recipient,
+            &input_denom,
+            protocol_fee_amount,
+            false,
+        )?);
+    }
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender,
+    };
+    msgs.push(get_transfer_to_msg(
+        &recipient,
+        &output_denom,
+        output_amount,
+    )?);
+
+    assets[input_index].reserve = assets[input_index]
+        .reserve
+        .checked_add(input_amount_minus_protocol_fee)
+        .map_err(StdError::overflow)?;
+    assets[output_index].reserve -= output_amount;
+    save_assets(deps.storage, &assets)?;
+    if !multi_asset {
+        update_volatility(deps.storage, &env.block)?;
+    }
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "swap_assets"),
+        attr("input_amount", input_amount),
+        attr("output_amount", output_amount),
+    ]))
+}
+
+pub fn execute_add_assets(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amounts: Vec<AssetAmount>,
+    min_liquidity: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    let mut assets = load_pool_assets(deps.storage)?;
+    let multi_asset = POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset;
+    let amounts = get_asset_amounts(&assets, &amounts)?;
+    for (asset, amount) in assets.iter().zip(&amounts) {
+        if !amount.is_zero() {
+            check_no_fee_on_transfer(asset)?;
+        }
+        validate_input_amount(&info.funds, *amount, &asset.denom)?;
+    }
+    if !multi_asset {
+        update_price_accumulators(deps.storage, &env.block)?;
+    }
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+
+    // The first deposit sets the prices and mints the geometric mean of the amounts, which is
+    // zero unless every asset is deposited
+    let liquidity_amount = if lp_token_supply.is_zero() {
+        let liquidity = geometric_mean(&amounts)?;
+        if liquidity <= MINIMUM_LIQUIDITY {
+            return Err(ContractError::InsufficientInitialLiquidity {
+                minimum: MINIMUM_LIQUIDITY,
+            });
+        }
+        liquidity - MINIMUM_LIQUIDITY
+    } else {
+        let reserves: Vec<Uint128> = assets.iter().map(|asset| asset.reserve).collect();
+        let fee = get_total_fee_fraction(deps.storage, &env.block)?;
+        get_deposit_liquidity(&reserves, &amounts, lp_token_supply, fee)?
+    };
+    if liquidity_amount < min_liquidity {
+        return Err(ContractError::MinLiquidityError {
+            min_liquidity,
+            liquidity_available: liquidity_amount,
+        });
+    }
+
+    let mut msgs = protocol_fee_mint_msg;
+    for (asset, amount) in assets.iter_mut().zip(amounts) {
+        if let Denom::Cw20(addr) = &asset.denom {
+            if !amount.is_zero() {
+                msgs.push(get_cw20_transfer_from_msg(
+                    &info.sender,
+                    &env.contract.address,
+                    addr,
+                    amount,
+                )?);
+            }
+        }
+        asset.reserve = asset
+            .reserve
+            .checked_add(amount)
+            .map_err(StdError::overflow)?;
+    }
+    save_assets(deps.storage, &assets)?;
+    update_k_last(deps.storage)?;
+    if !multi_asset {
+        update_volatility(deps.storage, &env.block)?;
+    }
+
+    if lp_token_supply.is_zero() {
+        msgs.push(mint_lp_tokens(
+            &env.contract.address,
+            MINIMUM_LIQUIDITY,
+            &lp_token_addr,
+        )?);
+    }
+    msgs.push(mint_lp_tokens(
+        &info.sender,
+        liquidity_amount,
+        &lp_token_addr,
+    )?);
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "add_assets"),
+        attr("liquidity_received", liquidity_amount),
+    ]))
+}
+
+pub fn execute_remove_assets(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+    min_amounts: Vec<AssetAmount>,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    let mut assets = load_pool_assets(deps.storage)?;
+    let multi_asset = POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset;
+    let min_amounts = get_asset_amounts(&assets, &min_amounts)?;
+    if !multi_asset {
+        update_price_accumulators(deps.storage, &env.block)?;
+    }
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let balance = get_token_balance(deps.as_ref(), &lp_token_addr, &info.sender)?;
+    if amount > balance {
+        return Err(ContractError::InsufficientLiquidityError {
+            requested: amount,
+            available: balance,
+        });
+    }
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+
+    let mut msgs = protocol_fee_mint_msg;
+    for (asset, min_amount) in assets.iter_mut().zip(min_amounts) {
+        let output_amount = asset.reserve.multiply_ratio(amount, lp_token_supply);
+        if output_amount < min_amount {
+            return Err(ContractError::MinAssetError {
+                denom: denom_key(&asset.denom),
+                min: min_amount,
+                available: output_amount,
+            });
+        }
+        asset.reserve -= output_amount;
+        if !output_amount.is_zero() {
+            msgs.push(get_transfer_to_msg(
+                &info.sender,
+                &asset.denom,
+                output_amount,
+            )?);
+        }
+    }
+    save_assets(deps.storage, &assets)?;
+    update_k_last(deps.storage)?;
+    if !multi_asset {
+        update_volatility(deps.storage, &env.block)?;
+    }
+    msgs.push(get_burn_msg(&lp_token_addr, &info.sender, amount)?);
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "remove_assets"),
+        attr("liquidity_burned", amount),
+    ]))
+}
+
+pub fn execute_remove_assets_imbalanced(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amounts: Vec<AssetAmount>,
+    max_liquidity: Uint128,
+    expiration: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    let mut assets = load_pool_assets(deps.storage)?;
+    let multi_asset = POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset;
+    let amounts = get_asset_amounts(&assets, &amounts)?;
+    // Every reserve keeps a part of the locked liquidity
+    for (asset, amount) in assets.iter().zip(&amounts) {
+        if *amount >= asset.reserve {
+            return Err(ContractError::InsufficientLiquidityError {
+                requested: *amount,
+                available: asset.reserve,
+            });
+        }
+    }
+    if !multi_asset {
+        update_price_accumulators(deps.storage, &env.block)?;
+    }
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps.as_ref(), &lp_token_addr)?;
+    let (protocol_fee_mint_msg, protocol_fee_liquidity) =
+        mint_protocol_fee(deps.storage, &lp_token_addr, lp_token_supply)?;
+    let lp_token_supply = lp_token_supply + protocol_fee_liquidity;
+
+    let reserves: Vec<Uint128> = assets.iter().map(|asset| asset.reserve).collect();
+    let fee = get_total_fee_fraction(deps.storage, &env.block)?;
+    let liquidity_amount = get_withdraw_liquidity(&reserves, &amounts, lp_token_supply, fee)?;
+    if liquidity_amount > max_liquidity {
+        return Err(ContractError::MaxLiquidityError {
+            max_liquidity,
+            liquidity_required: liquidity_amount,
+        });
+    }
+    let balance = get_token_balance(deps.as_ref(), &lp_token_addr, &info.sender)?;
+    if liquidity_amount > balance {
+        return Err(ContractError::InsufficientLiquidityError {
+            requested: liquidity_amount,
+            available: balance,
+        });
+    }
+
+    let mut msgs = protocol_fee_mint_msg;
+    for (asset, amount) in assets.iter_mut().zip(amounts) {
+        asset.reserve -= amount;
+        if !amount.is_zero() {
+            msgs.push(get_transfer_to_msg(&info.sender, &asset.denom, amount)?);
+        }
+    }
+    save_assets(deps.storage, &assets)?;
+    update_k_last(deps.storage)?;
+    if !multi_asset {
+        update_volatility(deps.storage, &env.block)?;
+    }
+    msgs.push(get_burn_msg(
+        &lp_token_addr,
+        &info.sender,
+        liquidity_amount,
+    )?);
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "remove_assets_imbalanced"),
+        attr("liquidity_burned", liquidity_amount),
+    ]))
+}
+
+/// Checks the reserves after a flash swap callback satisfy the constant product invariant with
+/// the fee charged on the repaid amounts, then adds the repaid amounts to the reserves
+fn handle_flash_swap_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let flash_swap = PENDING_FLASH_SWAP.load(deps.storage)?;
+    PENDING_FLASH_SWAP.remove(deps.storage);
+
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let token1_balance = get_pool_balance(deps.as_ref(), &env, &token1.denom)?;
+    let token2_balance = get_pool_balance(deps.as_ref(), &env, &token2.denom)?;
+
+    // Everything above the balances left after sending out the flash swap was paid in. The pool
+    // may hold more than its reserves, e.g. staking rewards, so the reserves are used below.
+    let token1_input = token1_balance.saturating_sub(
+        flash_swap
+            .token1_balance
+            .saturating_sub(flash_swap.token1_amount),
+    );
+    let token2_input = token2_balance.saturating_sub(
+        flash_swap
+            .token2_balance
+            .saturating_sub(flash_swap.token2_amount),
+    );
+    let token1_balance = token1.reserve - flash_swap.token1_amount + token1_input;
+    let token2_balance = token2.reserve - flash_swap.token2_amount + token2_input;
+
+    let fees = FEES.load(deps.storage)?;
+    let fee_percent =
+        fee_decimal_to_uint128(get_total_fee_percent(deps.storage, &env.block, &fees)?)?;
+    let adjusted_balance = |balance: Uint128, input: Uint128| -> StdResult<Uint512> {
+        Uint512::from(balance.full_mul(FEE_SCALE_FACTOR))
+            .checked_sub(Uint512::from(input.full_mul(fee_percent)))
+            .map_err(StdError::overflow)
+    };
+    let new_k = adjusted_balance(token1_balance, token1_input)?
+        .checked_mul(adjusted_balance(token2_balance, token2_input)?)
+        .map_err(StdError::overflow)?;
+    let old_k = Uint512::from(token1.reserve.full_mul(token2.reserve))
+        .checked_mul(Uint512::from(FEE_SCALE_FACTOR.full_mul(FEE_SCALE_FACTOR)))
+        .map_err(StdError::overflow)?;
+    if new_k < old_k {
+        return Err(ContractError::FlashSwapInvariantError {});
+    }
+
+    // Send the protocol fee out of the repaid amounts
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let token1_protocol_fee =
+        get_protocol_fee_amount(token1_input, fees.transferred_protocol_fee_percent())?;
+    let token2_protocol_fee =
+        get_protocol_fee_amount(token2_input, fees.transferred_protocol_fee_percent())?;
+    for (denom, amount) in [
+        (&token1.denom, token1_protocol_fee),
+        (&token2.denom, token2_protocol_fee),
+    ] {
+        if amount.is_zero() {
+            continue;
+        }
+        msgs.push(match denom {
+            Denom::Cw20(addr) => {
+                get_cw20_transfer_to_msg(&fees.protocol_fee_recipient, addr, amount)?
+            }
+            Denom::Native(denom) => {
+                get_bank_transfer_to_msg(&fees.protocol_fee_recipient, denom, amount)
+            }
+        });
+    }
+
+    TOKEN1.update(deps.storage, |mut token| -> StdResult<_> {
+        token.reserve = token1_balance - token1_protocol_fee;
+        Ok(token)
+    })?;
+    TOKEN2.update(deps.storage, |mut token| -> StdResult<_> {
+        token.reserve = token2_balance - token2_protocol_fee;
+        Ok(token)
+    })?;
+
+    update_volatility(deps.storage, &env.block)?;
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("token1_input", token1_input),
+        attr("token2_input", token2_input),
+    ]))
+}
+
+pub fn execute_update_staking_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    unbonding_period: u64,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::ConfigAdmin, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    STAKING_CONFIG.save(deps.storage, &StakingConfig { unbonding_period })?;
+    if TOTAL_BONDED.may_load(deps.storage)?.is_none() {
+        TOTAL_BONDED.save(deps.storage, &Uint128::zero())?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_staking_config"),
+        attr("unbonding_period", unbonding_period.to_string()),
+    ]))
+}
+
+/// Streams every reward schedule up to the current block and returns them
+fn update_reward_schedules(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+) -> StdResult<Vec<RewardSchedule>> {
+    let total_bonded = TOTAL_BONDED.load(storage)?;
+    let mut schedules = load_reward_schedules(storage)?;
+    for schedule in schedules.iter_mut() {
+        accrue_rewards(schedule, total_bonded, block.time.seconds());
+        REWARD_SCHEDULES.save(storage, &denom_key(&schedule.denom), schedule)?;
+    }
+    Ok(schedules)
+}
+
+fn load_reward_schedules(storage: &dyn Storage) -> StdResult<Vec<RewardSchedule>> {
+    REWARD_SCHEDULES
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, schedule)| schedule))
+        .collect()
+}
+
+/// Moves the rewards `staker` earned with its bonded tokens into its pending rewards. Must run
+/// before the bonded amount of the staker changes.
+fn update_staker_rewards(
+    storage: &mut dyn Storage,
+    staker: &Addr,
+    schedules: &[RewardSchedule],
+) -> StdResult<()> {
+    let bonded = BONDED.may_load(storage, staker)?.unwrap_or_default();
+    for schedule in schedules {
+        let key = denom_key(&schedule.denom);
+        let mut reward = STAKER_REWARDS
+            .may_load(storage, (staker, &key))?
+            .unwrap_or_default();
+        accrue_staker_rewards(&mut reward, bonded, schedule.reward_per_share)?;
+        STAKER_REWARDS.save(storage, (staker, &key), &reward)?;
+    }
+    Ok(())
+}
+
+pub fn execute_fund_rewards(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    denom: Denom,
+    amount: Uint128,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &Role::ConfigAdmin, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    if STAKING_CONFIG.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::StakingDisabled {});
+    }
+    if amount.is_zero() || duration == 0 {
+        return Err(ContractError::InvalidRewardSchedule {});
+    }
+    for token in load_assets(deps.storage)? {
+        if token.denom == denom {
+            check_no_fee_on_transfer(&token)?;
+        }
+    }
+    validate_input_amount(&info.funds, amount, &denom)?;
+
+    let now = env.block.time.seconds();
+    let schedules = update_reward_schedules(deps.storage, &env.block)?;
+    let key = denom_key(&denom);
+    let mut schedule = match REWARD_SCHEDULES.may_load(deps.storage, &key)? {
+        Some(schedule) => schedule,
+        None if schedules.len() >= MAX_REWARD_DENOMS as usize => {
+            return Err(ContractError::TooManyRewardDenoms {
+                max: MAX_REWARD_DENOMS,
+            })
+        }
+        None => RewardSchedule {
+            denom: denom.clone(),
+            reward_rate: Decimal256::zero(),
+            end_time: now,
+            last_update_time: now,
+            reward_per_share: Decimal256::zero(),
+        },
+    };
+    fund_rewards(&mut schedule, amount, duration, now);
+    REWARD_SCHEDULES.save(deps.storage, &key, &schedule)?;
+    add_escrowed_balance(deps.storage, &denom, amount)?;
+
+    let mut msgs = vec![];
+    if let Denom::Cw20(addr) = &denom {
+        msgs.push(get_cw20_transfer_from_msg(
+            &info.sender,
+            &env.contract.address,
+            addr,
+            amount,
+        )?);
+    }
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "fund_rewards"),
+        attr("amount", amount),
+        attr("reward_rate", schedule.reward_rate.to_string()),
+        attr("end_time", schedule.end_time.to_string()),
+    ]))
+}
+
+pub fn execute_bond(
+    deps: DepsMut,
+    env: Env,
+    staker: Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if STAKING_CONFIG.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::StakingDisabled {});
+    }
+
+    let schedules = update_reward_schedules(deps.storage, &env.block)?;
+    update_staker_rewards(deps.storage, &staker, &schedules)?;
+
+    BONDED.update(deps.storage, &staker, |bonded| -> StdResult<_> {
+        Ok(bonded.unwrap_or_default() + amount)
+    })?;
+    TOTAL_BONDED.update(deps.storage, |total| -> StdResult<_> { Ok(total + amount) })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "bond"),
+        attr("staker", staker),
+        attr("amount", amount),
+    ]))
+}
+
+pub fn execute_unbond(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = STAKING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::StakingDisabled {})?;
+    let bonded = BONDED
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    if amount.is_zero() || amount > bonded {
+        return Err(ContractError::InsufficientBonded {
+            requested: amount,
+            bonded,
+        });
+    }
+
+    let schedules = update_reward_schedules(deps.storage, &env.block)?;
+    update_staker_rewards(deps.storage, &info.sender, &schedules)?;
+
+    BONDED.save(deps.storage, &info.sender, &(bonded - amount))?;
+    TOTAL_BONDED.update(deps.storage, |total| -> StdResult<_> { Ok(total - amount) })?;
+
+    let release_time = env.block.time.seconds() + config.unbonding_period;
+    UNBONDINGS.update(deps.storage, &info.sender, |unbondings| -> StdResult<_> {
+        let mut unbondings = unbondings.unwrap_or_default();
+        unbondings.push(Unbonding {
+            amount,
+            release_time,
+        });
+        Ok(unbondings)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "unbond"),
+        attr("amount", amount),
+        attr("release_time", release_time.to_string()),
+    ]))
+}
+
+pub fn execute_withdraw_unbonded(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let now = env.block.time.seconds();
+    let (released, unbondings): (Vec<_>, Vec<_>) = UNBONDINGS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default()
+        .into_iter()
+        .partition(|unbonding| unbonding.release_time <= now);
+    let amount: Uint128 = released.iter().map(|unbonding| unbonding.amount).sum();
+    if amount.is_zero() {
+        return Err(ContractError::NoUnbondedTokens {});
+    }
+
+    if unbondings.is_empty() {
+        UNBONDINGS.remove(deps.storage, &info.sender);
+    } else {
+        UNBONDINGS.save(deps.storage, &info.sender, &unbondings)?;
+    }
+
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let transfer_msg = get_cw20_transfer_to_msg(&info.sender, &lp_token_addr, amount)?;
+
+    Ok(Response::new()
+        .add_message(transfer_msg)
+        .add_attributes(vec![
+            attr("action", "withdraw_unbonded"),
+            attr("amount", amount),
+        ]))
+}
+
+pub fn execute_claim_rewards(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    if STAKING_CONFIG.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::StakingDisabled {});
+    }
+
+    let schedules = update_reward_schedules(deps.storage, &env.block)?;
+    update_staker_rewards(deps.storage, &info.sender, &schedules)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for schedule in schedules {
+        let key = denom_key(&schedule.denom);
+        let mut reward = STAKER_REWARDS.load(deps.storage, (&info.sender, &key))?;
+        if reward.pending.is_zero() {
+            continue;
+        }
+        msgs.push(match &schedule.denom {
+            Denom::Cw20(addr) => get_cw20_transfer_to_msg(&info.sender, addr, reward.pending)?,
+            Denom::Native(denom) => get_bank_transfer_to_msg(&info.sender, denom, reward.pending),
+        });
+        sub_escrowed_balance(deps.storage, &schedule.denom, reward.pending)?;
+        reward.pending = Uint128::zero();
+        STAKER_REWARDS.save(deps.storage, (&info.sender, &key), &reward)?;
+    }
+    if msgs.is_empty() {
+        return Err(ContractError::NoRewards {});
+    }
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "claim_rewards"))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_place_order(
+    deps: DepsMut,
+    info: &MessageInfo,
+    env: Env,
+    input_token: TokenSelect,
+    input_amount: Uint128,
+    min_output: Uint128,
+    expiration: Option<Expiration>,
+    input_received: bool,
+) -> Result<Response, ContractError> {
+    check_expiration(&expiration, &env.block)?;
+    if input_amount.is_zero() || min_output.is_zero() {
+        return Err(ContractError::InvalidOrder {});
+    }
+
+    let pool_token = match input_token {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    }
+    .load(deps.storage)?;
+    check_no_fee_on_transfer(&pool_token)?;
+    let input_denom = pool_token.denom;
+    validate_input_amount(&info.funds, input_amount, &input_denom)?;
+
+    let id = ORDER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ORDER_COUNT.save(deps.storage, &id)?;
+    let order = LimitOrder {
+        id,
+        owner: info.sender.clone(),
+        input_token,
+        input_amount,
+        min_output,
+        price: Decimal256::from_ratio(min_output, input_amount),
+        expiration,
+    };
+    save_order(deps.storage, &order)?;
+
+    let msgs = match input_denom {
+        Denom::Cw20(addr) if !input_received => vec![get_cw20_transfer_from_msg(
+            &info.sender,
+            &env.contract.address,
+            &addr,
+            input_amount,
+        )?],
+        _ => vec![],
+    };
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "place_order"),
+        attr("order_id", id.to_string()),
+        attr("price", order.price.to_string()),
+    ]))
+}
+
+fn save_order(storage: &mut dyn Storage, order: &LimitOrder) -> StdResult<()> {
+    let input_denom = order_input_denom(storage, order)?;
+    add_escrowed_balance(storage, &input_denom, order.input_amount)?;
+    ORDERS.save(storage, order.id, order)?;
+    OWNER_ORDERS.save(storage, (&order.owner, order.id), order)?;
+    PRICE_ORDERS.save(
+        storage,
+        (order_side_key(&order.input_token), &order.price_key()),
+        order,
+    )
+}
+
+fn remove_order(storage: &mut dyn Storage, order: &LimitOrder) -> StdResult<()> {
+    let input_denom = order_input_denom(storage, order)?;
+    sub_escrowed_balance(storage, &input_denom, order.input_amount)?;
+    ORDERS.remove(storage, order.id);
+    OWNER_ORDERS.remove(storage, (&order.owner, order.id));
+    PRICE_ORDERS.remove(
+        storage,
+        (order_side_key(&order.input_token), &order.price_key()),
+    );
+    Ok(())
+}
+
+fn order_input_denom(storage: &dyn Storage, order: &LimitOrder) -> StdResult<Denom> {
+    Ok(match order.input_token {
+        TokenSelect::Token1 => TOKEN1,
+        TokenSelect::Token2 => TOKEN2,
+    }
+    .load(storage)?
+    .denom)
+}
+
+fn add_escrowed_balance(
+    storage: &mut dyn Storage,
+    denom: &Denom,
+    amount: Uint128,
+) -> StdResult<()> {
+    ESCROWED_BALANCES.update(storage, &denom_key(denom), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_add(amount)?)
+    })?;
+    Ok(())
+}
+
+fn sub_escrowed_balance(
+    storage: &mut dyn Storage,
+    denom: &Denom,
+    amount: Uint128,
+) -> StdResult<()> {
+    ESCROWED_BALANCES.update(storage, &denom_key(denom), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    Ok(())
+}
+
+pub fn execute_cancel_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    let order = ORDERS.load(deps.storage, order_id)?;
+    if order.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_order(deps.storage, &order)?;
+
+    let input_denom = order_input_denom(deps.storage, &order)?;
+    let refund_msg = get_transfer_to_msg(&order.owner, &input_denom, order.input_amount)?;
+
+    Ok(Response::new().add_message(refund_msg).add_attributes(vec![
+        attr("action", "cancel_order"),
+        attr("order_id", order_id.to_string()),
+    ]))
+}
+
+pub fn execute_fill_orders(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    update_price_accumulators(deps.storage, &env.block)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let tip_percent = Decimal::from_str(ORDER_EXECUTOR_TIP_PERCENT)?;
+    let mut msgs = vec![];
+    let mut filled = 0u32;
+    let mut expired = 0u32;
+    for input_token_enum in [TokenSelect::Token1, TokenSelect::Token2] {
+        let (input_token_item, output_token_item) = match input_token_enum {
+            TokenSelect::Token1 => (TOKEN1, TOKEN2),
+            TokenSelect::Token2 => (TOKEN2, TOKEN1),
+        };
+        let orders = PRICE_ORDERS
+            .prefix(order_side_key(&input_token_enum))
+            .range(deps.storage, None, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, order)| order))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        let mut tip = Uint128::zero();
+        for order in orders {
+            let mut input_token = input_token_item.load(deps.storage)?;
+            let mut output_token = output_token_item.load(deps.storage)?;
+            if let Some(expiration) = &order.expiration {
+                if expiration.is_expired(&env.block) {
+                    remove_order(deps.storage, &order)?;
+                    msgs.push(get_transfer_to_msg(
+                        &order.owner,
+                        &input_token.denom,
+                        order.input_amount,
+                    )?);
+                    expired += 1;
+                    continue;
+                }
+            }
+
+            let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+            // Swaps the curve rejects, e.g. over the weighted in ratio, can not be filled yet
+            let output_amount = get_swap_output(
+                deps.storage,
+                &env.block,
+                &input_token_enum,
+                order.input_amount,
+                input_token.reserve,
+                output_token.reserve,
+                total_fee_percent,
+            )
+            .unwrap_or_default();
+            let order_tip = get_protocol_fee_amount(output_amount, tip_percent)?;
+            let owner_amount = output_amount - order_tip;
+            if owner_amount < order.min_output {
+                continue;
+            }
+
+            let protocol_fee_amount = get_protocol_fee_amount(
+                order.input_amount,
+                fees.transferred_protocol_fee_percent(),
+            )?;
+            input_token.reserve = input_token
+                .reserve
+                .checked_add(order.input_amount - protocol_fee_amount)
+                .map_err(StdError::overflow)?;
+            output_token.reserve = output_token
+                .reserve
+                .checked_sub(output_amount)
+                .map_err(StdError::overflow)?;
+            input_token_item.save(deps.storage, &input_token)?;
+            output_token_item.save(deps.storage, &output_token)?;
+            update_volatility(deps.storage, &env.block)?;
+            remove_order(deps.storage, &order)?;
+
+            if !protocol_fee_amount.is_zero() {
+                msgs.push(get_transfer_to_msg(
+                    &fees.protocol_fee_recipient,
+                    &input_token.denom,
+                    protocol_fee_amount,
+                )?);
+            }
+            msgs.push(get_transfer_to_msg(
+                &order.owner,
+                &output_token.denom,
+                owner_amount,
+            )?);
+            tip += order_tip;
+            filled += 1;
+        }
+
+        if !tip.is_zero() {
+            let output_denom = output_token_item.load(deps.storage)?.denom;
+            msgs.push(get_transfer_to_msg(&info.sender, &output_denom, tip)?);
+        }
+    }
+
+    if filled == 0 && expired == 0 {
+        return Err(ContractError::NoOrdersExecuted {});
+    }
+
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "execute_orders"),
+        attr("filled", filled.to_string()),
+        attr("expired", expired.to_string()),
+    ]))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    if is_two_token_query(&msg) && POOL_TYPE.load(deps.storage)? == PoolType::MultiAsset {
+        return Err(StdError::generic_err(
+            "This query requires a two token pool",
+        ));
+    }
+
+    match msg {
+        QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
+        QueryMsg::Info {} => to_binary(&query_info(deps)?),
+        QueryMsg::Token1ForToken2Price { token1_amount } => {
+            to_binary(&query_token1_for_token2_price(deps, _env, token1_amount)?)
+        }
+        QueryMsg::Token2ForToken1Price { token2_amount } => {
+            to_binary(&query_token2_for_token1_price(deps, _env, token2_amount)?)
+        }
+        QueryMsg::Token1ForExactToken2Price { token2_amount } => to_binary(
+            &query_token1_for_exact_token2_price(deps, _env, token2_amount)?,
+        ),
+        QueryMsg::Token2ForExactToken1Price { token1_amount } => to_binary(
+            &query_token2_for_exact_token1_price(deps, _env, token1_amount)?,
+        ),
+        QueryMsg::SimulateSwap {
+            input_token,
+            input_amount,
+        } => to_binary(&query_simulate_swap(deps, _env, input_token, input_amount)?),
+        QueryMsg::ReverseSimulateSwap {
+            output_token,
+            output_amount,
+        } => to_binary(&query_reverse_simulate_swap(
+            deps,
+            _env,
+            output_token,
+            output_amount,
+        )?),
+        QueryMsg::ZapInLiquidity {
+            input_token,
+            amount,
+        } => to_binary(&query_zap_in_liquidity(deps, _env, input_token, amount)?),
+        QueryMsg::ZapOutOutput {
+            amount,
+            output_token,
+        } => to_binary(&query_zap_out_output(deps, _env, amount, output_token)?),
+        QueryMsg::Fee {} => to_binary(&query_fee(deps)?),
+        QueryMsg::PendingConfig {} => to_binary(&PendingConfigResponse {
+            config_timelock: CONFIG_TIMELOCK.may_load(deps.storage)?.unwrap_or_default(),
+            pending_config: PENDING_CONFIG.may_load(deps.storage)?,
+        }),
+        QueryMsg::PauseFlags {} => to_binary(&load_pause_flags(deps.storage, &_env.block)?),
+        QueryMsg::FeeOnTransfer {} => to_binary(&FeeOnTransferResponse {
+            token1: TOKEN1.load(deps.storage)?.fee_on_transfer,
+            token2: TOKEN2.load(deps.storage)?.fee_on_transfer,
+        }),
+        QueryMsg::Guardian {} => {
+            let pause = GUARDIAN_PAUSE.may_load(deps.storage)?;
+            let now = _env.block.time.seconds();
+            to_binary(&GuardianResponse {
+                guardian: GUARDIAN.may_load(deps.storage)?,
+                pause_used: matches!(&pause, Some(pause) if !pause.is_active(now)),
+                pause: pause.filter(|pause| pause.is_active(now)),
+            })
+        }
+        QueryMsg::OwnershipProposal {} => to_binary(&OwnershipProposalResponse {
+            proposal: OWNERSHIP_PROPOSAL.may_load(deps.storage)?,
+        }),
+        QueryMsg::Roles {
+            role,
+            start_after,
+            limit,
+        } => to_binary(&query_roles(deps, role, start_after, limit)?),
+        QueryMsg::CurrentFee {} => to_binary(&query_current_fee(deps, _env)?),
+        QueryMsg::DynamicFee {} => to_binary(&query_dynamic_fee(deps, _env)?),
+        QueryMsg::Twap {
+            start_time,
+            end_time,
+        } => to_binary(&query_twap(deps, _env, start_time, end_time)?),
+        QueryMsg::Amp {} => to_binary(&query_amp(deps, _env)?),
+        QueryMsg::Weights {} => to_binary(&query_weights(deps)?),
+        QueryMsg::StakingConfig {} => to_binary(&query_staking_config(deps)?),
+        QueryMsg::Staker { address } => to_binary(&query_staker(deps, address)?),
+        QueryMsg::PendingRewards { address } => {
+            to_binary(&query_pending_rewards(deps, _env, address)?)
+        }
+        QueryMsg::RewardSchedules {} => to_binary(&query_reward_schedules(deps, _env)?),
+        QueryMsg::Order { order_id } => to_binary(&ORDERS.load(deps.storage, order_id)?),
+        QueryMsg::OrdersByOwner {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by_owner(deps, owner, start_after, limit)?),
+        QueryMsg::OrdersByPrice {
+            input_token,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by_price(
+            deps,
+            input_token,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ReserveDrift {} => to_binary(&ReserveDriftResponse {
+            token1: get_token_drift(deps, &_env, &TOKEN1.load(deps.storage)?)?,
+            token2: get_token_drift(deps, &_env, &TOKEN2.load(deps.storage)?)?,
+        }),
+        QueryMsg::Assets {} => to_binary(&query_assets(deps)?),
+        QueryMsg::AssetPrices {
+            input_denom,
+            input_amount,
+        } => to_binary(&query_asset_prices(deps, _env, input_denom, input_amount)?),
+    }
+}
+
+// Queries that load `TOKEN1` and `TOKEN2`
+fn is_two_token_query(msg: &QueryMsg) -> bool {
+    matches!(
+        msg,
+        QueryMsg::Info {}
+            | QueryMsg::Token1ForToken2Price { .. }
+            | QueryMsg::Token2ForToken1Price { .. }
+            | QueryMsg::Token1ForExactToken2Price { .. }
+            | QueryMsg::Token2ForExactToken1Price { .. }
+            | QueryMsg::SimulateSwap { .. }
+            | QueryMsg::ReverseSimulateSwap { .. }
+            | QueryMsg::ZapInLiquidity { .. }
+            | QueryMsg::ZapOutOutput { .. }
+            | QueryMsg::FeeOnTransfer {}
+            | QueryMsg::Twap { .. }
+            | QueryMsg::ReserveDrift {}
+    )
+}
+
+pub fn query_assets(deps: Deps) -> StdResult<AssetsResponse> {
+    let lp_token_address = LP_TOKEN.load(deps.storage)?;
+    Ok(AssetsResponse {
+        assets: load_assets(deps.storage)?,
+        lp_token_supply: get_lp_token_supply(deps, &lp_token_address)?,
+        lp_token_address: lp_token_address.into_string(),
+    })
+}
+
+pub fn query_asset_prices(
+    deps: Deps,
+    env: Env,
+    input_denom: Denom,
+    input_amount: Uint128,
+) -> StdResult<AssetPricesResponse> {
+    let to_std_error = |err: ContractError| StdError::generic_err(err.to_string());
+    let assets = load_pool_assets(deps.storage).map_err(to_std_error)?;
+    let input_index = find_asset(&assets, &input_denom).map_err(to_std_error)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let prices = assets
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != input_index)
+        .map(|(_, asset)| {
+            Ok(AssetAmount {
+                denom: asset.denom.clone(),
+                amount: get_input_price(
+                    input_amount,
+                    assets[input_index].reserve,
+                    asset.reserve,
+                    total_fee_percent,
+                )?,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(AssetPricesResponse { prices })
+}
+
+pub fn query_info(deps: Deps) -> StdResult<InfoResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let lp_token_address = LP_TOKEN.load(deps.storage)?;
+
+    // TODO get total supply
+    Ok(InfoResponse {
+        token1_reserve: token1.reserve,
+        token1_denom: token1.denom,
+        token2_reserve: token2.reserve,
+        token2_denom: token2.denom,
+        lp_token_supply: get_lp_token_supply(deps, &lp_token_address)?,
+        lp_token_address: lp_token_address.into_string(),
+    })
+}
+
+pub fn query_token1_for_token2_price(
+    deps: Deps,
+    env: Env,
+    token1_amount: Uint128,
+) -> StdResult<Token1ForToken2PriceResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let token2_amount = get_swap_output(
+        deps.storage,
+        &env.block,
+        &TokenSelect::Token1,
+        token1_amount,
+        token1.reserve,
+        token2.reserve,
+        total_fee_percent,
+    )?;
+    Ok(Token1ForToken2PriceResponse { token2_amount })
+}
+
+pub fn query_token2_for_token1_price(
+    deps: Deps,
+    env: Env,
+    token2_amount: Uint128,
+) -> StdResult<Token2ForToken1PriceResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let token1_amount = get_swap_output(
+        deps.storage,
+        &env.block,
+        &TokenSelect::Token2,
+        token2_amount,
+        token2.reserve,
+        token1.reserve,
+        total_fee_percent,
+    )?;
+    Ok(Token2ForToken1PriceResponse { token1_amount })
+}
+
+pub fn query_token1_for_exact_token2_price(
+    deps: Deps,
+    env: Env,
+    token2_amount: Uint128,
+) -> StdResult<Token1ForExactToken2PriceResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let token1_amount = get_swap_input(
+        deps.storage,
+        &env.block,
+        &TokenSelect::Token1,
+        token2_amount,
+        token1.reserve,
+        token2.reserve,
+        total_fee_percent,
+    )?;
+    Ok(Token1ForExactToken2PriceResponse { token1_amount })
+}
+
+pub fn query_token2_for_exact_token1_price(
+    deps: Deps,
+    env: Env,
+    token1_amount: Uint128,
+) -> StdResult<Token2ForExactToken1PriceResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+
+    let fees = FEES.load(deps.storage)?;
+    let total_fee_percent = get_total_fee_percent(deps.storage, &env.block, &fees)?;
+    let token2_amount = get_swap_input(
+        deps.storage,
+        &env.block,
+        &TokenSelect::Token2,
+        token1_amount,
+        token2.reserve,
+        token1.reserve,
+        total_fee_percent,
+    )?;
+    Ok(Token2ForExactToken1PriceResponse { token2_amount })
+}
+
+pub fn query_simulate_swap(
+    deps: Deps,
+    env: Env,
+    input_token: TokenSelect,
+    input_amount: Uint128,
+) -> StdResult<SimulateSwapResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let (input_reserve, output_reserve) = match input_token {
+        TokenSelect::Token1 => (token1.reserve, token2.reserve),
+        TokenSelect::Token2 => (token2.reserve, token1.reserve),
+    };
+    let spot_price_before = get_spot_price(
+        deps.storage,
+        env.block.time.seconds(),
+        &input_token,
+        token1.reserve,
+        token2.reserve,
+    )?;
+
+    let fees = FEES.load(deps.storage)?;
+    let lp_fee_percent = get_lp_fee_percent(deps.storage, &env.block, &fees)?;
+    let output_amount = get_swap_output(
+        deps.storage,
+        &env.block,
+        &input_token,
+        input_amount,
+        input_reserve,
+        output_reserve,
+        lp_fee_percent + fees.protocol_fee_percent,
+    )?;
+    let lp_fee_amount = get_protocol_fee_amount(input_amount, lp_fee_percent)?;
+    let protocol_fee_amount = get_protocol_fee_amount(input_amount, fees.protocol_fee_percent)?;
+
+    // The transferred protocol fee leaves the pool, the rest of the input is added to the reserve
+    let input_reserve_after = input_reserve + input_amount
+        - get_protocol_fee_amount(input_amount, fees.transferred_protocol_fee_percent())?;
+    let output_reserve_after = output_reserve - output_amount;
+    let spot_price_after = match input_token {
+        TokenSelect::Token1 => get_spot_price(
+            deps.storage,
+            env.block.time.seconds(),
+            &input_token,
+            input_reserve_after,
+            output_reserve_after,
+        )?,
+        TokenSelect::Token2 => get_spot_price(
+            deps.storage,
+            env.block.time.seconds(),
+            &input_token,
+            output_reserve_after,
+            input_reserve_after,
+        )?,
+    };
+
+    let execution_price = if input_amount.is_zero() {
+        spot_price_before
+    } else {
+        Decimal256::from_ratio(output_amount, input_amount)
+    };
+    let input_after_fees = input_amount.saturating_sub(lp_fee_amount + protocol_fee_amount);
+    let expected_output: Uint128 =
+        (Uint256::from(input_after_fees) * spot_price_before).try_into()?;
+    let price_impact = if expected_output.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(
+            expected_output.saturating_sub(output_amount),
+            expected_output,
+        )
+    };
+
+    Ok(SimulateSwapResponse {
+        output_amount,
+        lp_fee_amount,
+        protocol_fee_amount,
+        spot_price_before,
+        spot_price_after,
+        execution_price,
+        price_impact,
+    })
+}
+
+pub fn query_reverse_simulate_swap(
+    deps: Deps,
+    env: Env,
+    output_token: TokenSelect,
+    output_amount: Uint128,
+) -> StdResult<ReverseSimulateSwapResponse> {
+    let token1 = TOKEN1.load(deps.storage)?;
+    let token2 = TOKEN2.load(deps.storage)?;
+    let (input_token, input_reserve, output_reserve) = match output_token {
+        TokenSelect::Token1 => (TokenSelect::Token2, token2.reserve, token1.reserve),
+        TokenSelect::Token2 => (TokenSelect::Token1, token1.reserve, token2.reserve),
+    };
+
+    let fees = FEES.load(deps.storage)?;
+    let lp_fee_percent = get_lp_fee_percent(deps.storage, &env.block, &fees)?;
+    let input_amount = get_swap_input(
+        deps.storage,
+        &env.block,
+        &input_token,
+        output_amount,
+        input_reserve,
+        output_reserve,
+        lp_fee_percent + fees.protocol_fee_percent,
+    )?;
+
+    Ok(ReverseSimulateSwapResponse {
+        input_amount,
+        lp_fee_amount: get_protocol_fee_amount(input_amount, lp_fee_percent)?,
+        protocol_fee_amount: get_protocol_fee_amount(input_amount, fees.protocol_fee_percent)?,
+    })
+}
+
+pub fn query_zap_in_liquidity(
+    deps: Deps,
+    env: Env,
+    input_token: TokenSelect,
+    amount: Uint128,
+) -> StdResult<ZapInLiquidityResponse> {
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps, &lp_token_addr)?;
+    let lp_token_supply =
+        lp_token_supply + get_protocol_fee_liquidity(deps.storage, lp_token_supply)?;
+    let zap = get_zap_in(
+        deps.storage,
+        &env.block,
+        &input_token,
+        amount,
+        lp_token_supply,
+    )?;
+    Ok(ZapInLiquidityResponse {
+        liquidity: zap.liquidity_amount,
+        swap_amount: zap.swap_amount,
+    })
+}
+
+pub fn query_zap_out_output(
+    deps: Deps,
+    env: Env,
+    amount: Uint128,
+    output_token: TokenSelect,
+) -> StdResult<ZapOutOutputResponse> {
+    let lp_token_addr = LP_TOKEN.load(deps.storage)?;
+    let lp_token_supply = get_lp_token_supply(deps, &lp_token_addr)?;
+    let lp_token_supply =
+        lp_token_supply + get_protocol_fee_liquidity(deps.storage, lp_token_supply)?;
+    let zap = get_zap_out(
+        deps.storage,
+        &env.block,
+        &output_token,
+        amount,
+        lp_token_supply,
+    )?;
+    Ok(ZapOutOutputResponse {
+        output_amount: zap.output_amount(),
+    })
+}
+
+pub fn query_fee(deps: Deps) -> StdResult<FeeResponse> {
+    let fees = FEES.load(deps.storage)?;
+    let owner = OWNER.load(deps.storage)?.map(|o| o.into_string());
+
+    Ok(FeeResponse {
+        owner,
+        lp_fee_percent: fees.lp_fee_percent,
+        protocol_fee_percent: fees.protocol_fee_percent,
+        protocol_fee_recipient: fees.protocol_fee_recipient.into_string(),
+        fee_switch: fees.fee_switch,
+    })
+}
+
+pub fn query_current_fee(deps: Deps, env: Env) -> StdResult<CurrentFeeResponse> {
+    let fees = FEES.load(deps.storage)?;
+    let lp_fee_percent = get_lp_fee_percent(deps.storage, &env.block, &fees)?;
+    Ok(CurrentFeeResponse {
+        lp_fee_percent,
+        protocol_fee_percent: fees.protocol_fee_percent,
+        total_fee_percent: lp_fee_percent + fees.protocol_fee_percent,
+    })
+}
+
+pub fn query_dynamic_fee(deps: Deps, env: Env) -> StdResult<DynamicFeeResponse> {
+    let dynamic_fee = DYNAMIC_FEE.may_load(deps.storage)?;
+    let volatility = match (&dynamic_fee, VOLATILITY.may_load(deps.storage)?) {
+        (Some(config), Some(volatility)) => {
+            decayed_volatility(&volatility, config, env.block.time.seconds())
+        }
+        _ => Decimal256::zero(),
+    };
+    Ok(DynamicFeeResponse {
+        dynamic_fee,
+        volatility,
+    })
+}
 
-    transfer_token_state.update(deps.storage, |mut token| -> Result<_, ContractError> {
-        token.reserve = token
-            .reserve
-            .checked_sub(amount_to_transfer)
-            .map_err(StdError::overflow)?;
-        Ok(token)
-    })?;
+pub fn query_amp(deps: Deps, env: Env) -> StdResult<AmpResponse> {
+    if POOL_TYPE.load(deps.storage)? != PoolType::Stable {
+        return Err(StdError::generic_err("This pool is not a stable pool"));
+    }
 
-    Ok(Response::new().add_messages(msgs).add_attributes(vec![
-        attr("input_token_amount", input_token_amount),
-        attr("native_transferred", amount_to_transfer),
-    ]))
+    let amp = AMP.load(deps.storage)?;
+    Ok(AmpResponse {
+        amp: amp.current_amp(env.block.time.seconds()),
+        future_amp: amp.future_amp,
+        future_time: amp.future_time,
+    })
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
-        QueryMsg::Info {} => to_binary(&query_info(deps)?),
-        QueryMsg::Token1ForToken2Price { token1_amount } => {
-            to_binary(&query_token1_for_token2_price(deps, token1_amount)?)
+pub fn query_weights(deps: Deps) -> StdResult<WeightsResponse> {
+    if POOL_TYPE.load(deps.storage)? != PoolType::Weighted {
+        return Err(StdError::generic_err("This pool is not a weighted pool"));
+    }
+
+    let weights = WEIGHTS.load(deps.storage)?;
+    Ok(WeightsResponse {
+        token1_weight: weights.token1_weight,
+        token2_weight: weights.token2_weight,
+    })
+}
+
+/// Returns the cumulative prices at `time`. They grow linearly between observations, after the
+/// last observation they grow with the current reserves.
+fn get_price_cumulative_at(deps: Deps, time: u64) -> StdResult<PriceCumulative> {
+    let no_observation = || StdError::generic_err("No price observation before the requested time");
+    let index = PRICE_OBSERVATION_INDEX
+        .may_load(deps.storage)?
+        .ok_or_else(no_observation)?;
+
+    // Observations are ordered by time, find the first one after `time`
+    let (mut low, mut high) = (0, index.len);
+    while low < high {
+        let mid = (low + high) / 2;
+        if load_price_observation(deps.storage, &index, mid)?.time <= time {
+            low = mid + 1;
+        } else {
+            high = mid;
         }
-        QueryMsg::Token2ForToken1Price { token2_amount } => {
-            to_binary(&query_token2_for_token1_price(deps, token2_amount)?)
+    }
+    if low == 0 {
+        return Err(no_observation());
+    }
+    let before = load_price_observation(deps.storage, &index, low - 1)?;
+    let (before_time, before) = (before.time, before.cumulative);
+    if before_time == time {
+        return Ok(before);
+    }
+
+    let after = if low < index.len {
+        Some(load_price_observation(deps.storage, &index, low)?)
+    } else {
+        None
+    };
+    match after {
+        Some(PriceObservation {
+            time: after_time,
+            cumulative: after,
+        }) => {
+            let ratio = Decimal256::from_ratio(time - before_time, after_time - before_time);
+            Ok(PriceCumulative {
+                token1_price_cumulative: before.token1_price_cumulative
+                    + (after.token1_price_cumulative - before.token1_price_cumulative) * ratio,
+                token2_price_cumulative: before.token2_price_cumulative
+                    + (after.token2_price_cumulative - before.token2_price_cumulative) * ratio,
+            })
+        }
+        None => {
+            let (token1_reserve, token2_reserve) = get_price_reserves(deps.storage, time)?;
+            Ok(accumulate_prices(
+                before,
+                token1_reserve,
+                token2_reserve,
+                time - before_time,
+            ))
         }
-        QueryMsg::Fee {} => to_binary(&query_fee(deps)?),
     }
 }
 
-pub fn query_info(deps: Deps) -> StdResult<InfoResponse> {
-    let token1 = TOKEN1.load(deps.storage)?;
-    let token2 = TOKEN2.load(deps.storage)?;
-    let lp_token_address = LP_TOKEN.load(deps.storage)?;
+pub fn query_twap(deps: Deps, env: Env, start_time: u64, end_time: u64) -> StdResult<TwapResponse> {
+    if start_time >= end_time {
+        return Err(StdError::generic_err(
+            "TWAP start time must be before end time",
+        ));
+    }
+    if end_time > env.block.time.seconds() {
+        return Err(StdError::generic_err("TWAP end time is in the future"));
+    }
 
-    // TODO get total supply
-    Ok(InfoResponse {
-        token1_reserve: token1.reserve,
-        token1_denom: token1.denom,
-        token2_reserve: token2.reserve,
-        token2_denom: token2.denom,
-        lp_token_supply: get_lp_token_supply(deps, &lp_token_address)?,
-        lp_token_address: lp_token_address.into_string(),
+    let start = get_price_cumulative_at(deps, start_time)?;
+    let end = get_price_cumulative_at(deps, end_time)?;
+    let elapsed = Uint256::from(end_time - start_time);
+    Ok(TwapResponse {
+        token1_price: (end.token1_price_cumulative - start.token1_price_cumulative) / elapsed,
+        token2_price: (end.token2_price_cumulative - start.token2_price_cumulative) / elapsed,
     })
 }
 
-pub fn query_token1_for_token2_price(
+pub fn query_staking_config(deps: Deps) -> StdResult<StakingConfigResponse> {
+    let config = STAKING_CONFIG.load(deps.storage)?;
+    Ok(StakingConfigResponse {
+        unbonding_period: config.unbonding_period,
+        total_bonded: TOTAL_BONDED.load(deps.storage)?,
+    })
+}
+
+pub fn query_staker(deps: Deps, address: String) -> StdResult<StakerResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    Ok(StakerResponse {
+        bonded: BONDED.may_load(deps.storage, &address)?.unwrap_or_default(),
+        unbondings: UNBONDINGS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default(),
+    })
+}
+
+/// Reward schedules streamed up to the current block, without saving them
+fn get_current_reward_schedules(deps: Deps, env: &Env) -> StdResult<Vec<RewardSchedule>> {
+    let total_bonded = TOTAL_BONDED.may_load(deps.storage)?.unwrap_or_default();
+    let mut schedules = load_reward_schedules(deps.storage)?;
+    for schedule in schedules.iter_mut() {
+        accrue_rewards(schedule, total_bonded, env.block.time.seconds());
+    }
+    Ok(schedules)
+}
+
+pub fn query_pending_rewards(
     deps: Deps,
-    token1_amount: Uint128,
-) -> StdResult<Token1ForToken2PriceResponse> {
-    let token1 = TOKEN1.load(deps.storage)?;
-    let token2 = TOKEN2.load(deps.storage)?;
+    env: Env,
+    address: String,
+) -> StdResult<PendingRewardsResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let bonded = BONDED.may_load(deps.storage, &address)?.unwrap_or_default();
+    let rewards = get_current_reward_schedules(deps, &env)?
+        .into_iter()
+        .map(|schedule| {
+            let mut reward: StakerReward = STAKER_REWARDS
+                .may_load(deps.storage, (&address, &denom_key(&schedule.denom)))?
+                .unwrap_or_default();
+            accrue_staker_rewards(&mut reward, bonded, schedule.reward_per_share)?;
+            Ok(RewardAmount {
+                denom: schedule.denom,
+                amount: reward.pending,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(PendingRewardsResponse { rewards })
+}
 
-    let fees = FEES.load(deps.storage)?;
-    let total_fee_percent = fees.lp_fee_percent + fees.protocol_fee_percent;
-    let token2_amount = get_input_price(
-        token1_amount,
-        token1.reserve,
-        token2.reserve,
-        total_fee_percent,
-    )?;
-    Ok(Token1ForToken2PriceResponse { token2_amount })
+pub fn query_reward_schedules(deps: Deps, env: Env) -> StdResult<RewardSchedulesResponse> {
+    Ok(RewardSchedulesResponse {
+        schedules: get_current_reward_schedules(deps, &env)?,
+    })
 }
 
-pub fn query_token2_for_token1_price(
+pub fn query_roles(
     deps: Deps,
-    token2_amount: Uint128,
-) -> StdResult<Token2ForToken1PriceResponse> {
-    let token1 = TOKEN1.load(deps.storage)?;
-    let token2 = TOKEN2.load(deps.storage)?;
+    role: Role,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RolesResponse> {
+    if role == Role::Owner {
+        return Ok(RolesResponse {
+            addresses: OWNER.load(deps.storage)?.into_iter().collect(),
+        });
+    }
 
-    let fees = FEES.load(deps.storage)?;
-    let total_fee_percent = fees.lp_fee_percent + fees.protocol_fee_percent;
-    let token1_amount = get_input_price(
-        token2_amount,
-        token2.reserve,
-        token1.reserve,
-        total_fee_percent,
-    )?;
-    Ok(Token2ForToken1PriceResponse { token1_amount })
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|address| Bound::exclusive(address.as_bytes()));
+    let addresses = ROLES
+        .prefix(role.key())
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|key| {
+            String::from_utf8(key)
+                .map(Addr::unchecked)
+                .map_err(StdError::from)
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(RolesResponse { addresses })
 }
 
-pub fn query_fee(deps: Deps) -> StdResult<FeeResponse> {
-    let fees = FEES.load(deps.storage)?;
-    let owner = OWNER.load(deps.storage)?.map(|o| o.into_string());
+pub fn query_orders_by_owner(
+    deps: Deps,
+    owner: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
 
-    Ok(FeeResponse {
-        owner,
-        lp_fee_percent: fees.lp_fee_percent,
-        protocol_fee_percent: fees.protocol_fee_percent,
-        protocol_fee_recipient: fees.protocol_fee_recipient.into_string(),
-    })
+    let orders = OWNER_ORDERS
+        .prefix(&owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect::<StdResult<_>>()?;
+    Ok(OrdersResponse { orders })
+}
+
+pub fn query_orders_by_price(
+    deps: Deps,
+    input_token: TokenSelect,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|order_id| ORDERS.load(deps.storage, order_id))
+        .transpose()?
+        .map(|order| Bound::exclusive(order.price_key()));
+
+    let orders = PRICE_ORDERS
+        .prefix(order_side_key(&input_token))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect::<StdResult<_>>()?;
+    Ok(OrdersResponse { orders })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
-    if msg.id != INSTANTIATE_LP_TOKEN_REPLY_ID {
-        return Err(ContractError::UnknownReplyId { id: msg.id });
-    };
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        INSTANTIATE_LP_TOKEN_REPLY_ID => handle_instantiate_lp_token_reply(deps, msg),
+        FLASH_SWAP_REPLY_ID => handle_flash_swap_reply(deps, env),
+        FEE_ON_TRANSFER_DEPOSIT_REPLY_ID => handle_fee_on_transfer_deposit_reply(deps, env),
+        id => Err(ContractError::UnknownReplyId { id }),
+    }
+}
+
+fn handle_instantiate_lp_token_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
     let res = parse_reply_instantiate_data(msg);
     match res {
         Ok(res) => {
@@ -1074,15 +5359,31 @@ pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, Co
         });
     }
 
+    let fee_switch = FEES
+        .may_load(deps.storage)?
+        .map(|fees| fees.fee_switch)
+        .unwrap_or_default();
     let fees = Fees {
         lp_fee_percent: msg.lp_fee_percent,
         protocol_fee_percent: msg.protocol_fee_percent,
         protocol_fee_recipient,
+        fee_switch,
     };
     FEES.save(deps.storage, &fees)?;
+    // A config queued before the migration would overwrite the migrated fees
+    PENDING_CONFIG.remove(deps.storage);
+
+    // Pause flags replace the single frozen flag of earlier versions, which paused deposits
+    // and swaps
+    deps.storage.remove(b"frozen");
+    let mut pause_flags = PAUSE_FLAGS.may_load(deps.storage)?.unwrap_or_default();
+    pause_flags.freeze(msg.freeze_pool);
+    PAUSE_FLAGS.save(deps.storage, &pause_flags)?;
 
-    // By default deposits are not frozen
-    FROZEN.save(deps.storage, &msg.freeze_pool)?;
+    // Pools created before stable pools existed are constant product pools
+    if POOL_TYPE.may_load(deps.storage)?.is_none() {
+        POOL_TYPE.save(deps.storage, &PoolType::ConstantProduct)?;
+    }
 
     Ok(Response::default())
 }
@@ -1090,18 +5391,71 @@ pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, Co
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
+    use cosmwasm_std::Timestamp;
 
     #[test]
     fn test_get_liquidity_amount() {
-        let liquidity =
-            get_lp_token_amount_to_mint(Uint128::new(100), Uint128::zero(), Uint128::zero())
-                .unwrap();
-        assert_eq!(liquidity, Uint128::new(100));
+        let liquidity = get_lp_token_amount_to_mint(
+            Uint128::new(40_000),
+            Uint128::new(10_000),
+            Uint128::zero(),
+            Uint128::zero(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::new(19_000));
 
-        let liquidity =
-            get_lp_token_amount_to_mint(Uint128::new(100), Uint128::new(50), Uint128::new(25))
-                .unwrap();
+        let err = get_lp_token_amount_to_mint(
+            Uint128::new(1_000),
+            Uint128::new(1_000),
+            Uint128::zero(),
+            Uint128::zero(),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientInitialLiquidity {
+                minimum: MINIMUM_LIQUIDITY
+            }
+        );
+
+        let liquidity = get_lp_token_amount_to_mint(
+            Uint128::new(100),
+            Uint128::new(100),
+            Uint128::new(50),
+            Uint128::new(25),
+            None,
+        )
+        .unwrap();
         assert_eq!(liquidity, Uint128::new(200));
+
+        // The first deposit of a weighted pool mints its weighted invariant
+        let weights = Weights {
+            token1_weight: 80,
+            token2_weight: 20,
+        };
+        let liquidity = get_lp_token_amount_to_mint(
+            Uint128::new(1_600_000),
+            Uint128::new(100_000),
+            Uint128::zero(),
+            Uint128::zero(),
+            Some(&weights),
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::new(917_958));
+
+        // Later deposits stay proportional to the reserves
+        let liquidity = get_lp_token_amount_to_mint(
+            Uint128::new(16_000),
+            Uint128::new(1_000),
+            Uint128::new(918_958),
+            Uint128::new(1_600_000),
+            Some(&weights),
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::new(9_189));
     }
 
     #[test]
@@ -1172,4 +5526,283 @@ mod tests {
         .unwrap_err();
         assert_eq!(err, StdError::generic_err("No liquidity"));
     }
+
+    #[test]
+    fn test_get_stable_input_price() {
+        let fee_percent = Decimal::from_str("0.3").unwrap();
+        // Near the peg the stable curve gives almost 1:1 minus the fee
+        let output = get_stable_input_price(
+            Uint128::new(10_000),
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            fee_percent,
+            100,
+        )
+        .unwrap();
+        let constant_product_output = get_input_price(
+            Uint128::new(10_000),
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            fee_percent,
+        )
+        .unwrap();
+        assert!(output > constant_product_output);
+        assert!(output <= Uint128::new(9_970));
+        assert!(output > Uint128::new(9_950));
+
+        let err = get_stable_input_price(
+            Uint128::new(10),
+            Uint128::new(0),
+            Uint128::new(100),
+            fee_percent,
+            100,
+        )
+        .unwrap_err();
+        assert_eq!(err, StdError::generic_err("No liquidity"));
+    }
+
+    #[test]
+    fn test_get_weighted_input_price() {
+        let fee_percent = Decimal::from_str("0.3").unwrap();
+        // Equal weights match the constant product price
+        let output = get_weighted_input_price(
+            Uint128::new(10_000),
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            fee_percent,
+            50,
+            50,
+        )
+        .unwrap();
+        let constant_product_output = get_input_price(
+            Uint128::new(10_000),
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            fee_percent,
+        )
+        .unwrap();
+        assert!(output <= constant_product_output);
+        assert!(output + Uint128::new(2) >= constant_product_output);
+
+        // 80/20 pool where both tokens are worth the same
+        let output = get_weighted_input_price(
+            Uint128::new(1_000),
+            Uint128::new(1_000_000),
+            Uint128::new(250_000),
+            fee_percent,
+            80,
+            20,
+        )
+        .unwrap();
+        assert_eq!(output, Uint128::new(993));
+
+        let err = get_weighted_input_price(
+            Uint128::new(600_000),
+            Uint128::new(1_000_000),
+            Uint128::new(250_000),
+            fee_percent,
+            80,
+            20,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Swap input exceeds 50% of the input reserve")
+        );
+
+        let err = get_weighted_input_price(
+            Uint128::new(10),
+            Uint128::new(0),
+            Uint128::new(100),
+            fee_percent,
+            80,
+            20,
+        )
+        .unwrap_err();
+        assert_eq!(err, StdError::generic_err("No liquidity"));
+    }
+
+    #[test]
+    fn test_price_observation_ring_buffer() {
+        let mut deps = mock_dependencies();
+        POOL_TYPE
+            .save(&mut deps.storage, &PoolType::ConstantProduct)
+            .unwrap();
+        for (item, reserve) in [(TOKEN1, 1_000u128), (TOKEN2, 2_000u128)] {
+            let token = Token {
+                denom: Denom::Native("juno".to_string()),
+                reserve: Uint128::new(reserve),
+                fee_on_transfer: false,
+            };
+            item.save(&mut deps.storage, &token).unwrap();
+        }
+
+        let mut env = mock_env();
+        let start = env.block.time.seconds();
+        for block in 0..PRICE_OBSERVATION_CAPACITY as u64 + 10 {
+            env.block.time = Timestamp::from_seconds(start + block * 5);
+            update_price_accumulators(&mut deps.storage, &env.block).unwrap();
+        }
+
+        // The ten oldest observations were overwritten
+        let index = PRICE_OBSERVATION_INDEX.load(&deps.storage).unwrap();
+        assert_eq!(
+            index,
+            PriceObservationIndex {
+                latest: 9,
+                len: PRICE_OBSERVATION_CAPACITY
+            }
+        );
+        assert_eq!(
+            PRICE_OBSERVATIONS
+                .range(&deps.storage, None, None, Order::Ascending)
+                .count(),
+            PRICE_OBSERVATION_CAPACITY as usize
+        );
+        let oldest = load_price_observation(&deps.storage, &index, 0).unwrap();
+        assert_eq!(oldest.time, start + 50);
+
+        let err = query_twap(deps.as_ref(), env.clone(), start + 45, start + 100).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("No price observation before the requested time")
+        );
+        let twap = query_twap(deps.as_ref(), env.clone(), start + 50, start + 1_003).unwrap();
+        assert_eq!(twap.token1_price, Decimal256::from_ratio(2u8, 1u8));
+        assert_eq!(twap.token2_price, Decimal256::from_ratio(1u8, 2u8));
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(Uint256::zero()), Uint256::zero());
+        assert_eq!(isqrt(Uint256::from(1u8)), Uint256::from(1u8));
+        assert_eq!(isqrt(Uint256::from(15u8)), Uint256::from(3u8));
+        assert_eq!(isqrt(Uint256::from(16u8)), Uint256::from(4u8));
+        assert_eq!(
+            isqrt(Uint128::MAX.full_mul(Uint128::MAX)),
+            Uint256::from(Uint128::MAX)
+        );
+    }
+
+    #[test]
+    fn test_get_protocol_fee_liquidity_amount() {
+        let fees = Fees {
+            protocol_fee_recipient: Addr::unchecked("protocol"),
+            protocol_fee_percent: Decimal::from_str("0.05").unwrap(),
+            lp_fee_percent: Decimal::from_str("0.25").unwrap(),
+            fee_switch: true,
+        };
+        let k_last = Uint128::new(1_000_000).full_mul(Uint128::new(1_000_000));
+
+        // sqrt(k) grew by 10%, a sixth of it belongs to the protocol like in Uniswap v2
+        let liquidity = get_protocol_fee_liquidity_amount(
+            &fees,
+            Uint128::new(1_100_000),
+            Uint128::new(1_100_000),
+            k_last,
+            Uint128::new(1_000_000),
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::new(15_384));
+
+        // Nothing is minted without growth or without a k_last
+        let liquidity = get_protocol_fee_liquidity_amount(
+            &fees,
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            k_last,
+            Uint128::new(1_000_000),
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::zero());
+        let liquidity = get_protocol_fee_liquidity_amount(
+            &fees,
+            Uint128::new(1_100_000),
+            Uint128::new(1_100_000),
+            Uint256::zero(),
+            Uint128::new(1_000_000),
+        )
+        .unwrap();
+        assert_eq!(liquidity, Uint128::zero());
+    }
+
+    #[test]
+    fn test_get_output_price() {
+        let fee_percent = Decimal::from_str("0.3").unwrap();
+        // Base case, the inverse of get_input_price rounded up
+        assert_eq!(
+            get_output_price(
+                Uint128::new(9),
+                Uint128::new(100),
+                Uint128::new(100),
+                fee_percent
+            )
+            .unwrap(),
+            Uint128::new(10)
+        );
+
+        let err = get_output_price(
+            Uint128::new(100),
+            Uint128::new(100),
+            Uint128::new(100),
+            fee_percent,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Output amount 100 must be less than the output reserve 100")
+        );
+
+        let err = get_output_price(
+            Uint128::new(10),
+            Uint128::new(0),
+            Uint128::new(100),
+            fee_percent,
+        )
+        .unwrap_err();
+        assert_eq!(err, StdError::generic_err("No liquidity"));
+
+        // The required input always buys at least the requested output
+        let output = Uint128::new(12_345);
+        let reserve = Uint128::new(1_000_000);
+        let input = get_output_price(output, reserve, reserve, fee_percent).unwrap();
+        assert!(get_input_price(input, reserve, reserve, fee_percent).unwrap() >= output);
+        assert!(
+            get_input_price(input - Uint128::new(1), reserve, reserve, fee_percent).unwrap()
+                < output
+        );
+
+        let input = get_stable_output_price(output, reserve, reserve, fee_percent, 100).unwrap();
+        assert!(
+            get_stable_input_price(input, reserve, reserve, fee_percent, 100).unwrap() >= output
+        );
+
+        // Both directions round in favor of the pool
+        let input =
+            get_weighted_output_price(output, reserve, Uint128::new(250_000), fee_percent, 80, 20)
+                .unwrap();
+        assert!(
+            get_weighted_input_price(input, reserve, Uint128::new(250_000), fee_percent, 80, 20)
+                .unwrap()
+                + Uint128::new(1)
+                >= output
+        );
+    }
+
+    #[test]
+    fn test_weighted_output_price_extreme_weights() {
+        // Buying 99.9% of the 2% side of a 98/2 pool takes
+        // 10^12 * (0.001^(-2 / 98) - 1) = 151_395_399_326.45 of the 98% side, rounded up
+        // in favor of the pool
+        let input = get_weighted_output_price(
+            Uint128::new(999_000),
+            Uint128::new(1_000_000_000_000),
+            Uint128::new(1_000_000),
+            Decimal::zero(),
+            98,
+            2,
+        )
+        .unwrap();
+        assert_eq!(input, Uint128::new(151_395_399_327));
+    }
 }
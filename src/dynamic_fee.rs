@@ -0,0 +1,120 @@
+use cosmwasm_std::{Decimal, Decimal256, StdResult, Uint128};
+use std::convert::TryInto;
+
+use crate::state::{DynamicFeeConfig, Volatility};
+
+/// Converts a `Decimal` to a `Decimal256` of the same value
+pub fn to_decimal256(decimal: Decimal) -> Decimal256 {
+    Decimal256::from_ratio(decimal.atomics(), Decimal::one().atomics())
+}
+
+/// Volatility left at `now`, the accumulator decays linearly to zero over the volatility window
+pub fn decayed_volatility(
+    volatility: &Volatility,
+    config: &DynamicFeeConfig,
+    now: u64,
+) -> Decimal256 {
+    let elapsed = now.saturating_sub(volatility.last_update_time);
+    if elapsed >= config.volatility_window {
+        return Decimal256::zero();
+    }
+    volatility.accumulator
+        * Decimal256::from_ratio(config.volatility_window - elapsed, config.volatility_window)
+}
+
+/// Adds the relative move from the last price to `price` to the decayed volatility. The
+/// accumulator saturates at `max_fee_volatility`, where the fee is at its maximum.
+pub fn accumulate_volatility(
+    volatility: &mut Volatility,
+    config: &DynamicFeeConfig,
+    price: Decimal256,
+    now: u64,
+) {
+    let mut accumulator = decayed_volatility(volatility, config, now);
+    if !volatility.last_price.is_zero() {
+        let price_change = if price > volatility.last_price {
+            price - volatility.last_price
+        } else {
+            volatility.last_price - price
+        };
+        accumulator = accumulator
+            + Decimal256::from_ratio(price_change.atomics(), volatility.last_price.atomics());
+    }
+
+    volatility.accumulator = accumulator.min(to_decimal256(config.max_fee_volatility));
+    volatility.last_price = price;
+    volatility.last_update_time = now;
+}
+
+/// Lp fee for the accumulated volatility, linear between the minimum fee without volatility
+/// and the maximum fee at `max_fee_volatility`
+pub fn get_dynamic_fee(config: &DynamicFeeConfig, accumulator: Decimal256) -> StdResult<Decimal> {
+    let accumulator = accumulator.min(to_decimal256(config.max_fee_volatility));
+    let accumulator: Uint128 = accumulator.atomics().try_into()?;
+    let ratio = Decimal::from_ratio(accumulator, config.max_fee_volatility.atomics());
+    Ok(config.min_fee_percent + (config.max_fee_percent - config.min_fee_percent) * ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn config() -> DynamicFeeConfig {
+        DynamicFeeConfig {
+            min_fee_percent: Decimal::from_str("0.1").unwrap(),
+            max_fee_percent: Decimal::from_str("0.9").unwrap(),
+            max_fee_volatility: Decimal::percent(10),
+            volatility_window: 100,
+        }
+    }
+
+    #[test]
+    fn test_accumulate_volatility() {
+        let config = config();
+        let mut volatility = Volatility {
+            accumulator: Decimal256::zero(),
+            last_price: Decimal256::one(),
+            last_update_time: 1_000,
+        };
+
+        // 4% move up and 2% move back down
+        accumulate_volatility(&mut volatility, &config, Decimal256::percent(104), 1_000);
+        assert_eq!(volatility.accumulator, Decimal256::percent(4));
+        let price = Decimal256::from_str("1.0192").unwrap();
+        accumulate_volatility(&mut volatility, &config, price, 1_000);
+        assert_eq!(volatility.accumulator, Decimal256::percent(6));
+
+        // Half of the window passed
+        assert_eq!(
+            decayed_volatility(&volatility, &config, 1_050),
+            Decimal256::percent(3)
+        );
+        assert_eq!(
+            decayed_volatility(&volatility, &config, 1_100),
+            Decimal256::zero()
+        );
+
+        // Saturates at the volatility of the maximum fee
+        accumulate_volatility(&mut volatility, &config, Decimal256::percent(200), 1_050);
+        assert_eq!(volatility.accumulator, Decimal256::percent(10));
+        assert_eq!(volatility.last_update_time, 1_050);
+    }
+
+    #[test]
+    fn test_get_dynamic_fee() {
+        let config = config();
+        assert_eq!(
+            get_dynamic_fee(&config, Decimal256::zero()).unwrap(),
+            config.min_fee_percent
+        );
+        assert_eq!(
+            get_dynamic_fee(&config, Decimal256::percent(5)).unwrap(),
+            Decimal::from_str("0.5").unwrap()
+        );
+        assert_eq!(
+            get_dynamic_fee(&config, Decimal256::percent(50)).unwrap(),
+            config.max_fee_percent
+        );
+    }
+}
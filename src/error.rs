@@ -1,6 +1,8 @@
 use cosmwasm_std::{Decimal, StdError, Uint128};
 use thiserror::Error;
 
+use crate::state::{PoolOperation, Role};
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
@@ -22,6 +24,11 @@ pub enum ContractError {
         liquidity_available: Uint128,
     },
 
+    #[error(
+        "The first deposit must mint more than {minimum} liquidity, which stays locked in the pool"
+    )]
+    InsufficientInitialLiquidity { minimum: Uint128 },
+
     #[error("Max token error: max_token: {max_token}, tokens_required: {tokens_required}")]
     MaxTokenError {
         max_token: Uint128,
@@ -52,6 +59,12 @@ pub enum ContractError {
     #[error("Swap min error: min: {min}, available: {available}")]
     SwapMinError { min: Uint128, available: Uint128 },
 
+    #[error("Swap max input error: max: {max_input}, required: {required}")]
+    SwapMaxInputError {
+        max_input: Uint128,
+        required: Uint128,
+    },
+
     #[error("MsgExpirationError")]
     MsgExpirationError {},
 
@@ -73,9 +86,162 @@ pub enum ContractError {
     #[error("The output amm provided is invalid")]
     InvalidOutputPool {},
 
+    #[error("There is no ownership proposal")]
+    NoOwnershipProposal {},
+
+    #[error("The ownership proposal has expired")]
+    OwnershipProposalExpired {},
+
+    #[error("The owner role can only be changed with the ownership messages")]
+    OwnerRoleNotGrantable {},
+
+    #[error("{address} does not have the {role:?} role")]
+    MissingRole { role: Role, address: String },
+
+    #[error("There is no pending config")]
+    NoPendingConfig {},
+
+    #[error("The pending config can not be applied before {effective_time}")]
+    PendingConfigNotEffective { effective_time: u64 },
+
+    #[error("The config timelock can be at most {max} seconds")]
+    ConfigTimelockTooLong { max: u64 },
+
     #[error("Unauthorized pool freeze - sender is not an owner or owner has not been set")]
     UnauthorizedPoolFreeze {},
 
-    #[error("This pools is frozen - you can not deposit or swap tokens")]
-    FrozenPool {},
+    #[error("There is no active guardian pause")]
+    NoGuardianPause {},
+
+    #[error("The guardian pause was used, the pauser must reset it with UpdatePauseFlags")]
+    GuardianPauseUsed {},
+
+    #[error("The guardian pause must expire after the current block time")]
+    InvalidGuardianPauseExpiry {},
+
+    #[error("Guardian pauses can last at most {max} seconds")]
+    GuardianPauseTooLong { max: u64 },
+
+    #[error("{operation:?} is paused")]
+    OperationPaused { operation: PoolOperation },
+
+    #[error("Received cw20 token {token} is not one of the pool tokens")]
+    InvalidCw20Token { token: String },
+
+    #[error("Received cw20 amount does not match: received: {received}, expected: {expected}")]
+    InvalidCw20Amount {
+        received: Uint128,
+        expected: Uint128,
+    },
+
+    #[error("This pool is not a stable pool")]
+    NotStablePool {},
+
+    #[error("Stable pools require an amplification coefficient")]
+    MissingAmp {},
+
+    #[error("Invalid amplification: {amp}, must be between 1 and {max_amp}")]
+    InvalidAmp { amp: u64, max_amp: u64 },
+
+    #[error("Flash swap must request at least one token")]
+    EmptyFlashSwap {},
+
+    #[error("Flash swaps are only supported by constant product pools")]
+    FlashSwapUnsupported {},
+
+    #[error("A flash swap is in progress")]
+    FlashSwapInProgress {},
+
+    #[error("A fee on transfer deposit is in progress")]
+    DepositInProgress {},
+
+    #[error("Fee on transfer tokens can only be deposited with Swap, SwapAndSendTo and AddLiquidity using an allowance")]
+    FeeOnTransferUnsupported {},
+
+    #[error("Only cw20 tokens can be fee on transfer")]
+    NativeFeeOnTransfer {},
+
+    #[error("Only multi asset pools take extra denoms")]
+    ExtraDenomsUnsupported {},
+
+    #[error("A multi asset pool holds at most {max} assets")]
+    TooManyAssets { max: u32 },
+
+    #[error("Asset {denom} is listed more than once")]
+    DuplicateAsset { denom: String },
+
+    #[error("Asset {denom} is not held by this pool")]
+    UnknownAsset { denom: String },
+
+    #[error("This message requires a two token pool")]
+    TwoTokenPoolRequired {},
+
+    #[error("Asset messages require a constant product or multi asset pool")]
+    MultiAssetUnsupported {},
+
+    #[error("Min asset error: {denom}: requested: {min}, available: {available}")]
+    MinAssetError {
+        denom: String,
+        min: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Max liquidity error: max_liquidity: {max_liquidity}, liquidity_required: {liquidity_required}")]
+    MaxLiquidityError {
+        max_liquidity: Uint128,
+        liquidity_required: Uint128,
+    },
+
+    #[error("Flash swap was not repaid with fees")]
+    FlashSwapInvariantError {},
+
+    #[error("Weighted pools require token weights")]
+    MissingWeights {},
+
+    #[error(
+        "Invalid weights: every token must have at least {min_weight_percent}% of the total weight"
+    )]
+    InvalidWeights { min_weight_percent: u64 },
+
+    #[error("Token weights must be greater than zero")]
+    ZeroWeight {},
+
+    #[error("Invalid dynamic fee: the minimum fee must not exceed the maximum fee and the volatility window and maximum fee volatility must not be zero")]
+    InvalidDynamicFee {},
+
+    #[error("The fee switch is only supported by constant product pools")]
+    FeeSwitchUnsupported {},
+
+    #[error("The fee switch can not be combined with the dynamic fee")]
+    FeeSwitchWithDynamicFee {},
+
+    #[error("Staking is not enabled for this pool")]
+    StakingDisabled {},
+
+    #[error("Only LP tokens of this pool can be bonded, received {token}")]
+    InvalidBondToken { token: String },
+
+    #[error("Insufficient bonded tokens: requested: {requested}, bonded: {bonded}")]
+    InsufficientBonded { requested: Uint128, bonded: Uint128 },
+
+    #[error("No unbonded tokens can be withdrawn yet")]
+    NoUnbondedTokens {},
+
+    #[error("No rewards to claim")]
+    NoRewards {},
+
+    #[error("Reward schedules require a non zero amount and duration")]
+    InvalidRewardSchedule {},
+
+    #[error("A pool can stream at most {max} reward denoms")]
+    TooManyRewardDenoms { max: u32 },
+
+    #[error("Limit orders require a non zero input amount and min output")]
+    InvalidOrder {},
+
+    #[error("None of the orders could be filled")]
+    NoOrdersExecuted {},
+
+    #[error("Invalid amplification ramp: must last at least {min_ramp_time} seconds and change the amplification at most {max_change}x")]
+    InvalidAmpRamp { min_ramp_time: u64, max_change: u64 },
 }
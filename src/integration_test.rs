@@ -2,15 +2,32 @@
 
 use std::borrow::BorrowMut;
 
-use cosmwasm_std::{coins, to_binary, Addr, Coin, CosmosMsg, Decimal, Empty, Uint128, WasmMsg};
+use cosmwasm_std::{
+    coins, from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Decimal256,
+    Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult, Uint128, WasmMsg,
+};
 use cw0::Expiration;
 
 use crate::{error::ContractError, msg::MigrateMsg};
 use cw20::{Cw20Coin, Cw20Contract, Cw20ExecuteMsg, Denom};
 use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-use crate::msg::{ExecuteMsg, FeeResponse, InfoResponse, InstantiateMsg, QueryMsg, TokenSelect};
+use crate::msg::{
+    AmpResponse, AssetAmount, AssetPricesResponse, AssetsResponse, CurrentFeeResponse, Cw20HookMsg,
+    DynamicFeeResponse, ExecuteMsg, FeeOnTransferResponse, FeeResponse, FlashSwapReceiverMsg,
+    GuardianResponse, InfoResponse, InstantiateMsg, OrdersResponse, OwnershipProposalResponse,
+    PendingConfigResponse, PendingRewardsResponse, QueryMsg, ReserveDriftResponse,
+    ReverseSimulateSwapResponse, RewardAmount, RolesResponse, SimulateSwapResponse, StakerResponse,
+    StakingConfigResponse, Token1ForExactToken2PriceResponse, Token1ForToken2PriceResponse,
+    TokenDrift, TokenSelect, TwapResponse, WeightsResponse, ZapInLiquidityResponse,
+    ZapOutOutputResponse,
+};
+use crate::state::{
+    DynamicFeeConfig, GuardianConfig, GuardianPause, LimitOrder, OwnershipProposal, PauseFlags,
+    PoolOperation, PoolType, Role, Weights,
+};
 
 fn mock_app() -> App {
     App::default()
@@ -36,6 +53,96 @@ pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
     Box::new(contract)
 }
 
+/// Percent of every transfer of the taxed cw20 that is burned from the recipient
+const CW20_TAX_PERCENT: u128 = 1;
+
+fn taxed_cw20_execute(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: cw20_base::msg::ExecuteMsg,
+) -> Result<Response, cw20_base::ContractError> {
+    let (recipient, amount) = match &msg {
+        cw20_base::msg::ExecuteMsg::Transfer { recipient, amount }
+        | cw20_base::msg::ExecuteMsg::TransferFrom {
+            recipient, amount, ..
+        } => (recipient.clone(), *amount),
+        _ => return cw20_base::contract::execute(deps, env, info, msg),
+    };
+    let res = cw20_base::contract::execute(deps.branch(), env.clone(), info, msg)?;
+    let tax = amount.multiply_ratio(CW20_TAX_PERCENT, 100u128);
+    if !tax.is_zero() {
+        let recipient_info = MessageInfo {
+            sender: Addr::unchecked(recipient),
+            funds: vec![],
+        };
+        cw20_base::contract::execute_burn(deps, env, recipient_info, tax)?;
+    }
+    Ok(res)
+}
+
+pub fn contract_taxed_cw20() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        taxed_cw20_execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    );
+    Box::new(contract)
+}
+
+/// What the flash swap receiver does in its callback
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FlashSwapAction {
+    Repay(Vec<Coin>),
+    Reenter(ExecuteMsg, Vec<Coin>),
+}
+
+fn flash_swap_receiver_execute(
+    _deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: FlashSwapReceiverMsg,
+) -> StdResult<Response> {
+    let FlashSwapReceiverMsg::FlashSwapCallback(callback) = msg;
+    let msg: CosmosMsg = match from_binary(&callback.msg)? {
+        FlashSwapAction::Repay(amount) => BankMsg::Send {
+            to_address: info.sender.into_string(),
+            amount,
+        }
+        .into(),
+        FlashSwapAction::Reenter(msg, funds) => WasmMsg::Execute {
+            contract_addr: info.sender.into_string(),
+            msg: to_binary(&msg)?,
+            funds,
+        }
+        .into(),
+    };
+    Ok(Response::new().add_message(msg))
+}
+
+fn flash_swap_receiver_instantiate(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: Empty,
+) -> StdResult<Response> {
+    Ok(Response::new())
+}
+
+fn flash_swap_receiver_query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+    to_binary(&Empty {})
+}
+
+pub fn contract_flash_swap_receiver() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        flash_swap_receiver_execute,
+        flash_swap_receiver_instantiate,
+        flash_swap_receiver_query,
+    );
+    Box::new(contract)
+}
+
 fn get_info(router: &App, contract_addr: &Addr) -> InfoResponse {
     router
         .wrap()
@@ -70,6 +177,10 @@ fn create_amm(
         lp_fee_percent,
         protocol_fee_percent,
         protocol_fee_recipient,
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
     };
     router
         .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
@@ -167,6 +278,10 @@ fn test_instantiate() {
         lp_fee_percent,
         protocol_fee_percent,
         protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
     };
     let err = router
         .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
@@ -190,7 +305,7 @@ fn amm_add_and_remove_liquidity() {
     const NATIVE_TOKEN_DENOM: &str = "juno";
 
     let owner = Addr::unchecked("owner");
-    let funds = coins(2000, NATIVE_TOKEN_DENOM);
+    let funds = coins(200_000, NATIVE_TOKEN_DENOM);
     router.borrow_mut().init_modules(|router, _, storage| {
         router.bank.init_balance(storage, &owner, funds).unwrap()
     });
@@ -200,7 +315,7 @@ fn amm_add_and_remove_liquidity() {
         &owner,
         "token".to_string(),
         "CWTOKEN".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
 
     let lp_fee_percent = Decimal::from_str("0.3").unwrap();
@@ -223,12 +338,12 @@ fn amm_add_and_remove_liquidity() {
 
     // check initial balances
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(5000));
+    assert_eq!(owner_balance, Uint128::new(500_000));
 
     // send tokens to contract address
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(100u128),
+        amount: Uint128::new(10_000u128),
         expires: None,
     };
     let _res = router
@@ -236,9 +351,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     let _res = router
@@ -248,23 +363,23 @@ fn amm_add_and_remove_liquidity() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
 
     // ensure balances updated
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(4900));
+    assert_eq!(owner_balance, Uint128::new(490_000));
     let amm_balance = cw20_token.balance(&router, amm_addr.clone()).unwrap();
-    assert_eq!(amm_balance, Uint128::new(100));
+    assert_eq!(amm_balance, Uint128::new(10_000));
     let crust_balance = lp_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(crust_balance, Uint128::new(100));
+    assert_eq!(crust_balance, Uint128::new(9_000));
 
     // send tokens to contract address
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(51u128),
+        amount: Uint128::new(5_001u128),
         expires: None,
     };
     let _res = router
@@ -272,9 +387,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(50),
-        min_liquidity: Uint128::new(50),
-        max_token2: Uint128::new(51),
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(5_000),
+        max_token2: Uint128::new(5_001),
         expiration: None,
     };
     let _res = router
@@ -284,23 +399,23 @@ fn amm_add_and_remove_liquidity() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap();
 
     // ensure balances updated
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(4849));
+    assert_eq!(owner_balance, Uint128::new(484_999));
     let amm_balance = cw20_token.balance(&router, amm_addr.clone()).unwrap();
-    assert_eq!(amm_balance, Uint128::new(151));
+    assert_eq!(amm_balance, Uint128::new(15_001));
     let crust_balance = lp_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(crust_balance, Uint128::new(150));
+    assert_eq!(crust_balance, Uint128::new(14_000));
 
     // too low max token error
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(51u128),
+        amount: Uint128::new(5_001u128),
         expires: None,
     };
     let _res = router
@@ -308,9 +423,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(50),
-        min_liquidity: Uint128::new(50),
-        max_token2: Uint128::new(45),
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(5_000),
+        max_token2: Uint128::new(4_500),
         expiration: None,
     };
     let err = router
@@ -320,15 +435,15 @@ fn amm_add_and_remove_liquidity() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap_err();
 
     assert_eq!(
         ContractError::MaxTokenError {
-            max_token: Uint128::new(45),
-            tokens_required: Uint128::new(51)
+            max_token: Uint128::new(4_500),
+            tokens_required: Uint128::new(5_001)
         },
         err.downcast().unwrap()
     );
@@ -336,7 +451,7 @@ fn amm_add_and_remove_liquidity() {
     // too high min liquidity
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(51u128),
+        amount: Uint128::new(5_001u128),
         expires: None,
     };
     let _res = router
@@ -344,9 +459,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(50),
-        min_liquidity: Uint128::new(500),
-        max_token2: Uint128::new(50),
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(50_000),
+        max_token2: Uint128::new(5_000),
         expiration: None,
     };
     let err = router
@@ -356,15 +471,15 @@ fn amm_add_and_remove_liquidity() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap_err();
 
     assert_eq!(
         ContractError::MinLiquidityError {
-            min_liquidity: Uint128::new(500),
-            liquidity_available: Uint128::new(50)
+            min_liquidity: Uint128::new(50_000),
+            liquidity_available: Uint128::new(5_000)
         },
         err.downcast().unwrap()
     );
@@ -372,7 +487,7 @@ fn amm_add_and_remove_liquidity() {
     // Expired message
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(51u128),
+        amount: Uint128::new(5_001u128),
         expires: None,
     };
     let _res = router
@@ -380,9 +495,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(50),
-        min_liquidity: Uint128::new(50),
-        max_token2: Uint128::new(50),
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(5_000),
+        max_token2: Uint128::new(5_000),
         expiration: Some(Expiration::AtHeight(0)),
     };
     let err = router
@@ -392,7 +507,7 @@ fn amm_add_and_remove_liquidity() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap_err();
@@ -405,7 +520,7 @@ fn amm_add_and_remove_liquidity() {
     // Remove more liquidity then owned
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(50u128),
+        amount: Uint128::new(5_000u128),
         expires: None,
     };
     let _res = router
@@ -413,7 +528,7 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
-        amount: Uint128::new(151),
+        amount: Uint128::new(14_001),
         min_token1: Uint128::new(0),
         min_token2: Uint128::new(0),
         expiration: None,
@@ -425,15 +540,15 @@ fn amm_add_and_remove_liquidity() {
             &remove_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap_err();
 
     assert_eq!(
         ContractError::InsufficientLiquidityError {
-            requested: Uint128::new(151),
-            available: Uint128::new(150)
+            requested: Uint128::new(14_001),
+            available: Uint128::new(14_000)
         },
         err.downcast().unwrap()
     );
@@ -441,7 +556,7 @@ fn amm_add_and_remove_liquidity() {
     // Remove some liquidity
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(50u128),
+        amount: Uint128::new(5_000u128),
         expires: None,
     };
     let _res = router
@@ -449,9 +564,9 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
-        amount: Uint128::new(50),
-        min_token1: Uint128::new(50),
-        min_token2: Uint128::new(50),
+        amount: Uint128::new(5_000),
+        min_token1: Uint128::new(5_000),
+        min_token2: Uint128::new(5_000),
         expiration: None,
     };
     let _res = router
@@ -461,23 +576,23 @@ fn amm_add_and_remove_liquidity() {
             &remove_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap();
 
     // ensure balances updated
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(4899));
+    assert_eq!(owner_balance, Uint128::new(489_999));
     let amm_balance = cw20_token.balance(&router, amm_addr.clone()).unwrap();
-    assert_eq!(amm_balance, Uint128::new(101));
+    assert_eq!(amm_balance, Uint128::new(10_001));
     let crust_balance = lp_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(crust_balance, Uint128::new(100));
+    assert_eq!(crust_balance, Uint128::new(9_000));
 
     // Remove rest of liquidity
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(100u128),
+        amount: Uint128::new(9_000u128),
         expires: None,
     };
     let _res = router
@@ -485,26 +600,28 @@ fn amm_add_and_remove_liquidity() {
         .unwrap();
 
     let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
-        amount: Uint128::new(100),
-        min_token1: Uint128::new(100),
-        min_token2: Uint128::new(100),
+        amount: Uint128::new(9_000),
+        min_token1: Uint128::new(9_000),
+        min_token2: Uint128::new(9_000),
         expiration: None,
     };
     let _res = router
         .execute_contract(
             owner.clone(),
-            amm_addr,
+            amm_addr.clone(),
             &remove_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(50),
+                amount: Uint128::new(5_000),
             }],
         )
         .unwrap();
 
-    // ensure balances updated
+    // ensure balances updated, the minimum liquidity keeps its share of the reserves locked
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(5000));
+    assert_eq!(owner_balance, Uint128::new(498_999));
+    let crust_balance = lp_token.balance(&router, amm_addr).unwrap();
+    assert_eq!(crust_balance, Uint128::new(1_000));
 }
 
 #[test]
@@ -528,6 +645,10 @@ fn migrate() {
         lp_fee_percent,
         protocol_fee_percent,
         protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
     };
     let amm_addr = router
         .instantiate_contract(
@@ -597,6 +718,10 @@ fn migrate_and_freeze_pool() {
         lp_fee_percent,
         protocol_fee_percent,
         protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
     };
     let amm_addr = router
         .instantiate_contract(
@@ -634,6 +759,19 @@ fn migrate_and_freeze_pool() {
         .unwrap();
 
     let _ = get_fee(&router, &amm_addr);
+    let pause_flags: PauseFlags = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::PauseFlags {})
+        .unwrap();
+    assert_eq!(
+        pause_flags,
+        PauseFlags {
+            add_liquidity: true,
+            remove_liquidity: false,
+            swap: true,
+            pass_through_swap: true,
+        }
+    );
 
     // now adding liquidity will fail
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
@@ -653,7 +791,12 @@ fn migrate_and_freeze_pool() {
             }],
         )
         .unwrap_err();
-    assert_eq!(ContractError::FrozenPool {}, err.downcast().unwrap());
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::AddLiquidity
+        },
+        err.downcast().unwrap()
+    );
 }
 
 #[test]
@@ -663,7 +806,7 @@ fn swap_tokens_happy_path() {
     const NATIVE_TOKEN_DENOM: &str = "juno";
 
     let owner = Addr::unchecked("owner");
-    let funds = coins(2000, NATIVE_TOKEN_DENOM);
+    let funds = coins(200_000, NATIVE_TOKEN_DENOM);
     router.borrow_mut().init_modules(|router, _, storage| {
         router.bank.init_balance(storage, &owner, funds).unwrap()
     });
@@ -673,7 +816,7 @@ fn swap_tokens_happy_path() {
         &owner,
         "token".to_string(),
         "CWTOKEN".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
 
     let lp_fee_percent = Decimal::from_str("0.3").unwrap();
@@ -692,12 +835,12 @@ fn swap_tokens_happy_path() {
 
     // check initial balances
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(5000));
+    assert_eq!(owner_balance, Uint128::new(500_000));
 
     // send tokens to contract address
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(100u128),
+        amount: Uint128::new(10_000u128),
         expires: None,
     };
     let _res = router
@@ -705,9 +848,9 @@ fn swap_tokens_happy_path() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     let _res = router
@@ -717,25 +860,25 @@ fn swap_tokens_happy_path() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(100));
-    assert_eq!(info.token2_reserve, Uint128::new(100));
+    assert_eq!(info.token1_reserve, Uint128::new(10_000));
+    assert_eq!(info.token2_reserve, Uint128::new(10_000));
 
     let buyer = Addr::unchecked("buyer");
-    let funds = coins(2000, NATIVE_TOKEN_DENOM);
+    let funds = coins(200_000, NATIVE_TOKEN_DENOM);
     router.borrow_mut().init_modules(|router, _, storage| {
         router.bank.init_balance(storage, &buyer, funds).unwrap()
     });
 
     let swap_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token1,
-        input_amount: Uint128::new(10),
-        min_output: Uint128::new(9),
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::new(900),
         expiration: None,
     };
     let _res = router
@@ -745,27 +888,27 @@ fn swap_tokens_happy_path() {
             &swap_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(10),
+                amount: Uint128::new(1_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(110));
-    assert_eq!(info.token2_reserve, Uint128::new(91));
+    assert_eq!(info.token1_reserve, Uint128::new(11_000));
+    assert_eq!(info.token2_reserve, Uint128::new(9_094));
 
     // ensure balances updated
     let buyer_balance = cw20_token.balance(&router, buyer.clone()).unwrap();
-    assert_eq!(buyer_balance, Uint128::new(9));
+    assert_eq!(buyer_balance, Uint128::new(906));
 
     // Check balances of owner and buyer reflect the sale transaction
     let balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(balance.amount, Uint128::new(1990));
+    assert_eq!(balance.amount, Uint128::new(199_000));
 
     let swap_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token1,
-        input_amount: Uint128::new(10),
-        min_output: Uint128::new(7),
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::new(700),
         expiration: None,
     };
     let _res = router
@@ -775,29 +918,29 @@ fn swap_tokens_happy_path() {
             &swap_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(10),
+                amount: Uint128::new(1_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(120));
-    assert_eq!(info.token2_reserve, Uint128::new(84));
+    assert_eq!(info.token1_reserve, Uint128::new(12_000));
+    assert_eq!(info.token2_reserve, Uint128::new(8_339));
 
     // ensure balances updated
     let buyer_balance = cw20_token.balance(&router, buyer.clone()).unwrap();
-    assert_eq!(buyer_balance, Uint128::new(16));
+    assert_eq!(buyer_balance, Uint128::new(1_661));
 
     // Check balances of owner and buyer reflect the sale transaction
     let balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(balance.amount, Uint128::new(1980));
+    assert_eq!(balance.amount, Uint128::new(198_000));
 
     // Swap token for native
 
     // send tokens to contract address
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm_addr.to_string(),
-        amount: Uint128::new(16),
+        amount: Uint128::new(1_661),
         expires: None,
     };
     let _res = router
@@ -806,8 +949,8 @@ fn swap_tokens_happy_path() {
 
     let swap_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token2,
-        input_amount: Uint128::new(16),
-        min_output: Uint128::new(19),
+        input_amount: Uint128::new(1_661),
+        min_output: Uint128::new(1_900),
         expiration: None,
     };
     let _res = router
@@ -815,8 +958,8 @@ fn swap_tokens_happy_path() {
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(101));
-    assert_eq!(info.token2_reserve, Uint128::new(100));
+    assert_eq!(info.token1_reserve, Uint128::new(10_012));
+    assert_eq!(info.token2_reserve, Uint128::new(10_000));
 
     // ensure balances updated
     let buyer_balance = cw20_token.balance(&router, buyer.clone()).unwrap();
@@ -824,17 +967,17 @@ fn swap_tokens_happy_path() {
 
     // Check balances of owner and buyer reflect the sale transaction
     let balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(balance.amount, Uint128::new(1999));
+    assert_eq!(balance.amount, Uint128::new(199_988));
 
     // check owner balance
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(4900));
+    assert_eq!(owner_balance, Uint128::new(490_000));
 
     let swap_msg = ExecuteMsg::SwapAndSendTo {
         input_token: TokenSelect::Token1,
-        input_amount: Uint128::new(10),
+        input_amount: Uint128::new(1_000),
         recipient: owner.to_string(),
-        min_token: Uint128::new(3),
+        min_token: Uint128::new(300),
         expiration: None,
     };
     let _res = router
@@ -844,22 +987,22 @@ fn swap_tokens_happy_path() {
             &swap_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(10),
+                amount: Uint128::new(1_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(111));
-    assert_eq!(info.token2_reserve, Uint128::new(92));
+    assert_eq!(info.token1_reserve, Uint128::new(11_012));
+    assert_eq!(info.token2_reserve, Uint128::new(9_095));
 
     // ensure balances updated
     let owner_balance = cw20_token.balance(&router, owner.clone()).unwrap();
-    assert_eq!(owner_balance, Uint128::new(4908));
+    assert_eq!(owner_balance, Uint128::new(490_905));
 
     // Check balances of owner and buyer reflect the sale transaction
     let balance = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(balance.amount, Uint128::new(1989));
+    assert_eq!(balance.amount, Uint128::new(198_988));
 }
 
 #[test]
@@ -913,7 +1056,7 @@ fn swap_with_fee_split() {
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
         token1_amount: Uint128::new(100_000_000),
-        min_liquidity: Uint128::new(100_000_000),
+        min_liquidity: Uint128::new(99_999_000),
         max_token2: Uint128::new(100_000_000),
         expiration: None,
     };
@@ -1122,10 +1265,11 @@ fn update_config() {
     let lp_fee_percent = Decimal::from_str("0.15").unwrap();
     let protocol_fee_percent = Decimal::from_str("0.15").unwrap();
     let msg = ExecuteMsg::UpdateConfig {
-        owner: Some(owner.to_string()),
         protocol_fee_recipient: "new_fee_recpient".to_string(),
         lp_fee_percent,
         protocol_fee_percent,
+        fee_switch: None,
+        config_timelock: None,
     };
     let _res = router
         .execute_contract(owner.clone(), amm_addr.clone(), &msg, &[])
@@ -1141,10 +1285,11 @@ fn update_config() {
     let lp_fee_percent = Decimal::from_str("1.01").unwrap();
     let protocol_fee_percent = Decimal::zero();
     let msg = ExecuteMsg::UpdateConfig {
-        owner: Some(owner.to_string()),
         protocol_fee_recipient: "new_fee_recpient".to_string(),
         lp_fee_percent,
         protocol_fee_percent,
+        fee_switch: None,
+        config_timelock: None,
     };
     let err = router
         .execute_contract(owner.clone(), amm_addr.clone(), &msg, &[])
@@ -1163,10 +1308,11 @@ fn update_config() {
     let lp_fee_percent = Decimal::from_str("0.21").unwrap();
     let protocol_fee_percent = Decimal::from_str("0.09").unwrap();
     let msg = ExecuteMsg::UpdateConfig {
-        owner: Some(owner.to_string()),
         protocol_fee_recipient: owner.to_string(),
         lp_fee_percent,
         protocol_fee_percent,
+        fee_switch: None,
+        config_timelock: None,
     };
     let err = router
         .execute_contract(
@@ -1180,12 +1326,13 @@ fn update_config() {
         .unwrap();
     assert_eq!(ContractError::Unauthorized {}, err);
 
-    // Try updating owner and fee params
+    // Try updating fee params
     let msg = ExecuteMsg::UpdateConfig {
-        owner: Some("new_owner".to_string()),
         protocol_fee_recipient: owner.to_string(),
         lp_fee_percent,
         protocol_fee_percent,
+        fee_switch: None,
+        config_timelock: None,
     };
     let _res = router
         .execute_contract(owner.clone(), amm_addr.clone(), &msg, &[])
@@ -1195,7 +1342,7 @@ fn update_config() {
     assert_eq!(fee.protocol_fee_recipient, owner.to_string());
     assert_eq!(fee.protocol_fee_percent, protocol_fee_percent);
     assert_eq!(fee.lp_fee_percent, lp_fee_percent);
-    assert_eq!(fee.owner.unwrap(), "new_owner".to_string());
+    assert_eq!(fee.owner.unwrap(), owner.to_string());
 }
 
 #[test]
@@ -1209,11 +1356,11 @@ fn swap_native_to_native_tokens_happy_path() {
     let funds = vec![
         Coin {
             denom: NATIVE_TOKEN_DENOM.into(),
-            amount: Uint128::new(2000),
+            amount: Uint128::new(200_000),
         },
         Coin {
             denom: IBC_TOKEN_DENOM.into(),
-            amount: Uint128::new(5000),
+            amount: Uint128::new(500_000),
         },
     ];
     router.borrow_mut().init_modules(|router, _, storage| {
@@ -1233,6 +1380,10 @@ fn swap_native_to_native_tokens_happy_path() {
         lp_fee_percent,
         protocol_fee_percent,
         protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
     };
     let amm_addr = router
         .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
@@ -1240,9 +1391,9 @@ fn swap_native_to_native_tokens_happy_path() {
 
     // send tokens to contract address
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     let _res = router
@@ -1253,30 +1404,30 @@ fn swap_native_to_native_tokens_happy_path() {
             &[
                 Coin {
                     denom: NATIVE_TOKEN_DENOM.into(),
-                    amount: Uint128::new(100),
+                    amount: Uint128::new(10_000),
                 },
                 Coin {
                     denom: IBC_TOKEN_DENOM.into(),
-                    amount: Uint128::new(100),
+                    amount: Uint128::new(10_000),
                 },
             ],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(100));
-    assert_eq!(info.token2_reserve, Uint128::new(100));
+    assert_eq!(info.token1_reserve, Uint128::new(10_000));
+    assert_eq!(info.token2_reserve, Uint128::new(10_000));
 
     let buyer = Addr::unchecked("buyer");
-    let funds = coins(2000, NATIVE_TOKEN_DENOM);
+    let funds = coins(200_000, NATIVE_TOKEN_DENOM);
     router.borrow_mut().init_modules(|router, _, storage| {
         router.bank.init_balance(storage, &buyer, funds).unwrap()
     });
 
     let add_liquidity_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token1,
-        input_amount: Uint128::new(10),
-        min_output: Uint128::new(9),
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::new(900),
         expiration: None,
     };
     let _res = router
@@ -1286,25 +1437,25 @@ fn swap_native_to_native_tokens_happy_path() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(10),
+                amount: Uint128::new(1_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(110));
-    assert_eq!(info.token2_reserve, Uint128::new(91));
+    assert_eq!(info.token1_reserve, Uint128::new(11_000));
+    assert_eq!(info.token2_reserve, Uint128::new(9_094));
 
     // Check balances of owner and buyer reflect the sale transaction
     let native_balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(native_balance.amount, Uint128::new(1990));
+    assert_eq!(native_balance.amount, Uint128::new(199_000));
     let ibc_balance: Coin = bank_balance(&mut router, &buyer, IBC_TOKEN_DENOM.to_string());
-    assert_eq!(ibc_balance.amount, Uint128::new(9));
+    assert_eq!(ibc_balance.amount, Uint128::new(906));
 
     let swap_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token1,
-        input_amount: Uint128::new(10),
-        min_output: Uint128::new(7),
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::new(700),
         expiration: None,
     };
     let _res = router
@@ -1314,26 +1465,26 @@ fn swap_native_to_native_tokens_happy_path() {
             &swap_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(10),
+                amount: Uint128::new(1_000),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(120));
-    assert_eq!(info.token2_reserve, Uint128::new(84));
+    assert_eq!(info.token1_reserve, Uint128::new(12_000));
+    assert_eq!(info.token2_reserve, Uint128::new(8_339));
 
     // Check balances of owner and buyer reflect the sale transaction
     let native_balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(native_balance.amount, Uint128::new(1980));
+    assert_eq!(native_balance.amount, Uint128::new(198_000));
     let ibc_balance: Coin = bank_balance(&mut router, &buyer, IBC_TOKEN_DENOM.to_string());
-    assert_eq!(ibc_balance.amount, Uint128::new(16));
+    assert_eq!(ibc_balance.amount, Uint128::new(1_661));
 
     // Swap token for native
     let swap_msg = ExecuteMsg::Swap {
         input_token: TokenSelect::Token2,
-        input_amount: Uint128::new(16),
-        min_output: Uint128::new(19),
+        input_amount: Uint128::new(1_661),
+        min_output: Uint128::new(1_900),
         expiration: None,
     };
     let _res = router
@@ -1343,18 +1494,18 @@ fn swap_native_to_native_tokens_happy_path() {
             &swap_msg,
             &[Coin {
                 denom: IBC_TOKEN_DENOM.into(),
-                amount: Uint128::new(16),
+                amount: Uint128::new(1_661),
             }],
         )
         .unwrap();
 
     let info = get_info(&router, &amm_addr);
-    assert_eq!(info.token1_reserve, Uint128::new(101));
-    assert_eq!(info.token2_reserve, Uint128::new(100));
+    assert_eq!(info.token1_reserve, Uint128::new(10_012));
+    assert_eq!(info.token2_reserve, Uint128::new(10_000));
 
     // Check balances of owner and buyer reflect the sale transaction
     let native_balance: Coin = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(native_balance.amount, Uint128::new(1999));
+    assert_eq!(native_balance.amount, Uint128::new(199_988));
     let ibc_balance: Coin = bank_balance(&mut router, &buyer, IBC_TOKEN_DENOM.to_string());
     assert_eq!(ibc_balance.amount, Uint128::new(0));
 
@@ -1486,7 +1637,7 @@ fn token_to_token_swap_with_fee_split() {
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
         token1_amount: Uint128::new(100_000_000),
-        min_liquidity: Uint128::new(100_000_000),
+        min_liquidity: Uint128::new(99_999_000),
         max_token2: Uint128::new(100_000_000),
         expiration: None,
     };
@@ -1613,7 +1764,7 @@ fn test_pass_through_swap() {
     const NATIVE_TOKEN_DENOM: &str = "juno";
 
     let owner = Addr::unchecked("owner");
-    let funds = coins(2000, NATIVE_TOKEN_DENOM);
+    let funds = coins(200_000, NATIVE_TOKEN_DENOM);
     router.borrow_mut().init_modules(|router, _, storage| {
         router.bank.init_balance(storage, &owner, funds).unwrap()
     });
@@ -1623,14 +1774,14 @@ fn test_pass_through_swap() {
         &owner,
         "token1".to_string(),
         "TOKENONE".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
     let token2 = create_cw20(
         &mut router,
         &owner,
         "token2".to_string(),
         "TOKENTWO".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
 
     let lp_fee_percent = Decimal::from_str("0.3").unwrap();
@@ -1657,7 +1808,7 @@ fn test_pass_through_swap() {
     // Add initial liquidity to both pools
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm1.to_string(),
-        amount: Uint128::new(100),
+        amount: Uint128::new(10_000),
         expires: None,
     };
     let _res = router
@@ -1665,9 +1816,9 @@ fn test_pass_through_swap() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     router
@@ -1677,14 +1828,14 @@ fn test_pass_through_swap() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
 
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm2.to_string(),
-        amount: Uint128::new(100),
+        amount: Uint128::new(10_000),
         expires: None,
     };
     let _res = router
@@ -1692,9 +1843,9 @@ fn test_pass_through_swap() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     router
@@ -1704,7 +1855,7 @@ fn test_pass_through_swap() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
@@ -1712,7 +1863,7 @@ fn test_pass_through_swap() {
     // Swap token1 for token2
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm1.to_string(),
-        amount: Uint128::new(10),
+        amount: Uint128::new(1_000),
         expires: None,
     };
     let _res = router
@@ -1722,8 +1873,8 @@ fn test_pass_through_swap() {
     let swap_msg = ExecuteMsg::PassThroughSwap {
         output_amm_address: amm2.to_string(),
         input_token: TokenSelect::Token2,
-        input_token_amount: Uint128::new(10),
-        output_min_token: Uint128::new(8),
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::new(800),
         expiration: None,
     };
     let _res = router
@@ -1732,21 +1883,21 @@ fn test_pass_through_swap() {
 
     // ensure balances updated
     let token1_balance = token1.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token1_balance, Uint128::new(4890));
+    assert_eq!(token1_balance, Uint128::new(489_000));
 
     let token2_balance = token2.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token2_balance, Uint128::new(4908));
+    assert_eq!(token2_balance, Uint128::new(490_828));
 
     let amm1_native_balance = bank_balance(&mut router, &amm1, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm1_native_balance.amount, Uint128::new(91));
+    assert_eq!(amm1_native_balance.amount, Uint128::new(9_094));
 
     let amm2_native_balance = bank_balance(&mut router, &amm2, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm2_native_balance.amount, Uint128::new(109));
+    assert_eq!(amm2_native_balance.amount, Uint128::new(10_906));
 
     // Swap token2 for token1
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm2.to_string(),
-        amount: Uint128::new(10),
+        amount: Uint128::new(1_000),
         expires: None,
     };
     let _res = router
@@ -1756,8 +1907,8 @@ fn test_pass_through_swap() {
     let swap_msg = ExecuteMsg::PassThroughSwap {
         output_amm_address: amm1.to_string(),
         input_token: TokenSelect::Token2,
-        input_token_amount: Uint128::new(10),
-        output_min_token: Uint128::new(1),
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::new(100),
         expiration: None,
     };
     let _res = router
@@ -1766,16 +1917,16 @@ fn test_pass_through_swap() {
 
     // ensure balances updated
     let token1_balance = token1.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token1_balance, Uint128::new(4900));
+    assert_eq!(token1_balance, Uint128::new(490_153));
 
     let token2_balance = token2.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token2_balance, Uint128::new(4898));
+    assert_eq!(token2_balance, Uint128::new(489_828));
 
     let amm1_native_balance = bank_balance(&mut router, &amm1, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm1_native_balance.amount, Uint128::new(101));
+    assert_eq!(amm1_native_balance.amount, Uint128::new(10_163));
 
     let amm2_native_balance = bank_balance(&mut router, &amm2, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm2_native_balance.amount, Uint128::new(99));
+    assert_eq!(amm2_native_balance.amount, Uint128::new(9_837));
 
     // assert internal state is consistent
     let info_amm1 = get_info(&router, &amm1);
@@ -1802,11 +1953,11 @@ fn test_pass_through_swap_alternative_positions() {
     let funds = vec![
         Coin {
             denom: NATIVE_TOKEN_DENOM.into(),
-            amount: Uint128::new(2000),
+            amount: Uint128::new(200_000),
         },
         Coin {
             denom: WRONG_DENOM.into(),
-            amount: Uint128::new(2000),
+            amount: Uint128::new(200_000),
         },
     ];
     router.borrow_mut().init_modules(|router, _, storage| {
@@ -1818,14 +1969,14 @@ fn test_pass_through_swap_alternative_positions() {
         &owner,
         "token1".to_string(),
         "TOKENONE".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
     let token2 = create_cw20(
         &mut router,
         &owner,
         "token2".to_string(),
         "TOKENTWO".to_string(),
-        Uint128::new(5000),
+        Uint128::new(500_000),
     );
 
     let lp_fee_percent = Decimal::from_str("0.3").unwrap();
@@ -1852,7 +2003,7 @@ fn test_pass_through_swap_alternative_positions() {
     // Add initial liquidity to both pools
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm1.to_string(),
-        amount: Uint128::new(100),
+        amount: Uint128::new(10_000),
         expires: None,
     };
     let _res = router
@@ -1860,9 +2011,9 @@ fn test_pass_through_swap_alternative_positions() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     router
@@ -1872,14 +2023,14 @@ fn test_pass_through_swap_alternative_positions() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
 
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm2.to_string(),
-        amount: Uint128::new(100),
+        amount: Uint128::new(10_000),
         expires: None,
     };
     let _res = router
@@ -1887,9 +2038,9 @@ fn test_pass_through_swap_alternative_positions() {
         .unwrap();
 
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     router
@@ -1899,7 +2050,7 @@ fn test_pass_through_swap_alternative_positions() {
             &add_liquidity_msg,
             &[Coin {
                 denom: NATIVE_TOKEN_DENOM.into(),
-                amount: Uint128::new(100),
+                amount: Uint128::new(10_000),
             }],
         )
         .unwrap();
@@ -1907,7 +2058,7 @@ fn test_pass_through_swap_alternative_positions() {
     // Swap token1 for token2
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm1.to_string(),
-        amount: Uint128::new(10),
+        amount: Uint128::new(1_000),
         expires: None,
     };
     let _res = router
@@ -1917,8 +2068,8 @@ fn test_pass_through_swap_alternative_positions() {
     let swap_msg = ExecuteMsg::PassThroughSwap {
         output_amm_address: amm2.to_string(),
         input_token: TokenSelect::Token2,
-        input_token_amount: Uint128::new(10),
-        output_min_token: Uint128::new(8),
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::new(800),
         expiration: None,
     };
     let _res = router
@@ -1927,21 +2078,21 @@ fn test_pass_through_swap_alternative_positions() {
 
     // ensure balances updated
     let token1_balance = token1.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token1_balance, Uint128::new(4890));
+    assert_eq!(token1_balance, Uint128::new(489_000));
 
     let token2_balance = token2.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token2_balance, Uint128::new(4908));
+    assert_eq!(token2_balance, Uint128::new(490_828));
 
     let amm1_native_balance = bank_balance(&mut router, &amm1, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm1_native_balance.amount, Uint128::new(91));
+    assert_eq!(amm1_native_balance.amount, Uint128::new(9_094));
 
     let amm2_native_balance = bank_balance(&mut router, &amm2, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm2_native_balance.amount, Uint128::new(109));
+    assert_eq!(amm2_native_balance.amount, Uint128::new(10_906));
 
     // Swap token2 for token1
     let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
         spender: amm2.to_string(),
-        amount: Uint128::new(10),
+        amount: Uint128::new(1_000),
         expires: None,
     };
     let _res = router
@@ -1951,8 +2102,8 @@ fn test_pass_through_swap_alternative_positions() {
     let swap_msg = ExecuteMsg::PassThroughSwap {
         output_amm_address: amm1.to_string(),
         input_token: TokenSelect::Token1,
-        input_token_amount: Uint128::new(10),
-        output_min_token: Uint128::new(1),
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::new(100),
         expiration: None,
     };
     let _res = router
@@ -1961,16 +2112,16 @@ fn test_pass_through_swap_alternative_positions() {
 
     // ensure balances updated
     let token1_balance = token1.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token1_balance, Uint128::new(4900));
+    assert_eq!(token1_balance, Uint128::new(490_153));
 
     let token2_balance = token2.balance(&router, owner.clone()).unwrap();
-    assert_eq!(token2_balance, Uint128::new(4898));
+    assert_eq!(token2_balance, Uint128::new(489_828));
 
     let amm1_native_balance = bank_balance(&mut router, &amm1, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm1_native_balance.amount, Uint128::new(101));
+    assert_eq!(amm1_native_balance.amount, Uint128::new(10_163));
 
     let amm2_native_balance = bank_balance(&mut router, &amm2, NATIVE_TOKEN_DENOM.to_string());
-    assert_eq!(amm2_native_balance.amount, Uint128::new(99));
+    assert_eq!(amm2_native_balance.amount, Uint128::new(9_837));
 
     // assert internal state is consistent
     let info_amm1 = get_info(&router, &amm1);
@@ -1994,9 +2145,9 @@ fn test_pass_through_swap_alternative_positions() {
         owner.to_string(),
     );
     let add_liquidity_msg = ExecuteMsg::AddLiquidity {
-        token1_amount: Uint128::new(100),
-        min_liquidity: Uint128::new(100),
-        max_token2: Uint128::new(100),
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
         expiration: None,
     };
     router
@@ -2007,11 +2158,11 @@ fn test_pass_through_swap_alternative_positions() {
             &[
                 Coin {
                     denom: NATIVE_TOKEN_DENOM.into(),
-                    amount: Uint128::new(100),
+                    amount: Uint128::new(10_000),
                 },
                 Coin {
                     denom: WRONG_DENOM.into(),
-                    amount: Uint128::new(100),
+                    amount: Uint128::new(10_000),
                 },
             ],
         )
@@ -2020,8 +2171,8 @@ fn test_pass_through_swap_alternative_positions() {
     let swap_msg = ExecuteMsg::PassThroughSwap {
         output_amm_address: invalid_output_amm.to_string(),
         input_token: TokenSelect::Token1,
-        input_token_amount: Uint128::new(10),
-        output_min_token: Uint128::new(1),
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::new(100),
         expiration: None,
     };
     let err = router
@@ -2029,10 +2180,4694 @@ fn test_pass_through_swap_alternative_positions() {
             owner.clone(),
             amm1.clone(),
             &swap_msg,
-            &coins(10, NATIVE_TOKEN_DENOM),
+            &coins(1_000, NATIVE_TOKEN_DENOM),
         )
         .unwrap_err()
         .downcast()
         .unwrap();
     assert_eq!(ContractError::InvalidOutputPool {}, err)
 }
+
+#[test]
+fn cw20_receive_hook() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let protocol_fee_recipient = Addr::unchecked("protocol_fee_recipient");
+    let funds = coins(2_000_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let token_a = create_cw20(
+        &mut router,
+        &owner,
+        "token_a".to_string(),
+        "TOKENA".to_string(),
+        Uint128::new(5_000_000_000),
+    );
+    let token_b = create_cw20(
+        &mut router,
+        &owner,
+        "token_b".to_string(),
+        "TOKENB".to_string(),
+        Uint128::new(5_000_000_000),
+    );
+
+    let amm1 = create_amm(
+        &mut router,
+        &owner,
+        Denom::Cw20(token_a.addr()),
+        Denom::Cw20(token_b.addr()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        protocol_fee_recipient.to_string(),
+    );
+    let amm2 = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.to_string()),
+        Denom::Cw20(token_b.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+
+    // Add liquidity by sending token1, token2 is still pulled with an allowance
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm1.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), token_b.addr(), &allowance_msg, &[])
+        .unwrap();
+
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm1.to_string(),
+        amount: Uint128::new(1_000_000),
+        msg: to_binary(&Cw20HookMsg::AddLiquidity {
+            token1_amount: Uint128::new(1_000_000),
+            min_liquidity: Uint128::new(999_000),
+            max_token2: Uint128::new(1_000_000),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(owner.clone(), token_a.addr(), &send_msg, &[])
+        .unwrap();
+
+    let info = get_info(&router, &amm1);
+    assert_eq!(info.token1_reserve, Uint128::new(1_000_000));
+    assert_eq!(info.token2_reserve, Uint128::new(1_000_000));
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(999_000));
+
+    // Add liquidity by sending token2, the unused part of max_token2 is refunded
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm1.to_string(),
+        amount: Uint128::new(500_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), token_a.addr(), &allowance_msg, &[])
+        .unwrap();
+
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm1.to_string(),
+        amount: Uint128::new(600_000),
+        msg: to_binary(&Cw20HookMsg::AddLiquidity {
+            token1_amount: Uint128::new(500_000),
+            min_liquidity: Uint128::new(500_000),
+            max_token2: Uint128::new(600_000),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(owner.clone(), token_b.addr(), &send_msg, &[])
+        .unwrap();
+
+    let info = get_info(&router, &amm1);
+    assert_eq!(info.token1_reserve, Uint128::new(1_500_000));
+    assert_eq!(info.token2_reserve, Uint128::new(1_500_001));
+    let amm_balance = token_b.balance(&router, amm1.clone()).unwrap();
+    assert_eq!(amm_balance, Uint128::new(1_500_001));
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(1_499_000));
+
+    // The received amount has to match the amount in the hook msg
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm1.to_string(),
+        amount: Uint128::new(10),
+        msg: to_binary(&Cw20HookMsg::AddLiquidity {
+            token1_amount: Uint128::new(500_000),
+            min_liquidity: Uint128::new(500_000),
+            max_token2: Uint128::new(600_000),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), token_a.addr(), &send_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidCw20Amount {
+            received: Uint128::new(10),
+            expected: Uint128::new(500_000)
+        },
+        err.downcast().unwrap()
+    );
+
+    // Swap without an allowance, the protocol fee is paid out of the received tokens
+    let buyer = Addr::unchecked("buyer");
+    let transfer_msg = Cw20ExecuteMsg::Transfer {
+        recipient: buyer.to_string(),
+        amount: Uint128::new(110_000),
+    };
+    router
+        .execute_contract(owner.clone(), token_a.addr(), &transfer_msg, &[])
+        .unwrap();
+
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm1.to_string(),
+        amount: Uint128::new(100_000),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            min_output: Uint128::new(93_000),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(buyer.clone(), token_a.addr(), &send_msg, &[])
+        .unwrap();
+
+    let info = get_info(&router, &amm1);
+    assert_eq!(info.token1_reserve, Uint128::new(1_599_900));
+    assert_eq!(info.token2_reserve, Uint128::new(1_406_515));
+    let buyer_balance = token_b.balance(&router, buyer.clone()).unwrap();
+    assert_eq!(buyer_balance, Uint128::new(93_486));
+    let fee_recipient_balance = token_a
+        .balance(&router, protocol_fee_recipient.clone())
+        .unwrap();
+    assert_eq!(fee_recipient_balance, Uint128::new(100));
+    let amm_balance = token_a.balance(&router, amm1.clone()).unwrap();
+    assert_eq!(amm_balance, info.token1_reserve);
+
+    // Pass through swap A -> B -> juno
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm2.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), token_b.addr(), &allowance_msg, &[])
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm2.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm1.to_string(),
+        amount: Uint128::new(10_000),
+        msg: to_binary(&Cw20HookMsg::PassThroughSwap {
+            output_amm_address: amm2.to_string(),
+            output_min_token: Uint128::new(8_600),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(buyer.clone(), token_a.addr(), &send_msg, &[])
+        .unwrap();
+
+    let info = get_info(&router, &amm1);
+    assert_eq!(info.token1_reserve, Uint128::new(1_609_890));
+    assert_eq!(info.token2_reserve, Uint128::new(1_397_805));
+    let info = get_info(&router, &amm2);
+    assert_eq!(info.token1_reserve, Uint128::new(991_391));
+    assert_eq!(info.token2_reserve, Uint128::new(1_008_710));
+    let balance = bank_balance(&mut router, &buyer, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(balance.amount, Uint128::new(8_609));
+    let buyer_balance = token_a.balance(&router, buyer.clone()).unwrap();
+    assert_eq!(buyer_balance, Uint128::zero());
+
+    // Tokens that are not part of the pool are rejected
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm2.to_string(),
+        amount: Uint128::new(1_000),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            min_output: Uint128::zero(),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    let err = router
+        .execute_contract(owner, token_a.addr(), &send_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidCw20Token {
+            token: token_a.addr().into_string()
+        },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn twap_oracle() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(50_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(50_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Native(IBC_TOKEN_DENOM.into()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+    let start_time = router.block_info().time.seconds();
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9_000),
+        max_token2: Uint128::new(10_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin {
+                    denom: IBC_TOKEN_DENOM.into(),
+                    amount: Uint128::new(10_000),
+                },
+                Coin {
+                    denom: NATIVE_TOKEN_DENOM.into(),
+                    amount: Uint128::new(10_000),
+                },
+            ],
+        )
+        .unwrap();
+
+    router.update_block(|block| block.time = block.time.plus_seconds(100));
+
+    // Only the first state change of a block records prices, so swapping back and forth
+    // within a block can not move the accumulated prices
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token2,
+        input_amount: Uint128::new(500),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(500, IBC_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(10_429));
+    assert_eq!(info.token2_reserve, Uint128::new(9_594));
+
+    let twap: TwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time,
+                end_time: start_time + 100,
+            },
+        )
+        .unwrap();
+    assert_eq!(twap.token1_price, Decimal256::one());
+    assert_eq!(twap.token2_price, Decimal256::one());
+
+    router.update_block(|block| block.time = block.time.plus_seconds(100));
+
+    // Half of the window at a price of 1 and half at the price after the swaps
+    let twap: TwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time,
+                end_time: start_time + 200,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        twap.token1_price,
+        Decimal256::from_str("0.959967398600057531").unwrap()
+    );
+    assert_eq!(
+        twap.token2_price,
+        Decimal256::from_str("1.04351678132165937").unwrap()
+    );
+
+    // Windows between observations are interpolated
+    let twap: TwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time: start_time + 50,
+                end_time: start_time + 150,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        twap.token1_price,
+        Decimal256::from_str("0.959967398600057531").unwrap()
+    );
+    assert_eq!(
+        twap.token2_price,
+        Decimal256::from_str("1.04351678132165937").unwrap()
+    );
+
+    let err = router
+        .wrap()
+        .query_wasm_smart::<TwapResponse>(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time: start_time + 100,
+                end_time: start_time + 100,
+            },
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("TWAP start time must be before end time"));
+
+    let err = router
+        .wrap()
+        .query_wasm_smart::<TwapResponse>(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time,
+                end_time: start_time + 201,
+            },
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("TWAP end time is in the future"));
+
+    let err = router
+        .wrap()
+        .query_wasm_smart::<TwapResponse>(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time: start_time - 1,
+                end_time: start_time + 100,
+            },
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("No price observation before the requested time"));
+}
+
+#[test]
+fn stable_pool() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "stjuno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_id = router.store_code(contract_amm());
+    let lp_token_id = router.store_code(contract_cw20());
+    let mut msg = InstantiateMsg {
+        token1_denom: Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        token2_denom: Denom::Native(IBC_TOKEN_DENOM.into()),
+        lp_token_code_id: lp_token_id,
+        owner: Some(owner.to_string()),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+        protocol_fee_percent: Decimal::zero(),
+        protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::Stable,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
+    };
+
+    // Stable pools need an amplification
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(ContractError::MissingAmp {}, err.downcast().unwrap());
+
+    msg.amp = Some(100);
+    let amm_addr = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap();
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin {
+                    denom: IBC_TOKEN_DENOM.into(),
+                    amount: Uint128::new(1_000_000),
+                },
+                Coin {
+                    denom: NATIVE_TOKEN_DENOM.into(),
+                    amount: Uint128::new(1_000_000),
+                },
+            ],
+        )
+        .unwrap();
+
+    // Near the peg almost all of the input minus the fee is received
+    let price: Token1ForToken2PriceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Token1ForToken2Price {
+                token1_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+    assert!(price.token2_amount > Uint128::new(9_950));
+    assert!(price.token2_amount <= Uint128::new(9_970));
+
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10_000),
+        min_output: price.token2_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_010_000));
+    assert_eq!(
+        info.token2_reserve,
+        Uint128::new(1_000_000) - price.token2_amount
+    );
+    let ibc_balance = bank_balance(&mut router, &owner, IBC_TOKEN_DENOM.to_string());
+    assert_eq!(
+        ibc_balance.amount,
+        Uint128::new(1_000_000) + price.token2_amount
+    );
+
+    // Withdrawing is proportional to the reserves
+    let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
+        amount: Uint128::new(500_000),
+        min_token1: Uint128::new(505_000),
+        min_token2: Uint128::zero(),
+        expiration: None,
+    };
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+    router
+        .execute_contract(
+            owner.clone(),
+            lp_token.addr(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: amm_addr.to_string(),
+                amount: Uint128::new(500_000),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &remove_liquidity_msg, &[])
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(505_000));
+    assert_eq!(info.lp_token_supply, Uint128::new(500_000));
+
+    // Only the owner can ramp the amplification
+    let now = router.block_info().time.seconds();
+    let ramp_msg = ExecuteMsg::RampAmp {
+        future_amp: 200,
+        future_time: now + 86_400,
+    };
+    let err = router
+        .execute_contract(Addr::unchecked("anyone"), amm_addr.clone(), &ramp_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Ramps must be slow and small
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RampAmp {
+                future_amp: 200,
+                future_time: now + 3_600,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidAmpRamp {
+            min_ramp_time: 86_400,
+            max_change: 10
+        },
+        err.downcast().unwrap()
+    );
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RampAmp {
+                future_amp: 1_001,
+                future_time: now + 86_400,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidAmpRamp {
+            min_ramp_time: 86_400,
+            max_change: 10
+        },
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &ramp_msg, &[])
+        .unwrap();
+
+    router.update_block(|block| block.time = block.time.plus_seconds(43_200));
+    let amp: AmpResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Amp {})
+        .unwrap();
+    assert_eq!(
+        amp,
+        AmpResponse {
+            amp: 150,
+            future_amp: 200,
+            future_time: now + 86_400,
+        }
+    );
+
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::StopRampAmp {},
+            &[],
+        )
+        .unwrap();
+    router.update_block(|block| block.time = block.time.plus_seconds(86_400));
+    let amp: AmpResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Amp {})
+        .unwrap();
+    assert_eq!(amp.amp, 150);
+    assert_eq!(amp.future_amp, 150);
+
+    // Constant product pools have no amplification
+    let cp_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Native(IBC_TOKEN_DENOM.into()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+    let err = router
+        .execute_contract(owner, cp_addr, &ramp_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NotStablePool {}, err.downcast().unwrap());
+}
+
+#[test]
+fn imbalanced_stable_pool_prices() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "stjuno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_id = router.store_code(contract_amm());
+    let lp_token_id = router.store_code(contract_cw20());
+    let msg = InstantiateMsg {
+        token1_denom: Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        token2_denom: Denom::Native(IBC_TOKEN_DENOM.into()),
+        lp_token_code_id: lp_token_id,
+        owner: Some(owner.to_string()),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+        protocol_fee_percent: Decimal::zero(),
+        protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::Stable,
+        amp: Some(100),
+        weights: None,
+        extra_denoms: vec![],
+    };
+    let amm_addr = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap();
+    let start_time = router.block_info().time.seconds();
+
+    // Three times as much juno as stjuno
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_500_000),
+        min_liquidity: Uint128::zero(),
+        max_token2: Uint128::new(500_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin::new(1_500_000, NATIVE_TOKEN_DENOM),
+                Coin::new(500_000, IBC_TOKEN_DENOM),
+            ],
+        )
+        .unwrap();
+
+    // The spot price is the slope of the StableSwap curve, 0.99125 instead of the reserve
+    // ratio of 0.333
+    let simulation: SimulateSwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::SimulateSwap {
+                input_token: TokenSelect::Token1,
+                input_amount: Uint128::new(1_000),
+            },
+        )
+        .unwrap();
+    assert!(simulation.spot_price_before > Decimal256::from_str("0.99124").unwrap());
+    assert!(simulation.spot_price_before < Decimal256::from_str("0.99125").unwrap());
+    assert!(simulation.spot_price_after < simulation.spot_price_before);
+    // A small swap has almost no price impact
+    assert_eq!(simulation.output_amount, Uint128::new(988));
+    assert!(simulation.price_impact < Decimal::from_str("0.001").unwrap());
+
+    let simulation: SimulateSwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::SimulateSwap {
+                input_token: TokenSelect::Token2,
+                input_amount: Uint128::new(1_000),
+            },
+        )
+        .unwrap();
+    assert!(simulation.spot_price_before > Decimal256::from_str("1.00882").unwrap());
+    assert!(simulation.spot_price_before < Decimal256::from_str("1.00883").unwrap());
+    assert!(simulation.price_impact < Decimal::from_str("0.001").unwrap());
+
+    // The oracle records the same prices
+    router.update_block(|block| block.time = block.time.plus_seconds(100));
+    let twap: TwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time,
+                end_time: start_time + 100,
+            },
+        )
+        .unwrap();
+    assert!(twap.token1_price > Decimal256::from_str("0.99124").unwrap());
+    assert!(twap.token1_price < Decimal256::from_str("0.99125").unwrap());
+    assert!(twap.token2_price > Decimal256::from_str("1.00882").unwrap());
+    assert!(twap.token2_price < Decimal256::from_str("1.00883").unwrap());
+}
+
+#[test]
+fn weighted_pool() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const GOV_TOKEN_DENOM: &str = "gov";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: GOV_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(2_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_id = router.store_code(contract_amm());
+    let lp_token_id = router.store_code(contract_cw20());
+    let mut msg = InstantiateMsg {
+        token1_denom: Denom::Native(GOV_TOKEN_DENOM.into()),
+        token2_denom: Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        lp_token_code_id: lp_token_id,
+        owner: Some(owner.to_string()),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+        protocol_fee_percent: Decimal::zero(),
+        protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::Weighted,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![],
+    };
+
+    // Weighted pools need weights and every token needs a meaningful share
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(ContractError::MissingWeights {}, err.downcast().unwrap());
+
+    msg.weights = Some(Weights {
+        token1_weight: 0,
+        token2_weight: 0,
+    });
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(ContractError::ZeroWeight {}, err.downcast().unwrap());
+
+    msg.weights = Some(Weights {
+        token1_weight: 100,
+        token2_weight: 0,
+    });
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(ContractError::ZeroWeight {}, err.downcast().unwrap());
+
+    msg.weights = Some(Weights {
+        token1_weight: 99,
+        token2_weight: 1,
+    });
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidWeights {
+            min_weight_percent: 2
+        },
+        err.downcast().unwrap()
+    );
+
+    msg.weights = Some(Weights {
+        token1_weight: 80,
+        token2_weight: 20,
+    });
+    let amm_addr = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap();
+    let weights: WeightsResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Weights {})
+        .unwrap();
+    assert_eq!(
+        weights,
+        WeightsResponse {
+            token1_weight: 80,
+            token2_weight: 20,
+        }
+    );
+
+    // An 80/20 pool only needs a quarter of the paired asset for the same price. The first
+    // deposit mints the weighted invariant 1_000_000^0.8 * 250_000^0.2 = 757_858
+    let start_time = router.block_info().time.seconds();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(756_858),
+        max_token2: Uint128::new(250_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin {
+                    denom: GOV_TOKEN_DENOM.into(),
+                    amount: Uint128::new(1_000_000),
+                },
+                Coin {
+                    denom: NATIVE_TOKEN_DENOM.into(),
+                    amount: Uint128::new(250_000),
+                },
+            ],
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(756_858));
+
+    let price: Token1ForToken2PriceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Token1ForToken2Price {
+                token1_amount: Uint128::new(1_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(price.token2_amount, Uint128::new(993));
+
+    router.update_block(|block| block.time = block.time.plus_seconds(100));
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(1_000),
+        min_output: price.token2_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, GOV_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_001_000));
+    assert_eq!(info.token2_reserve, Uint128::new(249_007));
+
+    // Large swaps are rejected instead of draining the pool
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token2,
+        input_amount: Uint128::new(200_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(200_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+
+    // The oracle prices tokens by their weighted reserves
+    let twap: TwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Twap {
+                start_time,
+                end_time: start_time + 100,
+            },
+        )
+        .unwrap();
+    assert_eq!(twap.token1_price, Decimal256::one());
+    assert_eq!(twap.token2_price, Decimal256::one());
+}
+
+#[test]
+fn flash_swap() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(10_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(10_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Native(IBC_TOKEN_DENOM.into()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+    let receiver_id = router.store_code(contract_flash_swap_receiver());
+    let receiver_addr = router
+        .instantiate_contract(receiver_id, owner.clone(), &Empty {}, &[], "receiver", None)
+        .unwrap();
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(4000),
+        max_token2: Uint128::new(5_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin {
+                    denom: IBC_TOKEN_DENOM.into(),
+                    amount: Uint128::new(5_000),
+                },
+                Coin {
+                    denom: NATIVE_TOKEN_DENOM.into(),
+                    amount: Uint128::new(5_000),
+                },
+            ],
+        )
+        .unwrap();
+
+    // The receiver has funds of its own to pay the fee
+    router
+        .send_tokens(
+            owner.clone(),
+            receiver_addr.clone(),
+            &coins(100, IBC_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // Repaying only the borrowed amount does not cover the fee
+    let flash_swap_msg = |action: FlashSwapAction| ExecuteMsg::FlashSwap {
+        token1_amount: Uint128::zero(),
+        token2_amount: Uint128::new(1_000),
+        recipient: receiver_addr.to_string(),
+        msg: to_binary(&action).unwrap(),
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &flash_swap_msg(FlashSwapAction::Repay(coins(1_000, IBC_TOKEN_DENOM))),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FlashSwapInvariantError {},
+        err.downcast().unwrap()
+    );
+
+    // The pool can not be used while the callback runs
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .send_tokens(
+            owner.clone(),
+            receiver_addr.clone(),
+            &coins(10, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &flash_swap_msg(FlashSwapAction::Reenter(
+                swap_msg,
+                coins(10, NATIVE_TOKEN_DENOM),
+            )),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FlashSwapInProgress {},
+        err.downcast().unwrap()
+    );
+
+    // Borrowed amount plus the 0.3% fee, rounded up
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &flash_swap_msg(FlashSwapAction::Repay(coins(1_004, IBC_TOKEN_DENOM))),
+            &[],
+        )
+        .unwrap();
+
+    // The pool keeps the lp fee and sends out the protocol fee
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(5_000));
+    assert_eq!(info.token2_reserve, Uint128::new(5_003));
+    let protocol_balance = bank_balance(
+        &mut router,
+        &Addr::unchecked("protocol"),
+        IBC_TOKEN_DENOM.to_string(),
+    );
+    assert_eq!(protocol_balance.amount, Uint128::new(1));
+    let receiver_balance = bank_balance(&mut router, &receiver_addr, IBC_TOKEN_DENOM.to_string());
+    assert_eq!(receiver_balance.amount, Uint128::new(96));
+
+    // More than the reserve can not be borrowed
+    let err = router
+        .execute_contract(
+            owner,
+            amm_addr,
+            &ExecuteMsg::FlashSwap {
+                token1_amount: Uint128::new(5_000),
+                token2_amount: Uint128::zero(),
+                recipient: receiver_addr.to_string(),
+                msg: to_binary(&FlashSwapAction::Repay(vec![])).unwrap(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InsufficientLiquidityError {
+            requested: Uint128::new(5_000),
+            available: Uint128::new(5_000),
+        },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn swap_exact_out() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+    const IBC_TOKEN_DENOM: &str = "atom";
+
+    let owner = Addr::unchecked("owner");
+    let funds = vec![
+        Coin {
+            denom: IBC_TOKEN_DENOM.into(),
+            amount: Uint128::new(10_000),
+        },
+        Coin {
+            denom: NATIVE_TOKEN_DENOM.into(),
+            amount: Uint128::new(10_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Native(IBC_TOKEN_DENOM.into()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(5_000),
+        min_liquidity: Uint128::new(4000),
+        max_token2: Uint128::new(5_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin {
+                    denom: IBC_TOKEN_DENOM.into(),
+                    amount: Uint128::new(5_000),
+                },
+                Coin {
+                    denom: NATIVE_TOKEN_DENOM.into(),
+                    amount: Uint128::new(5_000),
+                },
+            ],
+        )
+        .unwrap();
+
+    let price: Token1ForExactToken2PriceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Token1ForExactToken2Price {
+                token2_amount: Uint128::new(1_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(price.token1_amount, Uint128::new(1_254));
+
+    // Max input below the required input
+    let swap_msg = ExecuteMsg::SwapExactOut {
+        output_token: TokenSelect::Token2,
+        output_amount: Uint128::new(1_000),
+        max_input: Uint128::new(1_253),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_253, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::SwapMaxInputError {
+            max_input: Uint128::new(1_253),
+            required: Uint128::new(1_254),
+        },
+        err.downcast().unwrap()
+    );
+
+    // The unused native input is refunded
+    let swap_msg = ExecuteMsg::SwapExactOut {
+        output_token: TokenSelect::Token2,
+        output_amount: Uint128::new(1_000),
+        max_input: Uint128::new(1_500),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_500, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let native_balance = bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(native_balance.amount, Uint128::new(10_000 - 5_000 - 1_254));
+    let ibc_balance = bank_balance(&mut router, &owner, IBC_TOKEN_DENOM.to_string());
+    assert_eq!(ibc_balance.amount, Uint128::new(10_000 - 5_000 + 1_000));
+
+    // The protocol fee is taken from the input
+    let protocol_balance = bank_balance(
+        &mut router,
+        &Addr::unchecked("protocol"),
+        NATIVE_TOKEN_DENOM.to_string(),
+    );
+    assert_eq!(protocol_balance.amount, Uint128::new(1));
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(5_000 + 1_254 - 1));
+    assert_eq!(info.token2_reserve, Uint128::new(4_000));
+    let amm_balance = bank_balance(&mut router, &amm_addr, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(amm_balance.amount, info.token1_reserve);
+}
+
+#[test]
+fn zap_in() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(20_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(20_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+    let info = get_info(&router, &amm_addr);
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(20_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9000),
+        max_token2: Uint128::new(10_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // Zap in with the native token
+    let quote: ZapInLiquidityResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::ZapInLiquidity {
+                input_token: TokenSelect::Token1,
+                amount: Uint128::new(2_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        quote,
+        ZapInLiquidityResponse {
+            liquidity: Uint128::new(952),
+            swap_amount: Uint128::new(956),
+        }
+    );
+
+    let zap_in_msg = ExecuteMsg::ZapIn {
+        input_token: TokenSelect::Token1,
+        amount: Uint128::new(2_000),
+        min_liquidity: Uint128::new(953),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &zap_in_msg,
+            &coins(2_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MinLiquidityError {
+            min_liquidity: Uint128::new(953),
+            liquidity_available: Uint128::new(952),
+        },
+        err.downcast().unwrap()
+    );
+
+    let zap_in_msg = ExecuteMsg::ZapIn {
+        input_token: TokenSelect::Token1,
+        amount: Uint128::new(2_000),
+        min_liquidity: quote.liquidity,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &zap_in_msg,
+            &coins(2_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // Only the protocol fee of the swapped part leaves the pool
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(12_000));
+    assert_eq!(info.token2_reserve, Uint128::new(10_000));
+    assert_eq!(info.lp_token_supply, Uint128::new(10_952));
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(9952));
+
+    // Zap in with the cw20 token
+    let quote: ZapInLiquidityResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::ZapInLiquidity {
+                input_token: TokenSelect::Token2,
+                amount: Uint128::new(1_000),
+            },
+        )
+        .unwrap();
+    let zap_in_msg = ExecuteMsg::ZapIn {
+        input_token: TokenSelect::Token2,
+        amount: Uint128::new(1_000),
+        min_liquidity: quote.liquidity,
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &zap_in_msg, &[])
+        .unwrap();
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(9_952) + quote.liquidity);
+    let info = get_info(&router, &amm_addr);
+    let owner_balance = cw20_token.balance(&router, owner).unwrap();
+    assert_eq!(owner_balance, Uint128::new(9_000));
+    let protocol_balance = cw20_token
+        .balance(&router, Addr::unchecked("protocol"))
+        .unwrap();
+    assert_eq!(info.token2_reserve, Uint128::new(11_000) - protocol_balance);
+}
+
+#[test]
+fn zap_out() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(20_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(20_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+    let info = get_info(&router, &amm_addr);
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(10_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &allowance_msg, &[])
+        .unwrap();
+
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9000),
+        max_token2: Uint128::new(10_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // 1000 withdrawn directly and 1000 of the cw20 token swapped for 897
+    let quote: ZapOutOutputResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::ZapOutOutput {
+                amount: Uint128::new(1_000),
+                output_token: TokenSelect::Token1,
+            },
+        )
+        .unwrap();
+    assert_eq!(quote.output_amount, Uint128::new(1_897));
+
+    let zap_out_msg = ExecuteMsg::ZapOut {
+        amount: Uint128::new(1_000),
+        output_token: TokenSelect::Token1,
+        min_output: Uint128::new(1_898),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &zap_out_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::SwapMinError {
+            min: Uint128::new(1_898),
+            available: Uint128::new(1_897),
+        },
+        err.downcast().unwrap()
+    );
+
+    let zap_out_msg = ExecuteMsg::ZapOut {
+        amount: Uint128::new(1_000),
+        output_token: TokenSelect::Token1,
+        min_output: quote.output_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &zap_out_msg, &[])
+        .unwrap();
+
+    let native_balance = bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(native_balance.amount, Uint128::new(10_000 + 1_897));
+    let lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(8000));
+    let protocol_balance = cw20_token
+        .balance(&router, Addr::unchecked("protocol"))
+        .unwrap();
+    assert_eq!(protocol_balance, Uint128::new(1));
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(10_000 - 1_897));
+    assert_eq!(info.token2_reserve, Uint128::new(9_999));
+    let amm_balance = cw20_token.balance(&router, amm_addr.clone()).unwrap();
+    assert_eq!(amm_balance, info.token2_reserve);
+
+    // Zap out to the cw20 token
+    let quote: ZapOutOutputResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::ZapOutOutput {
+                amount: Uint128::new(1_000),
+                output_token: TokenSelect::Token2,
+            },
+        )
+        .unwrap();
+    let zap_out_msg = ExecuteMsg::ZapOut {
+        amount: Uint128::new(1_000),
+        output_token: TokenSelect::Token2,
+        min_output: quote.output_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &zap_out_msg, &[])
+        .unwrap();
+    let owner_balance = cw20_token.balance(&router, owner).unwrap();
+    assert_eq!(owner_balance, Uint128::new(10_000) + quote.output_amount);
+}
+
+#[test]
+fn lp_staking() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let staker = Addr::unchecked("staker");
+    let funds = coins(20_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(20_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::zero(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+    let info = get_info(&router, &amm_addr);
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(10_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(10_000),
+        min_liquidity: Uint128::new(9000),
+        max_token2: Uint128::new(10_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let transfer_msg = Cw20ExecuteMsg::Transfer {
+        recipient: staker.to_string(),
+        amount: Uint128::new(5_000),
+    };
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &transfer_msg, &[])
+        .unwrap();
+
+    let bond_msg = |amount: u128| Cw20ExecuteMsg::Send {
+        contract: amm_addr.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_binary(&Cw20HookMsg::Bond {}).unwrap(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), lp_token.addr(), &bond_msg(1_000), &[])
+        .unwrap_err();
+    assert_eq!(ContractError::StakingDisabled {}, err.downcast().unwrap());
+
+    let config_msg = ExecuteMsg::UpdateStakingConfig {
+        unbonding_period: 100,
+    };
+    let err = router
+        .execute_contract(staker.clone(), amm_addr.clone(), &config_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &config_msg, &[])
+        .unwrap();
+
+    // Pool tokens can not be bonded
+    let err = router
+        .execute_contract(owner.clone(), cw20_token.addr(), &bond_msg(1_000), &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidBondToken {
+            token: cw20_token.addr().into_string()
+        },
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &bond_msg(1_000), &[])
+        .unwrap();
+    router
+        .execute_contract(staker.clone(), lp_token.addr(), &bond_msg(3_000), &[])
+        .unwrap();
+
+    // Rewards are funded by the config admin, holders of other roles are rejected
+    let rewards_admin = Addr::unchecked("rewards_admin");
+    router
+        .send_tokens(
+            owner.clone(),
+            rewards_admin.clone(),
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let fund_msg = ExecuteMsg::FundRewards {
+        denom: Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        amount: Uint128::new(1_000),
+        duration: 100,
+    };
+    for (role, address) in [
+        (Role::ConfigAdmin, &rewards_admin),
+        (Role::FeeManager, &staker),
+    ] {
+        router
+            .execute_contract(
+                owner.clone(),
+                amm_addr.clone(),
+                &ExecuteMsg::GrantRole {
+                    role,
+                    address: address.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    let err = router
+        .execute_contract(staker.clone(), amm_addr.clone(), &fund_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Rewards in the pool's own native token do not change the reserves
+    router
+        .execute_contract(
+            rewards_admin,
+            amm_addr.clone(),
+            &fund_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(10_000));
+
+    // 400 streamed to 4000 bonded tokens
+    router.update_block(|block| block.time = block.time.plus_seconds(40));
+    let pending: PendingRewardsResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::PendingRewards {
+                address: staker.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        pending.rewards,
+        vec![RewardAmount {
+            denom: Denom::Native(NATIVE_TOKEN_DENOM.into()),
+            amount: Uint128::new(300),
+        }]
+    );
+
+    let unbond_msg = ExecuteMsg::Unbond {
+        amount: Uint128::new(3_001),
+    };
+    let err = router
+        .execute_contract(staker.clone(), amm_addr.clone(), &unbond_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InsufficientBonded {
+            requested: Uint128::new(3_001),
+            bonded: Uint128::new(3_000),
+        },
+        err.downcast().unwrap()
+    );
+    let unbond_msg = ExecuteMsg::Unbond {
+        amount: Uint128::new(3_000),
+    };
+    router
+        .execute_contract(staker.clone(), amm_addr.clone(), &unbond_msg, &[])
+        .unwrap();
+
+    // The owner is the only staker for the remaining 600
+    router.update_block(|block| block.time = block.time.plus_seconds(60));
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+        .unwrap();
+    let native_balance = bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(native_balance.amount, Uint128::new(9_000 + 700));
+
+    // The unbonding period ends 100 seconds after unbonding
+    let err = router
+        .execute_contract(
+            staker.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::WithdrawUnbonded {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::NoUnbondedTokens {}, err.downcast().unwrap());
+    let staker_info: StakerResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Staker {
+                address: staker.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(staker_info.bonded, Uint128::zero());
+    assert_eq!(staker_info.unbondings.len(), 1);
+    assert_eq!(staker_info.unbondings[0].amount, Uint128::new(3_000));
+
+    router.update_block(|block| block.time = block.time.plus_seconds(40));
+    router
+        .execute_contract(
+            staker.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::WithdrawUnbonded {},
+            &[],
+        )
+        .unwrap();
+    let lp_balance = lp_token.balance(&router, staker.clone()).unwrap();
+    assert_eq!(lp_balance, Uint128::new(5_000));
+
+    router
+        .execute_contract(
+            staker.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+        .unwrap();
+    let native_balance = bank_balance(&mut router, &staker, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(native_balance.amount, Uint128::new(300));
+    let err = router
+        .execute_contract(
+            staker.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::NoRewards {}, err.downcast().unwrap());
+
+    let config: StakingConfigResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::StakingConfig {})
+        .unwrap();
+    assert_eq!(config.unbonding_period, 100);
+    assert_eq!(config.total_bonded, Uint128::new(1_000));
+}
+
+#[test]
+fn fee_switch() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let protocol = Addr::unchecked("protocol");
+    let funds = coins(3_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(3_000_000),
+    );
+    let lp_fee_percent = Decimal::from_str("0.5").unwrap();
+    let protocol_fee_percent = Decimal::from_str("0.5").unwrap();
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        lp_fee_percent,
+        protocol_fee_percent,
+        protocol.to_string(),
+    );
+    let info = get_info(&router, &amm_addr);
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(3_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let update_config_msg = |fee_switch: bool| ExecuteMsg::UpdateConfig {
+        protocol_fee_recipient: protocol.to_string(),
+        lp_fee_percent,
+        protocol_fee_percent,
+        fee_switch: Some(fee_switch),
+        config_timelock: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg(true),
+            &[],
+        )
+        .unwrap();
+    let fee = get_fee(&router, &amm_addr);
+    assert!(fee.fee_switch);
+
+    // The protocol share of the fee switch is based on the static lp fee, so the dynamic fee
+    // can not be enabled at the same time
+    let dynamic_fee_msg = ExecuteMsg::UpdateDynamicFee {
+        dynamic_fee: Some(DynamicFeeConfig {
+            min_fee_percent: Decimal::from_str("0.1").unwrap(),
+            max_fee_percent: Decimal::from_str("0.5").unwrap(),
+            max_fee_volatility: Decimal::percent(10),
+            volatility_window: 100,
+        }),
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &dynamic_fee_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FeeSwitchWithDynamicFee {},
+        err.downcast().unwrap()
+    );
+
+    // The whole fee stays in the pool
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(100_000),
+        min_output: Uint128::new(90_081),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let protocol_balance = bank_balance(&mut router, &protocol, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(protocol_balance.amount, Uint128::zero());
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_100_000));
+    assert_eq!(info.token2_reserve, Uint128::new(909_919));
+
+    // sqrt(k) grew from 1_000_000 to 1_000_455, half of that growth is minted to the protocol
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(110_000),
+        min_liquidity: Uint128::new(100_022),
+        max_token2: Uint128::new(100_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(110_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let protocol_lp_balance = lp_token.balance(&router, protocol.clone()).unwrap();
+    assert_eq!(protocol_lp_balance, Uint128::new(227));
+    let owner_lp_balance = lp_token.balance(&router, owner.clone()).unwrap();
+    assert_eq!(owner_lp_balance, Uint128::new(999_000 + 100_022));
+
+    // Nothing accrued since the last liquidity change
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
+        amount: Uint128::new(100_022),
+        min_token1: Uint128::zero(),
+        min_token2: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &remove_liquidity_msg, &[])
+        .unwrap();
+    let protocol_lp_balance = lp_token.balance(&router, protocol.clone()).unwrap();
+    assert_eq!(protocol_lp_balance, Uint128::new(227));
+
+    // The fee of the swap inside a zap stays in the pool and accrues to the protocol as well
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ZapIn {
+                input_token: TokenSelect::Token1,
+                amount: Uint128::new(100_000),
+                min_liquidity: Uint128::zero(),
+                expiration: None,
+            },
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ZapOut {
+                amount: Uint128::new(50_000),
+                output_token: TokenSelect::Token1,
+                min_output: Uint128::zero(),
+                expiration: None,
+            },
+            &[],
+        )
+        .unwrap();
+    // The zap out mints the protocol share of the zap in fee, its own fee is minted later
+    let protocol_lp_balance = lp_token.balance(&router, protocol.clone()).unwrap();
+    assert_eq!(protocol_lp_balance, Uint128::new(227 + 112));
+
+    // Turning the switch off mints what accrued so far and transfers fees on swaps again
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(100_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg(false),
+            &[],
+        )
+        .unwrap();
+    let protocol_lp_balance = lp_token.balance(&router, protocol.clone()).unwrap();
+    assert_eq!(protocol_lp_balance, Uint128::new(227 + 112 + 329));
+    let fee = get_fee(&router, &amm_addr);
+    assert!(!fee.fee_switch);
+
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let protocol_balance = bank_balance(&mut router, &protocol, NATIVE_TOKEN_DENOM.to_string());
+    assert_eq!(protocol_balance.amount, Uint128::new(500));
+
+    // Neither can the fee switch be turned on while the dynamic fee is enabled
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &dynamic_fee_msg, &[])
+        .unwrap();
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg(true),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FeeSwitchWithDynamicFee {},
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn dynamic_fee() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(3_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(3_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let dynamic_fee = DynamicFeeConfig {
+        min_fee_percent: Decimal::from_str("0.1").unwrap(),
+        max_fee_percent: Decimal::from_str("0.9").unwrap(),
+        max_fee_volatility: Decimal::percent(10),
+        volatility_window: 100,
+    };
+    let update_msg = ExecuteMsg::UpdateDynamicFee {
+        dynamic_fee: Some(dynamic_fee.clone()),
+    };
+    let err = router
+        .execute_contract(
+            Addr::unchecked("anyone"),
+            amm_addr.clone(),
+            &update_msg,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    let invalid_msg = ExecuteMsg::UpdateDynamicFee {
+        dynamic_fee: Some(DynamicFeeConfig {
+            min_fee_percent: Decimal::one(),
+            ..dynamic_fee.clone()
+        }),
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &invalid_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidDynamicFee {}, err.downcast().unwrap());
+
+    let too_high_msg = ExecuteMsg::UpdateDynamicFee {
+        dynamic_fee: Some(DynamicFeeConfig {
+            max_fee_percent: Decimal::from_str("1.01").unwrap(),
+            ..dynamic_fee.clone()
+        }),
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &too_high_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FeesTooHigh {
+            max_fee_percent: Decimal::one(),
+            total_fee_percent: Decimal::from_str("1.01").unwrap(),
+        },
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &update_msg, &[])
+        .unwrap();
+    let current_fee: CurrentFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::CurrentFee {})
+        .unwrap();
+    assert_eq!(current_fee.lp_fee_percent, dynamic_fee.min_fee_percent);
+    assert_eq!(current_fee.total_fee_percent, dynamic_fee.min_fee_percent);
+
+    // Quotes use the current fee
+    let quote: Token1ForToken2PriceResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::Token1ForToken2Price {
+                token1_amount: Uint128::new(50_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(quote.token2_amount, Uint128::new(47_573));
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(50_000),
+        min_output: quote.token2_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(50_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // The price of token1 fell by about 9.3%, close to the volatility of the maximum fee
+    let current_fee: CurrentFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::CurrentFee {})
+        .unwrap();
+    assert!(current_fee.lp_fee_percent > Decimal::from_str("0.84").unwrap());
+    assert!(current_fee.lp_fee_percent < Decimal::from_str("0.85").unwrap());
+
+    // The volatility decays over the window
+    router.update_block(|block| block.time = block.time.plus_seconds(50));
+    let response: DynamicFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::DynamicFee {})
+        .unwrap();
+    assert!(response.volatility > Decimal256::permille(46));
+    assert!(response.volatility < Decimal256::permille(47));
+    router.update_block(|block| block.time = block.time.plus_seconds(50));
+    let current_fee: CurrentFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::CurrentFee {})
+        .unwrap();
+    assert_eq!(current_fee.lp_fee_percent, dynamic_fee.min_fee_percent);
+
+    // Back to the static lp fee
+    let disable_msg = ExecuteMsg::UpdateDynamicFee { dynamic_fee: None };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &disable_msg, &[])
+        .unwrap();
+    let current_fee: CurrentFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::CurrentFee {})
+        .unwrap();
+    assert_eq!(
+        current_fee.lp_fee_percent,
+        Decimal::from_str("0.3").unwrap()
+    );
+}
+
+#[test]
+fn limit_orders() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let executor = Addr::unchecked("executor");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let invalid_msg = ExecuteMsg::PlaceOrder {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &invalid_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidOrder {}, err.downcast().unwrap());
+
+    // Order 1 sells above the pool price, order 2 below it
+    let place_msg = ExecuteMsg::PlaceOrder {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::new(11_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &place_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let place_msg = ExecuteMsg::PlaceOrder {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(5_000),
+        min_output: Uint128::new(4_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &place_msg,
+            &coins(5_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    // Order 3 sells cw20 tokens through the receive hook
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm_addr.to_string(),
+        amount: Uint128::new(20_000),
+        msg: to_binary(&Cw20HookMsg::PlaceOrder {
+            min_output: Uint128::new(19_000),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &send_msg, &[])
+        .unwrap();
+    assert_eq!(
+        cw20_token.balance(&router, amm_addr.clone()).unwrap(),
+        Uint128::new(1_020_000)
+    );
+
+    let orders: OrdersResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::OrdersByPrice {
+                input_token: TokenSelect::Token1,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    let order_ids: Vec<u64> = orders.orders.iter().map(|order| order.id).collect();
+    assert_eq!(order_ids, vec![2, 1]);
+    assert_eq!(orders.orders[0].price, Decimal256::percent(80));
+    let orders: OrdersResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::OrdersByPrice {
+                input_token: TokenSelect::Token1,
+                start_after: Some(2),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(orders.orders.len(), 1);
+    assert_eq!(orders.orders[0].id, 1);
+
+    let orders: OrdersResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::OrdersByOwner {
+                owner: owner.to_string(),
+                start_after: None,
+                limit: Some(2),
+            },
+        )
+        .unwrap();
+    let order_ids: Vec<u64> = orders.orders.iter().map(|order| order.id).collect();
+    assert_eq!(order_ids, vec![1, 2]);
+    let orders: OrdersResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::OrdersByOwner {
+                owner: owner.to_string(),
+                start_after: Some(2),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(orders.orders.len(), 1);
+    assert_eq!(orders.orders[0].input_token, TokenSelect::Token2);
+
+    // Orders 2 and 3 are filled, order 1 waits for a higher price
+    let execute_msg = ExecuteMsg::ExecuteOrders { limit: None };
+    router
+        .execute_contract(executor.clone(), amm_addr.clone(), &execute_msg, &[])
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(985_257));
+    assert_eq!(info.token2_reserve, Uint128::new(1_015_040));
+    // 0.1% of the outputs of 4_960 cw20 tokens and 19_743 juno
+    assert_eq!(
+        cw20_token.balance(&router, executor.clone()).unwrap(),
+        Uint128::new(4)
+    );
+    assert_eq!(
+        bank_balance(&mut router, &executor, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(19)
+    );
+    assert_eq!(
+        bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(985_000 + 19_724)
+    );
+    let order: LimitOrder = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Order { order_id: 1 })
+        .unwrap();
+    assert_eq!(order.min_output, Uint128::new(11_000));
+    router
+        .wrap()
+        .query_wasm_smart::<LimitOrder>(&amm_addr, &QueryMsg::Order { order_id: 2 })
+        .unwrap_err();
+
+    let err = router
+        .execute_contract(executor.clone(), amm_addr.clone(), &execute_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NoOrdersExecuted {}, err.downcast().unwrap());
+
+    let cancel_msg = ExecuteMsg::CancelOrder { order_id: 1 };
+    let err = router
+        .execute_contract(executor.clone(), amm_addr.clone(), &cancel_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &cancel_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(995_000 + 19_724)
+    );
+
+    // Expired orders are refunded by the executor
+    let block_height = router.block_info().height;
+    let place_msg = ExecuteMsg::PlaceOrder {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::new(2_000),
+        expiration: Some(Expiration::AtHeight(block_height + 1)),
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &place_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    router.update_block(|block| block.height += 1);
+    router
+        .execute_contract(executor, amm_addr.clone(), &execute_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &owner, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(995_000 + 19_724)
+    );
+    let orders: OrdersResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::OrdersByOwner {
+                owner: owner.to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert!(orders.orders.is_empty());
+}
+
+#[test]
+fn simulate_swap() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let simulation: SimulateSwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::SimulateSwap {
+                input_token: TokenSelect::Token1,
+                input_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(simulation.output_amount, Uint128::new(9_871));
+    assert_eq!(simulation.lp_fee_amount, Uint128::new(20));
+    assert_eq!(simulation.protocol_fee_amount, Uint128::new(10));
+    assert_eq!(simulation.spot_price_before, Decimal256::one());
+    assert_eq!(
+        simulation.execution_price,
+        Decimal256::from_ratio(9_871u128, 10_000u128)
+    );
+    // 9_970 would be received at the spot price after fees
+    assert_eq!(
+        simulation.price_impact,
+        Decimal::from_ratio(9_970u128 - 9_871, 9_970u128)
+    );
+
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10_000),
+        min_output: simulation.output_amount,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_009_990));
+    assert_eq!(info.token2_reserve, Uint128::new(990_129));
+    assert_eq!(
+        simulation.spot_price_after,
+        Decimal256::from_ratio(info.token2_reserve, info.token1_reserve)
+    );
+
+    let simulation: SimulateSwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::SimulateSwap {
+                input_token: TokenSelect::Token2,
+                input_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        simulation.spot_price_before,
+        Decimal256::from_ratio(info.token1_reserve, info.token2_reserve)
+    );
+    assert!(simulation.spot_price_after < simulation.spot_price_before);
+}
+
+#[test]
+fn reverse_simulate_swap() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.2").unwrap(),
+        Decimal::from_str("0.1").unwrap(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let simulation: ReverseSimulateSwapResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::ReverseSimulateSwap {
+                output_token: TokenSelect::Token2,
+                output_amount: Uint128::new(9_871),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        simulation,
+        ReverseSimulateSwapResponse {
+            input_amount: Uint128::new(10_000),
+            lp_fee_amount: Uint128::new(20),
+            protocol_fee_amount: Uint128::new(10),
+        }
+    );
+
+    // The rounded up input buys at least the requested output
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: simulation.input_amount,
+        min_output: Uint128::new(9_871),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let err = router
+        .wrap()
+        .query_wasm_smart::<ReverseSimulateSwapResponse>(
+            &amm_addr,
+            &QueryMsg::ReverseSimulateSwap {
+                output_token: TokenSelect::Token1,
+                output_amount: Uint128::new(1_009_990),
+            },
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Output amount 1009990 must be less than the output reserve 1009990"));
+}
+
+#[test]
+fn ownership_transfer() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let new_owner = Addr::unchecked("new_owner");
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(5000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.to_string()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+    let get_proposal = |router: &App| -> Option<OwnershipProposal> {
+        let response: OwnershipProposalResponse = router
+            .wrap()
+            .query_wasm_smart(&amm_addr, &QueryMsg::OwnershipProposal {})
+            .unwrap();
+        response.proposal
+    };
+
+    let err = router
+        .execute_contract(
+            new_owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::NoOwnershipProposal {},
+        err.downcast().unwrap()
+    );
+
+    let block_height = router.block_info().height;
+    let propose_msg = ExecuteMsg::ProposeNewOwner {
+        owner: new_owner.to_string(),
+        expiry: Some(Expiration::AtHeight(block_height + 10)),
+    };
+    let err = router
+        .execute_contract(new_owner.clone(), amm_addr.clone(), &propose_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &propose_msg, &[])
+        .unwrap();
+    assert_eq!(
+        get_proposal(&router),
+        Some(OwnershipProposal {
+            owner: new_owner.clone(),
+            expiry: Some(Expiration::AtHeight(block_height + 10)),
+        })
+    );
+
+    // The owner does not change until the proposal is accepted
+    assert_eq!(
+        get_fee(&router, &amm_addr).owner.unwrap(),
+        owner.to_string()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::CancelOwnershipProposal {},
+            &[],
+        )
+        .unwrap();
+    assert_eq!(get_proposal(&router), None);
+
+    // Expired proposals can not be accepted
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &propose_msg, &[])
+        .unwrap();
+    router.update_block(|block| block.height += 10);
+    let err = router
+        .execute_contract(
+            new_owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OwnershipProposalExpired {},
+        err.downcast().unwrap()
+    );
+
+    let propose_msg = ExecuteMsg::ProposeNewOwner {
+        owner: new_owner.to_string(),
+        expiry: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &propose_msg, &[])
+        .unwrap();
+    let err = router
+        .execute_contract(
+            Addr::unchecked("anyone"),
+            amm_addr.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            new_owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        get_fee(&router, &amm_addr).owner.unwrap(),
+        new_owner.to_string()
+    );
+    assert_eq!(get_proposal(&router), None);
+
+    // The previous owner lost its rights
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RenounceOwnership {},
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            new_owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RenounceOwnership {},
+            &[],
+        )
+        .unwrap();
+    assert_eq!(get_fee(&router, &amm_addr).owner, None);
+    let err = router
+        .execute_contract(new_owner, amm_addr.clone(), &propose_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+}
+
+#[test]
+fn config_timelock() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(5000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.to_string()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+    let update_config_msg =
+        |lp_fee_percent: &str, config_timelock: Option<u64>| ExecuteMsg::UpdateConfig {
+            protocol_fee_recipient: owner.to_string(),
+            lp_fee_percent: Decimal::from_str(lp_fee_percent).unwrap(),
+            protocol_fee_percent: Decimal::zero(),
+            fee_switch: None,
+            config_timelock,
+        };
+    let get_pending_config = |router: &App| -> PendingConfigResponse {
+        router
+            .wrap()
+            .query_wasm_smart(&amm_addr, &QueryMsg::PendingConfig {})
+            .unwrap()
+    };
+
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg("0.3", Some(31 * 86_400)),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ConfigTimelockTooLong { max: 30 * 86_400 },
+        err.downcast().unwrap()
+    );
+
+    // Without a timelock the update, including the new timelock, applies right away
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg("0.3", Some(86_400)),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        get_pending_config(&router),
+        PendingConfigResponse {
+            config_timelock: 86_400,
+            pending_config: None,
+        }
+    );
+
+    // Updates are queued on top of each other
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg("0.5", None),
+            &[],
+        )
+        .unwrap();
+    let dynamic_fee = DynamicFeeConfig {
+        min_fee_percent: Decimal::from_str("0.1").unwrap(),
+        max_fee_percent: Decimal::from_str("0.9").unwrap(),
+        max_fee_volatility: Decimal::percent(10),
+        volatility_window: 100,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdateDynamicFee {
+                dynamic_fee: Some(dynamic_fee.clone()),
+            },
+            &[],
+        )
+        .unwrap();
+    let effective_time = router.block_info().time.seconds() + 86_400;
+    let pending_config = get_pending_config(&router).pending_config.unwrap();
+    assert_eq!(
+        pending_config.fees.lp_fee_percent,
+        Decimal::from_str("0.5").unwrap()
+    );
+    assert_eq!(pending_config.dynamic_fee, Some(dynamic_fee.clone()));
+    assert_eq!(pending_config.effective_time, effective_time);
+    assert_eq!(
+        get_fee(&router, &amm_addr).lp_fee_percent,
+        Decimal::from_str("0.3").unwrap()
+    );
+
+    let apply_msg = ExecuteMsg::ApplyPendingConfig {};
+    let err = router
+        .execute_contract(Addr::unchecked("anyone"), amm_addr.clone(), &apply_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::PendingConfigNotEffective { effective_time },
+        err.downcast().unwrap()
+    );
+
+    let cancel_msg = ExecuteMsg::CancelPendingConfig {};
+    let err = router
+        .execute_contract(
+            Addr::unchecked("anyone"),
+            amm_addr.clone(),
+            &cancel_msg,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &cancel_msg, &[])
+        .unwrap();
+    assert_eq!(get_pending_config(&router).pending_config, None);
+    let err = router
+        .execute_contract(Addr::unchecked("anyone"), amm_addr.clone(), &apply_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NoPendingConfig {}, err.downcast().unwrap());
+
+    // Anyone can apply the config once the timelock has passed
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_config_msg("0.5", None),
+            &[],
+        )
+        .unwrap();
+    router.update_block(|block| block.time = block.time.plus_seconds(86_400));
+    router
+        .execute_contract(Addr::unchecked("anyone"), amm_addr.clone(), &apply_msg, &[])
+        .unwrap();
+    assert_eq!(
+        get_fee(&router, &amm_addr).lp_fee_percent,
+        Decimal::from_str("0.5").unwrap()
+    );
+    assert_eq!(get_pending_config(&router).pending_config, None);
+    let response: DynamicFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::DynamicFee {})
+        .unwrap();
+    assert_eq!(response.dynamic_fee, None);
+}
+
+#[test]
+fn pause_flags() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(100_000),
+        min_liquidity: Uint128::new(99_000),
+        max_token2: Uint128::new(100_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let pause_msg = ExecuteMsg::UpdatePauseFlags {
+        pause_flags: PauseFlags {
+            remove_liquidity: true,
+            pass_through_swap: true,
+            ..PauseFlags::default()
+        },
+    };
+    let err = router
+        .execute_contract(Addr::unchecked("anyone"), amm_addr.clone(), &pause_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnauthorizedPoolFreeze {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &pause_msg, &[])
+        .unwrap();
+
+    let remove_liquidity_msg = ExecuteMsg::RemoveLiquidity {
+        amount: Uint128::new(1_000),
+        min_token1: Uint128::zero(),
+        min_token2: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &remove_liquidity_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::RemoveLiquidity
+        },
+        err.downcast().unwrap()
+    );
+    let pass_through_msg = ExecuteMsg::PassThroughSwap {
+        output_amm_address: "other_amm".to_string(),
+        input_token: TokenSelect::Token1,
+        input_token_amount: Uint128::new(1_000),
+        output_min_token: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &pass_through_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::PassThroughSwap
+        },
+        err.downcast().unwrap()
+    );
+
+    // Deposits and swaps are still possible
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::AddLiquidity {
+                token1_amount: Uint128::new(100_000),
+                min_liquidity: Uint128::new(1),
+                max_token2: Uint128::new(100_001),
+                expiration: None,
+            },
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // Freezing pauses deposits and swaps and keeps the withdrawal flag
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::FreezeDeposits { freeze: true },
+            &[],
+        )
+        .unwrap();
+    let pause_flags: PauseFlags = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::PauseFlags {})
+        .unwrap();
+    assert_eq!(
+        pause_flags,
+        PauseFlags {
+            add_liquidity: true,
+            remove_liquidity: true,
+            swap: true,
+            pass_through_swap: true,
+        }
+    );
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm_addr.to_string(),
+        amount: Uint128::new(1_000),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            min_output: Uint128::zero(),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), cw20_token.addr(), &send_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::Swap
+        },
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdatePauseFlags {
+                pause_flags: PauseFlags::default(),
+            },
+            &[],
+        )
+        .unwrap();
+    let lp_token = Cw20Contract(Addr::unchecked(
+        get_info(&router, &amm_addr).lp_token_address,
+    ));
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    router
+        .execute_contract(owner, amm_addr, &remove_liquidity_msg, &[])
+        .unwrap();
+}
+
+#[test]
+fn guardian_pause() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let guardian = Addr::unchecked("guardian");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::AddLiquidity {
+                token1_amount: Uint128::new(100_000),
+                min_liquidity: Uint128::new(99_000),
+                max_token2: Uint128::new(100_000),
+                expiration: None,
+            },
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let guardian_pause_msg = |pause_flags: PauseFlags| ExecuteMsg::GuardianPause { pause_flags };
+    let swap_pause = PauseFlags {
+        swap: true,
+        ..PauseFlags::default()
+    };
+
+    // Only the owner sets the guardian, with a capped pause duration
+    let update_guardian_msg = |max_pause_duration: u64| ExecuteMsg::UpdateGuardian {
+        guardian: Some(guardian.to_string()),
+        max_pause_duration,
+    };
+    let err = router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &update_guardian_msg(3_600),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_guardian_msg(8 * 86_400),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::GuardianPauseTooLong { max: 7 * 86_400 },
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &update_guardian_msg(3_600),
+            &[],
+        )
+        .unwrap();
+
+    // Only the guardian can pause
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &guardian_pause_msg(swap_pause.clone()),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &guardian_pause_msg(swap_pause.clone()),
+            &[],
+        )
+        .unwrap();
+    let pause_started = router.block_info().time.seconds();
+
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(1_000),
+        min_output: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::Swap
+        },
+        err.downcast().unwrap()
+    );
+
+    // Pausing more operations later adds them without extending the pause
+    router.update_block(|b| b.time = b.time.plus_seconds(1_800));
+    router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &guardian_pause_msg(PauseFlags {
+                add_liquidity: true,
+                ..PauseFlags::default()
+            }),
+            &[],
+        )
+        .unwrap();
+    let guardian_info: GuardianResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Guardian {})
+        .unwrap();
+    assert_eq!(
+        guardian_info,
+        GuardianResponse {
+            guardian: Some(GuardianConfig {
+                guardian: guardian.clone(),
+                max_pause_duration: 3_600,
+            }),
+            pause: Some(GuardianPause {
+                pause_flags: PauseFlags {
+                    add_liquidity: true,
+                    swap: true,
+                    ..PauseFlags::default()
+                },
+                expires_at: pause_started + 3_600,
+            }),
+            pause_used: false,
+        }
+    );
+    let pause_flags: PauseFlags = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::PauseFlags {})
+        .unwrap();
+    assert!(pause_flags.add_liquidity && pause_flags.swap);
+
+    // The guardian can not unpause
+    let err = router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdatePauseFlags {
+                pause_flags: PauseFlags::default(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnauthorizedPoolFreeze {},
+        err.downcast().unwrap()
+    );
+
+    // The pause expires on its own
+    router.update_block(|b| b.time = b.time.plus_seconds(1_800));
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let pause_flags: PauseFlags = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::PauseFlags {})
+        .unwrap();
+    assert_eq!(pause_flags, PauseFlags::default());
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ExtendGuardianPause {
+                expires_at: pause_started + 7_200,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::NoGuardianPause {}, err.downcast().unwrap());
+
+    // The guardian can not pause again until the owner resets its pause
+    let err = router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &guardian_pause_msg(swap_pause.clone()),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::GuardianPauseUsed {}, err.downcast().unwrap());
+    let guardian_info: GuardianResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::Guardian {})
+        .unwrap();
+    assert_eq!(guardian_info.pause, None);
+    assert!(guardian_info.pause_used);
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdatePauseFlags {
+                pause_flags: PauseFlags::default(),
+            },
+            &[],
+        )
+        .unwrap();
+
+    // The owner can extend a new pause beyond the maximum duration
+    router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &guardian_pause_msg(swap_pause),
+            &[],
+        )
+        .unwrap();
+    let now = router.block_info().time.seconds();
+    let err = router
+        .execute_contract(
+            guardian.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ExtendGuardianPause {
+                expires_at: now + 86_400,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ExtendGuardianPause { expires_at: now },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidGuardianPauseExpiry {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::ExtendGuardianPause {
+                expires_at: now + 86_400,
+            },
+            &[],
+        )
+        .unwrap();
+    router.update_block(|b| b.time = b.time.plus_seconds(3_600));
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OperationPaused {
+            operation: PoolOperation::Swap
+        },
+        err.downcast().unwrap()
+    );
+
+    // Owner pause flags replace the guardian pause
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdatePauseFlags {
+                pause_flags: PauseFlags::default(),
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &coins(1_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    // A removed guardian can not pause anymore
+    router
+        .execute_contract(
+            owner,
+            amm_addr.clone(),
+            &ExecuteMsg::UpdateGuardian {
+                guardian: None,
+                max_pause_duration: 0,
+            },
+            &[],
+        )
+        .unwrap();
+    let err = router
+        .execute_contract(
+            guardian,
+            amm_addr,
+            &guardian_pause_msg(PauseFlags::default()),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+}
+
+#[test]
+fn roles() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let fee_manager = Addr::unchecked("fee_manager");
+    let pauser = Addr::unchecked("pauser");
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(5_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let grant_role_msg = |role: Role, address: &Addr| ExecuteMsg::GrantRole {
+        role,
+        address: address.to_string(),
+    };
+    let update_config_msg = ExecuteMsg::UpdateConfig {
+        lp_fee_percent: Decimal::from_str("0.2").unwrap(),
+        protocol_fee_percent: Decimal::from_str("0.1").unwrap(),
+        protocol_fee_recipient: "protocol".to_string(),
+        fee_switch: None,
+        config_timelock: None,
+    };
+    let freeze_msg = ExecuteMsg::FreezeDeposits { freeze: true };
+
+    // Only the owner grants roles, and not its own
+    let err = router
+        .execute_contract(
+            fee_manager.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::FeeManager, &fee_manager),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::Owner, &fee_manager),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::OwnerRoleNotGrantable {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::FeeManager, &fee_manager),
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::Pauser, &pauser),
+            &[],
+        )
+        .unwrap();
+
+    let query_roles = |router: &App, role: Role| -> Vec<Addr> {
+        let res: RolesResponse = router
+            .wrap()
+            .query_wasm_smart(
+                &amm_addr,
+                &QueryMsg::Roles {
+                    role,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        res.addresses
+    };
+    assert_eq!(query_roles(&router, Role::Owner), vec![owner.clone()]);
+    assert_eq!(
+        query_roles(&router, Role::FeeManager),
+        vec![fee_manager.clone()]
+    );
+    assert_eq!(query_roles(&router, Role::Pauser), vec![pauser.clone()]);
+    assert!(query_roles(&router, Role::ConfigAdmin).is_empty());
+
+    // Every role is limited to its own messages
+    let err = router
+        .execute_contract(pauser.clone(), amm_addr.clone(), &update_config_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            fee_manager.clone(),
+            amm_addr.clone(),
+            &update_config_msg,
+            &[],
+        )
+        .unwrap();
+    let fee = get_fee(&router, &amm_addr);
+    assert_eq!(fee.lp_fee_percent, Decimal::from_str("0.2").unwrap());
+
+    // Changing the recipient also needs the config admin role
+    let update_recipient_msg = ExecuteMsg::UpdateConfig {
+        lp_fee_percent: Decimal::from_str("0.2").unwrap(),
+        protocol_fee_percent: Decimal::from_str("0.1").unwrap(),
+        protocol_fee_recipient: fee_manager.to_string(),
+        fee_switch: None,
+        config_timelock: None,
+    };
+    let err = router
+        .execute_contract(
+            fee_manager.clone(),
+            amm_addr.clone(),
+            &update_recipient_msg,
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::ConfigAdmin, &fee_manager),
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            fee_manager.clone(),
+            amm_addr.clone(),
+            &update_recipient_msg,
+            &[],
+        )
+        .unwrap();
+    let fee = get_fee(&router, &amm_addr);
+    assert_eq!(fee.protocol_fee_recipient, fee_manager.to_string());
+
+    let err = router
+        .execute_contract(fee_manager.clone(), amm_addr.clone(), &freeze_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnauthorizedPoolFreeze {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(pauser.clone(), amm_addr.clone(), &freeze_msg, &[])
+        .unwrap();
+    let pause_flags: PauseFlags = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::PauseFlags {})
+        .unwrap();
+    assert!(pause_flags.add_liquidity);
+
+    let err = router
+        .execute_contract(
+            pauser.clone(),
+            amm_addr.clone(),
+            &grant_role_msg(Role::Pauser, &fee_manager),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // Renounced and revoked roles are gone
+    let renounce_msg = ExecuteMsg::RenounceRole { role: Role::Pauser };
+    router
+        .execute_contract(pauser.clone(), amm_addr.clone(), &renounce_msg, &[])
+        .unwrap();
+    let err = router
+        .execute_contract(pauser.clone(), amm_addr.clone(), &renounce_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MissingRole {
+            role: Role::Pauser,
+            address: pauser.to_string()
+        },
+        err.downcast().unwrap()
+    );
+    let err = router
+        .execute_contract(pauser, amm_addr.clone(), &freeze_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnauthorizedPoolFreeze {},
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RevokeRole {
+                role: Role::FeeManager,
+                address: fee_manager.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(query_roles(&router, Role::FeeManager).is_empty());
+    let err = router
+        .execute_contract(fee_manager, amm_addr.clone(), &update_config_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // The owner holds every role
+    router
+        .execute_contract(owner, amm_addr, &update_config_msg, &[])
+        .unwrap();
+}
+
+#[test]
+fn sync_and_skim() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let skimmer = Addr::unchecked("skimmer");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let cw20_token = create_cw20(
+        &mut router,
+        &owner,
+        "token".to_string(),
+        "CWTOKEN".to_string(),
+        Uint128::new(2_000_000),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::new(999_000),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+
+    let query_drift = |router: &App| -> ReserveDriftResponse {
+        router
+            .wrap()
+            .query_wasm_smart(&amm_addr, &QueryMsg::ReserveDrift {})
+            .unwrap()
+    };
+    let no_drift = |reserve: u128, escrowed: u128| TokenDrift {
+        reserve: Uint128::new(reserve),
+        escrowed: Uint128::new(escrowed),
+        balance: Uint128::new(reserve + escrowed),
+        excess: Uint128::zero(),
+        deficit: Uint128::zero(),
+    };
+    assert_eq!(
+        query_drift(&router),
+        ReserveDriftResponse {
+            token1: no_drift(1_000_000, 0),
+            token2: no_drift(1_000_000, 0),
+        }
+    );
+
+    // Tokens sent without a message are not part of the reserves
+    router
+        .send_tokens(
+            owner.clone(),
+            amm_addr.clone(),
+            &coins(500, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let transfer_msg = Cw20ExecuteMsg::Transfer {
+        recipient: amm_addr.to_string(),
+        amount: Uint128::new(300),
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &transfer_msg, &[])
+        .unwrap();
+
+    // Tokens escrowed for an open order are not excess
+    let place_msg = ExecuteMsg::PlaceOrder {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::new(11_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &place_msg,
+            &coins(10_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    assert_eq!(
+        query_drift(&router),
+        ReserveDriftResponse {
+            token1: TokenDrift {
+                reserve: Uint128::new(1_000_000),
+                escrowed: Uint128::new(10_000),
+                balance: Uint128::new(1_010_500),
+                excess: Uint128::new(500),
+                deficit: Uint128::zero(),
+            },
+            token2: TokenDrift {
+                reserve: Uint128::new(1_000_000),
+                escrowed: Uint128::zero(),
+                balance: Uint128::new(1_000_300),
+                excess: Uint128::new(300),
+                deficit: Uint128::zero(),
+            },
+        }
+    );
+
+    // Anyone can skim the excess to a recipient
+    let skim_msg = ExecuteMsg::Skim {
+        recipient: skimmer.to_string(),
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &skim_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &skimmer, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(500)
+    );
+    assert_eq!(
+        cw20_token.balance(&router, skimmer.clone()).unwrap(),
+        Uint128::new(300)
+    );
+    assert_eq!(
+        query_drift(&router),
+        ReserveDriftResponse {
+            token1: no_drift(1_000_000, 10_000),
+            token2: no_drift(1_000_000, 0),
+        }
+    );
+
+    // Skimming again sends nothing
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &skim_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &skimmer, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(500)
+    );
+
+    // Sync adds the excess to the reserves
+    router
+        .send_tokens(
+            owner.clone(),
+            amm_addr.clone(),
+            &coins(700, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    router
+        .execute_contract(skimmer, amm_addr.clone(), &ExecuteMsg::Sync {}, &[])
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_000_700));
+    assert_eq!(info.token2_reserve, Uint128::new(1_000_000));
+    assert_eq!(
+        query_drift(&router),
+        ReserveDriftResponse {
+            token1: no_drift(1_000_700, 10_000),
+            token2: no_drift(1_000_000, 0),
+        }
+    );
+
+    // Cancelling the order releases the escrow
+    router
+        .execute_contract(
+            owner,
+            amm_addr.clone(),
+            &ExecuteMsg::CancelOrder { order_id: 1 },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        query_drift(&router),
+        ReserveDriftResponse {
+            token1: no_drift(1_000_700, 0),
+            token2: no_drift(1_000_000, 0),
+        }
+    );
+}
+
+#[test]
+fn fee_on_transfer_tokens() {
+    let mut router = mock_app();
+
+    const NATIVE_TOKEN_DENOM: &str = "juno";
+
+    let owner = Addr::unchecked("owner");
+    let user = Addr::unchecked("user");
+    let funds = coins(2_000_000, NATIVE_TOKEN_DENOM);
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let taxed_cw20_id = router.store_code(contract_taxed_cw20());
+    let msg = cw20_base::msg::InstantiateMsg {
+        name: "taxed".to_string(),
+        symbol: "TAXED".to_string(),
+        decimals: 6,
+        initial_balances: vec![Cw20Coin {
+            address: owner.to_string(),
+            amount: Uint128::new(2_000_000),
+        }],
+        mint: None,
+        marketing: None,
+    };
+    let cw20_token = Cw20Contract(
+        router
+            .instantiate_contract(taxed_cw20_id, owner.clone(), &msg, &[], "TAXED", None)
+            .unwrap(),
+    );
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(NATIVE_TOKEN_DENOM.into()),
+        Denom::Cw20(cw20_token.addr()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        "protocol".to_string(),
+    );
+
+    // Only the config admin can flag tokens, and only cw20 tokens
+    let flag_msg = ExecuteMsg::UpdateFeeOnTransfer {
+        token: TokenSelect::Token2,
+        fee_on_transfer: true,
+    };
+    let err = router
+        .execute_contract(user.clone(), amm_addr.clone(), &flag_msg, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdateFeeOnTransfer {
+                token: TokenSelect::Token1,
+                fee_on_transfer: true,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::NativeFeeOnTransfer {},
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &flag_msg, &[])
+        .unwrap();
+    let flags: FeeOnTransferResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::FeeOnTransfer {})
+        .unwrap();
+    assert_eq!(
+        flags,
+        FeeOnTransferResponse {
+            token1: false,
+            token2: true
+        }
+    );
+
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(2_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), cw20_token.addr(), &allowance_msg, &[])
+        .unwrap();
+
+    let query_drift = |router: &App| -> ReserveDriftResponse {
+        router
+            .wrap()
+            .query_wasm_smart(&amm_addr, &QueryMsg::ReserveDrift {})
+            .unwrap()
+    };
+
+    // Only 990_000 of the token 2 deposit arrives
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::zero(),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(1_000_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_000_000));
+    assert_eq!(info.token2_reserve, Uint128::new(990_000));
+    assert_eq!(info.lp_token_supply, Uint128::new(994_987));
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+    assert_eq!(
+        lp_token.balance(&router, owner.clone()).unwrap(),
+        Uint128::new(993_987)
+    );
+    assert!(query_drift(&router).token2.excess.is_zero());
+
+    // The swap is priced with the 9_900 tokens that arrive
+    let swap_msg = ExecuteMsg::Swap {
+        input_token: TokenSelect::Token2,
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::new(9_872),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &swap_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::SwapMinError {
+            min: Uint128::new(9_872),
+            available: Uint128::new(9_871)
+        },
+        err.downcast().unwrap()
+    );
+    let swap_msg = ExecuteMsg::SwapAndSendTo {
+        input_token: TokenSelect::Token2,
+        input_amount: Uint128::new(10_000),
+        recipient: user.to_string(),
+        min_token: Uint128::new(9_871),
+        expiration: None,
+    };
+    router
+        .execute_contract(owner.clone(), amm_addr.clone(), &swap_msg, &[])
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &user, NATIVE_TOKEN_DENOM.to_string()).amount,
+        Uint128::new(9_871)
+    );
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(990_129));
+    assert_eq!(info.token2_reserve, Uint128::new(999_900));
+
+    // 100_987 token 2 are required for the ratio, 99_978 arrive and bound the liquidity
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(100_000),
+        min_liquidity: Uint128::zero(),
+        max_token2: Uint128::new(200_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &coins(100_000, NATIVE_TOKEN_DENOM),
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(1_090_129));
+    assert_eq!(info.token2_reserve, Uint128::new(1_099_878));
+    assert_eq!(info.lp_token_supply, Uint128::new(1_094_473));
+    let drift = query_drift(&router);
+    assert!(drift.token1.excess.is_zero() && drift.token1.deficit.is_zero());
+    assert!(drift.token2.excess.is_zero() && drift.token2.deficit.is_zero());
+
+    // Deposits that can not be measured are rejected
+    let send_msg = Cw20ExecuteMsg::Send {
+        contract: amm_addr.to_string(),
+        amount: Uint128::new(10_000),
+        msg: to_binary(&Cw20HookMsg::Swap {
+            min_output: Uint128::zero(),
+            expiration: None,
+        })
+        .unwrap(),
+    };
+    let err = router
+        .execute_contract(owner.clone(), cw20_token.addr(), &send_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FeeOnTransferUnsupported {},
+        err.downcast().unwrap()
+    );
+    let zap_in_msg = ExecuteMsg::ZapIn {
+        input_token: TokenSelect::Token2,
+        amount: Uint128::new(10_000),
+        min_liquidity: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(owner.clone(), amm_addr.clone(), &zap_in_msg, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::FeeOnTransferUnsupported {},
+        err.downcast().unwrap()
+    );
+
+    router
+        .execute_contract(
+            owner,
+            amm_addr.clone(),
+            &ExecuteMsg::UpdateFeeOnTransfer {
+                token: TokenSelect::Token2,
+                fee_on_transfer: false,
+            },
+            &[],
+        )
+        .unwrap();
+    let flags: FeeOnTransferResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::FeeOnTransfer {})
+        .unwrap();
+    assert!(!flags.token2);
+}
+
+#[test]
+fn multi_asset_pool() {
+    let mut router = mock_app();
+
+    const ATOM_DENOM: &str = "atom";
+    const JUNO_DENOM: &str = "juno";
+    const OSMO_DENOM: &str = "osmo";
+
+    let owner = Addr::unchecked("owner");
+    let recipient = Addr::unchecked("recipient");
+    let funds = vec![
+        Coin {
+            denom: ATOM_DENOM.into(),
+            amount: Uint128::new(10_000_000),
+        },
+        Coin {
+            denom: JUNO_DENOM.into(),
+            amount: Uint128::new(10_000_000),
+        },
+        Coin {
+            denom: OSMO_DENOM.into(),
+            amount: Uint128::new(10_000_000),
+        },
+    ];
+    router.borrow_mut().init_modules(|router, _, storage| {
+        router.bank.init_balance(storage, &owner, funds).unwrap()
+    });
+
+    let amm_id = router.store_code(contract_amm());
+    let lp_token_id = router.store_code(contract_cw20());
+    let mut msg = InstantiateMsg {
+        token1_denom: Denom::Native(ATOM_DENOM.into()),
+        token2_denom: Denom::Native(JUNO_DENOM.into()),
+        lp_token_code_id: lp_token_id,
+        owner: Some(owner.to_string()),
+        lp_fee_percent: Decimal::from_str("0.3").unwrap(),
+        protocol_fee_percent: Decimal::zero(),
+        protocol_fee_recipient: owner.to_string(),
+        pool_type: PoolType::ConstantProduct,
+        amp: None,
+        weights: None,
+        extra_denoms: vec![Denom::Native(OSMO_DENOM.into())],
+    };
+
+    // Only multi asset pools take extra denoms, every asset once and at most four of them
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ExtraDenomsUnsupported {},
+        err.downcast().unwrap()
+    );
+
+    msg.pool_type = PoolType::MultiAsset;
+    msg.extra_denoms = vec![Denom::Native(ATOM_DENOM.into())];
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::DuplicateAsset {
+            denom: "native:atom".to_string()
+        },
+        err.downcast().unwrap()
+    );
+
+    msg.extra_denoms = ["a", "b", "c"]
+        .iter()
+        .map(|denom| Denom::Native(denom.to_string()))
+        .collect();
+    let err = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::TooManyAssets { max: 4 },
+        err.downcast().unwrap()
+    );
+
+    msg.extra_denoms = vec![Denom::Native(OSMO_DENOM.into())];
+    let amm_addr = router
+        .instantiate_contract(amm_id, owner.clone(), &msg, &[], "amm", None)
+        .unwrap();
+
+    // The two token messages and queries are not available
+    let add_liquidity_msg = ExecuteMsg::AddLiquidity {
+        token1_amount: Uint128::new(1_000_000),
+        min_liquidity: Uint128::zero(),
+        max_token2: Uint128::new(1_000_000),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_liquidity_msg,
+            &[
+                Coin::new(1_000_000, ATOM_DENOM),
+                Coin::new(1_000_000, JUNO_DENOM),
+            ],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::TwoTokenPoolRequired {},
+        err.downcast().unwrap()
+    );
+    router
+        .wrap()
+        .query_wasm_smart::<InfoResponse>(&amm_addr, &QueryMsg::Info {})
+        .unwrap_err();
+
+    let asset_amount = |denom: &str, amount: u128| AssetAmount {
+        denom: Denom::Native(denom.into()),
+        amount: Uint128::new(amount),
+    };
+    let query_assets = |router: &App| -> AssetsResponse {
+        router
+            .wrap()
+            .query_wasm_smart(&amm_addr, &QueryMsg::Assets {})
+            .unwrap()
+    };
+    let reserves = |router: &App| -> Vec<u128> {
+        query_assets(router)
+            .assets
+            .iter()
+            .map(|asset| asset.reserve.u128())
+            .collect()
+    };
+
+    // The first deposit must include every asset
+    let add_assets_msg = ExecuteMsg::AddAssets {
+        amounts: vec![
+            asset_amount(ATOM_DENOM, 1_000_000),
+            asset_amount(JUNO_DENOM, 1_000_000),
+        ],
+        min_liquidity: Uint128::zero(),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_assets_msg,
+            &[
+                Coin::new(1_000_000, ATOM_DENOM),
+                Coin::new(1_000_000, JUNO_DENOM),
+            ],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InsufficientInitialLiquidity {
+            minimum: Uint128::new(1_000)
+        },
+        err.downcast().unwrap()
+    );
+
+    let add_assets_msg = ExecuteMsg::AddAssets {
+        amounts: vec![
+            asset_amount(ATOM_DENOM, 1_000_000),
+            asset_amount(JUNO_DENOM, 1_000_000),
+            asset_amount(OSMO_DENOM, 1_000_000),
+        ],
+        min_liquidity: Uint128::new(999_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_assets_msg,
+            &[
+                Coin::new(1_000_000, ATOM_DENOM),
+                Coin::new(1_000_000, JUNO_DENOM),
+                Coin::new(1_000_000, OSMO_DENOM),
+            ],
+        )
+        .unwrap();
+    let assets = query_assets(&router);
+    assert_eq!(assets.lp_token_supply, Uint128::new(1_000_000));
+    assert_eq!(reserves(&router), vec![1_000_000, 1_000_000, 1_000_000]);
+    let lp_token = Cw20Contract(Addr::unchecked(assets.lp_token_address));
+    assert_eq!(
+        lp_token.balance(&router, owner.clone()).unwrap(),
+        Uint128::new(999_000)
+    );
+
+    // Any pair can be swapped at the constant product price
+    let prices: AssetPricesResponse = router
+        .wrap()
+        .query_wasm_smart(
+            &amm_addr,
+            &QueryMsg::AssetPrices {
+                input_denom: Denom::Native(ATOM_DENOM.into()),
+                input_amount: Uint128::new(10_000),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        prices.prices,
+        vec![
+            asset_amount(JUNO_DENOM, 9_871),
+            asset_amount(OSMO_DENOM, 9_871)
+        ]
+    );
+
+    let swap_msg = |min_output: u128| ExecuteMsg::SwapAssets {
+        input_denom: Denom::Native(ATOM_DENOM.into()),
+        output_denom: Denom::Native(JUNO_DENOM.into()),
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::new(min_output),
+        recipient: Some(recipient.to_string()),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg(9_872),
+            &[Coin::new(10_000, ATOM_DENOM)],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::SwapMinError {
+            min: Uint128::new(9_872),
+            available: Uint128::new(9_871)
+        },
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg(9_871),
+            &[Coin::new(10_000, ATOM_DENOM)],
+        )
+        .unwrap();
+    assert_eq!(
+        bank_balance(&mut router, &recipient, JUNO_DENOM.to_string()).amount,
+        Uint128::new(9_871)
+    );
+    assert_eq!(reserves(&router), vec![1_010_000, 990_129, 1_000_000]);
+
+    let swap_msg = ExecuteMsg::SwapAssets {
+        input_denom: Denom::Native(ATOM_DENOM.into()),
+        output_denom: Denom::Native("foo".into()),
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::zero(),
+        recipient: None,
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &[Coin::new(10_000, ATOM_DENOM)],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnknownAsset {
+            denom: "native:foo".to_string()
+        },
+        err.downcast().unwrap()
+    );
+
+    // A single asset deposit pays the fee on the part that is not proportional
+    let add_assets_msg = |min_liquidity: u128| ExecuteMsg::AddAssets {
+        amounts: vec![asset_amount(OSMO_DENOM, 100_000)],
+        min_liquidity: Uint128::new(min_liquidity),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_assets_msg(32_187),
+            &[Coin::new(100_000, OSMO_DENOM)],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MinLiquidityError {
+            min_liquidity: Uint128::new(32_187),
+            liquidity_available: Uint128::new(32_186)
+        },
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_assets_msg(32_186),
+            &[Coin::new(100_000, OSMO_DENOM)],
+        )
+        .unwrap();
+    assert_eq!(reserves(&router), vec![1_010_000, 990_129, 1_100_000]);
+    assert_eq!(
+        query_assets(&router).lp_token_supply,
+        Uint128::new(1_032_186)
+    );
+
+    // Proportional withdrawals return the same share of every asset
+    let allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: amm_addr.to_string(),
+        amount: Uint128::new(1_000_000),
+        expires: None,
+    };
+    router
+        .execute_contract(owner.clone(), lp_token.addr(), &allowance_msg, &[])
+        .unwrap();
+    let remove_assets_msg = |min_osmo: u128| ExecuteMsg::RemoveAssets {
+        amount: Uint128::new(103_218),
+        min_amounts: vec![asset_amount(OSMO_DENOM, min_osmo)],
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &remove_assets_msg(110_000),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MinAssetError {
+            denom: "native:osmo".to_string(),
+            min: Uint128::new(110_000),
+            available: Uint128::new(109_999)
+        },
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &remove_assets_msg(109_999),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(reserves(&router), vec![909_001, 891_117, 990_001]);
+    assert_eq!(query_assets(&router).lp_token_supply, Uint128::new(928_968));
+
+    // Withdrawing a single asset burns the liquidity of the imbalanced part plus the fee
+    let remove_imbalanced_msg = |max_liquidity: u128| ExecuteMsg::RemoveAssetsImbalanced {
+        amounts: vec![asset_amount(JUNO_DENOM, 50_000)],
+        max_liquidity: Uint128::new(max_liquidity),
+        expiration: None,
+    };
+    let err = router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &remove_imbalanced_msg(1),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::MaxLiquidityError {
+            max_liquidity: Uint128::new(1),
+            liquidity_required: Uint128::new(17_765)
+        },
+        err.downcast().unwrap()
+    );
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &remove_imbalanced_msg(17_765),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(reserves(&router), vec![909_001, 841_117, 990_001]);
+    assert_eq!(query_assets(&router).lp_token_supply, Uint128::new(911_203));
+    assert_eq!(
+        bank_balance(&mut router, &owner, JUNO_DENOM.to_string()).amount,
+        Uint128::new(9_149_012)
+    );
+
+    // Asset messages work on two token constant product pools along with the two token ones
+    let amm_addr = create_amm(
+        &mut router,
+        &owner,
+        Denom::Native(JUNO_DENOM.into()),
+        Denom::Native(ATOM_DENOM.into()),
+        Decimal::from_str("0.3").unwrap(),
+        Decimal::zero(),
+        owner.to_string(),
+    );
+    let add_assets_msg = ExecuteMsg::AddAssets {
+        amounts: vec![
+            asset_amount(ATOM_DENOM, 1_000_000),
+            asset_amount(JUNO_DENOM, 1_000_000),
+        ],
+        min_liquidity: Uint128::new(999_000),
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &add_assets_msg,
+            &[
+                Coin::new(1_000_000, ATOM_DENOM),
+                Coin::new(1_000_000, JUNO_DENOM),
+            ],
+        )
+        .unwrap();
+    let swap_msg = ExecuteMsg::SwapAssets {
+        input_denom: Denom::Native(ATOM_DENOM.into()),
+        output_denom: Denom::Native(JUNO_DENOM.into()),
+        input_amount: Uint128::new(10_000),
+        min_output: Uint128::new(9_871),
+        recipient: None,
+        expiration: None,
+    };
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &swap_msg,
+            &[Coin::new(10_000, ATOM_DENOM)],
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(990_129));
+    assert_eq!(info.token2_reserve, Uint128::new(1_010_000));
+    assert_eq!(info.lp_token_supply, Uint128::new(1_000_000));
+
+    // Withdrawals update the volatility of the dynamic fee. Rounding moves the price of the
+    // remaining 991 juno and 1010 atom from 1.02007 to 1.01917.
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::UpdateDynamicFee {
+                dynamic_fee: Some(DynamicFeeConfig {
+                    min_fee_percent: Decimal::from_str("0.1").unwrap(),
+                    max_fee_percent: Decimal::from_str("0.9").unwrap(),
+                    max_fee_volatility: Decimal::percent(10),
+                    volatility_window: 100,
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    let lp_token = Cw20Contract(Addr::unchecked(info.lp_token_address));
+    router
+        .execute_contract(
+            owner.clone(),
+            lp_token.addr(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: amm_addr.to_string(),
+                amount: Uint128::new(999_000),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    router
+        .execute_contract(
+            owner.clone(),
+            amm_addr.clone(),
+            &ExecuteMsg::RemoveAssets {
+                amount: Uint128::new(999_000),
+                min_amounts: vec![],
+                expiration: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let info = get_info(&router, &amm_addr);
+    assert_eq!(info.token1_reserve, Uint128::new(991));
+    assert_eq!(info.token2_reserve, Uint128::new(1_010));
+    let dynamic_fee: DynamicFeeResponse = router
+        .wrap()
+        .query_wasm_smart(&amm_addr, &QueryMsg::DynamicFee {})
+        .unwrap();
+    assert!(dynamic_fee.volatility > Decimal256::from_str("0.00087").unwrap());
+    assert!(dynamic_fee.volatility < Decimal256::from_str("0.00088").unwrap());
+}
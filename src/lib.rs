@@ -1,5 +1,10 @@
 pub mod contract;
+pub mod dynamic_fee;
 pub mod error;
 mod integration_test;
 pub mod msg;
+pub mod multi_asset;
+pub mod stableswap;
+pub mod staking;
 pub mod state;
+pub mod weighted;
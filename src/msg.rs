@@ -1,9 +1,16 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Decimal, Uint128};
+use cosmwasm_std::{
+    to_binary, Addr, Binary, CosmosMsg, Decimal, Decimal256, StdResult, Uint128, WasmMsg,
+};
 
-use cw20::{Denom, Expiration};
+use cw20::{Cw20ReceiveMsg, Denom, Expiration};
+
+use crate::state::{
+    DynamicFeeConfig, GuardianConfig, GuardianPause, LimitOrder, OwnershipProposal, PauseFlags,
+    PendingConfig, PoolType, RewardSchedule, Role, Token, Unbonding, Weights,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -15,6 +22,22 @@ pub struct InstantiateMsg {
     // NOTE: Fees percents are out of 100 e.g., 1 = 1%
     pub protocol_fee_percent: Decimal,
     pub lp_fee_percent: Decimal,
+    #[serde(default)]
+    pub pool_type: PoolType,
+    /// Amplification coefficient, required for stable pools
+    pub amp: Option<u64>,
+    /// Token weights, required for weighted pools
+    pub weights: Option<Weights>,
+    /// Assets of a multi asset pool besides token1 and token2. Without them the pool is
+    /// instantiated as a constant product pool.
+    #[serde(default)]
+    pub extra_denoms: Vec<Denom>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetAmount {
+    pub denom: Denom,
+    pub amount: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -44,7 +67,32 @@ pub enum ExecuteMsg {
         min_output: Uint128,
         expiration: Option<Expiration>,
     },
-    /// Chained swap converting A -> B and B -> C by leveraging two swap contracts
+    /// Swap for exactly `output_amount` of `output_token`, paying at most `max_input` of the
+    /// other token. Native input must be sent as `max_input`, the unused part is refunded.
+    SwapExactOut {
+        output_token: TokenSelect,
+        output_amount: Uint128,
+        max_input: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Add liquidity with only one of the tokens. Part of `amount` is swapped internally so the
+    /// remainder and the swap output match the reserve ratio, then both are deposited.
+    ZapIn {
+        input_token: TokenSelect,
+        amount: Uint128,
+        min_liquidity: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Remove liquidity and receive only `output_token`. The other side of the withdrawal is
+    /// swapped back into the pool at the current fee.
+    ZapOut {
+        amount: Uint128,
+        output_token: TokenSelect,
+        min_output: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Chained swap converting A -> B and B -> C by leveraging two swap contracts.
+    /// Routes through more pools are executed by the router contract.
     PassThroughSwap {
         output_amm_address: String,
         input_token: TokenSelect,
@@ -59,20 +107,259 @@ pub enum ExecuteMsg {
         min_token: Uint128,
         expiration: Option<Expiration>,
     },
+    /// Fee manager only. Changing `protocol_fee_recipient` also requires the config admin role.
     UpdateConfig {
-        owner: Option<String>,
         lp_fee_percent: Decimal,
         protocol_fee_percent: Decimal,
         protocol_fee_recipient: String,
+        /// Mint the protocol fee as LP tokens on liquidity changes instead of transferring it
+        /// on every swap, unchanged if not set. Only constant product pools support it.
+        fee_switch: Option<bool>,
+        /// Seconds later config updates are queued for, unchanged if not set
+        config_timelock: Option<u64>,
+    },
+    /// Applies the pending config once its timelock has passed, can be sent by anyone
+    ApplyPendingConfig {},
+    /// Fee manager only. Drops the pending config
+    CancelPendingConfig {},
+    /// Owner only. Proposes `owner` as the new owner, who has to accept with `AcceptOwnership`
+    /// before `expiry`. Replaces an earlier proposal.
+    ProposeNewOwner {
+        owner: String,
+        expiry: Option<Expiration>,
+    },
+    /// Makes the sender the owner if it is the proposed owner
+    AcceptOwnership {},
+    /// Owner only. Withdraws the ownership proposal
+    CancelOwnershipProposal {},
+    /// Owner only. Removes the owner for good, which disables every owner only message
+    RenounceOwnership {},
+    /// Owner only. Grants a role other than owner to `address`
+    GrantRole {
+        role: Role,
+        address: String,
+    },
+    /// Owner only. Revokes a granted role from `address`
+    RevokeRole {
+        role: Role,
+        address: String,
+    },
+    /// Gives up a role granted to the sender
+    RenounceRole {
+        role: Role,
+    },
+    /// Fee manager only. Enables dynamic lp fees with the given bounds, or goes back to the static
+    /// `lp_fee_percent` when unset. Queued like `UpdateConfig`.
+    UpdateDynamicFee {
+        dynamic_fee: Option<DynamicFeeConfig>,
     },
     // Freeze adding new deposits
     FreezeDeposits {
         freeze: bool,
     },
+    /// Pauser only. Pauses and unpauses operations independently
+    UpdatePauseFlags {
+        pause_flags: PauseFlags,
+    },
+    /// Config admin only. Sets or removes the guardian and the maximum duration of its pauses
+    UpdateGuardian {
+        guardian: Option<String>,
+        max_pause_duration: u64,
+    },
+    /// Guardian only. Pauses the given operations for `max_pause_duration` seconds. Pausing more
+    /// operations during an active guardian pause keeps its expiry, unset flags unpause nothing.
+    /// After the pause expired the guardian can not pause again until `UpdatePauseFlags` is called.
+    GuardianPause {
+        pause_flags: PauseFlags,
+    },
+    /// Pauser only. Moves the expiry of the active guardian pause to `expires_at`
+    ExtendGuardianPause {
+        expires_at: u64,
+    },
+    /// Config admin only. Flags a cw20 pool token that delivers less than the transferred amount.
+    /// Swaps and deposits of flagged tokens are credited with what the pool actually received.
+    UpdateFeeOnTransfer {
+        token: TokenSelect,
+        fee_on_transfer: bool,
+    },
+    /// Linearly change the amplification of a stable pool until `future_time`
+    RampAmp {
+        future_amp: u64,
+        future_time: u64,
+    },
+    /// Stop an amplification ramp at the current amplification
+    StopRampAmp {},
+    /// Sends the requested amounts to `recipient` and calls `FlashSwapCallback` on it. By the end
+    /// of the callback the pool must hold enough tokens to satisfy the constant product invariant
+    /// including fees, otherwise the whole transaction fails. Repay with a plain bank send or cw20
+    /// `Transfer`, the pool rejects all messages while the callback runs.
+    FlashSwap {
+        token1_amount: Uint128,
+        token2_amount: Uint128,
+        recipient: String,
+        msg: Binary,
+    },
+    /// Config admin only. Enables staking of LP tokens or changes the unbonding period of future
+    /// unbondings. LP tokens are bonded by sending them to the pool with `Cw20HookMsg::Bond`.
+    UpdateStakingConfig {
+        unbonding_period: u64,
+    },
+    /// Config admin only. Streams `amount` of `denom` to the bonded LP tokens over the next `duration`
+    /// seconds, together with what is left of an ongoing schedule of the same denom.
+    /// Native rewards must be sent as `amount`, cw20 rewards are pulled with `TransferFrom`.
+    FundRewards {
+        denom: Denom,
+        amount: Uint128,
+        duration: u64,
+    },
+    /// Stops earning rewards with `amount` bonded LP tokens, they can be withdrawn with
+    /// `WithdrawUnbonded` once the unbonding period has passed
+    Unbond {
+        amount: Uint128,
+    },
+    /// Sends all LP tokens whose unbonding period has passed to the sender
+    WithdrawUnbonded {},
+    /// Sends all pending staking rewards to the sender
+    ClaimRewards {},
+    /// Escrows `input_amount` of `input_token` until `ExecuteOrders` can swap it for at least
+    /// `min_output`. Native input must be sent as `input_amount`, cw20 input is pulled with
+    /// `TransferFrom`.
+    PlaceOrder {
+        input_token: TokenSelect,
+        input_amount: Uint128,
+        min_output: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Refunds the escrowed input of an order of the sender
+    CancelOrder {
+        order_id: u64,
+    },
+    /// Fills the orders of both input tokens that the pool can swap at their limit price,
+    /// checking at most `limit` orders per input token from the lowest price. Expired orders are
+    /// refunded and the sender receives a tip from the output of every filled order.
+    ExecuteOrders {
+        limit: Option<u32>,
+    },
+    /// Sets the reserves to the pool balances, minus the tokens escrowed for limit orders and
+    /// staking rewards
+    Sync {},
+    /// Sends the pool balances above the reserves and escrowed tokens to `recipient`
+    Skim {
+        recipient: String,
+    },
+    /// Swaps `input_amount` of `input_denom` for at least `min_output` of `output_denom`, sent to
+    /// `recipient` or the sender. Asset messages work on multi asset and constant product pools.
+    /// cw20 inputs are pulled with `TransferFrom`, so the pool needs an allowance.
+    SwapAssets {
+        input_denom: Denom,
+        output_denom: Denom,
+        input_amount: Uint128,
+        min_output: Uint128,
+        recipient: Option<String>,
+        expiration: Option<Expiration>,
+    },
+    /// Deposits any amounts of the pool assets, the first deposit must include every asset. The
+    /// part that is not proportional to the reserves pays the swap fee. cw20 assets are pulled
+    /// with `TransferFrom`, so the pool needs an allowance.
+    AddAssets {
+        amounts: Vec<AssetAmount>,
+        min_liquidity: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Burns `amount` liquidity for the same share of every asset
+    RemoveAssets {
+        amount: Uint128,
+        min_amounts: Vec<AssetAmount>,
+        expiration: Option<Expiration>,
+    },
+    /// Withdraws exactly `amounts` for at most `max_liquidity`. The part that is not
+    /// proportional to the reserves pays the swap fee.
+    RemoveAssetsImbalanced {
+        amounts: Vec<AssetAmount>,
+        max_liquidity: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Entry point for cw20 `Send`, the embedded msg must be a `Cw20HookMsg`. Multi asset pools
+    /// only accept LP tokens here, their cw20 assets are swapped and deposited with an allowance.
+    Receive(Cw20ReceiveMsg),
+}
+
+/// Operations that can be triggered by sending one of the pool's cw20 tokens with `Send`.
+/// The input token and amount are taken from the received cw20 tokens. Multi asset pools only
+/// support `Bond`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    Swap {
+        min_output: Uint128,
+        expiration: Option<Expiration>,
+    },
+    SwapAndSendTo {
+        recipient: String,
+        min_token: Uint128,
+        expiration: Option<Expiration>,
+    },
+    PassThroughSwap {
+        output_amm_address: String,
+        output_min_token: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// The received amount must equal `token1_amount` or `max_token2`, depending on which
+    /// token was sent. A cw20 counterpart is still pulled with `TransferFrom`, a native
+    /// counterpart can not be deposited this way as cw20 `Send` carries no funds.
+    AddLiquidity {
+        token1_amount: Uint128,
+        min_liquidity: Uint128,
+        max_token2: Uint128,
+        expiration: Option<Expiration>,
+    },
+    /// Bonds the received LP tokens of this pool for staking rewards
+    Bond {},
+    /// Places a limit order escrowing the received tokens
+    PlaceOrder {
+        min_output: Uint128,
+        expiration: Option<Expiration>,
+    },
+}
+
+/// Sent by the pool to the recipient of a flash swap
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FlashSwapCallbackMsg {
+    pub sender: String,
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128,
+    pub msg: Binary,
 }
 
+impl FlashSwapCallbackMsg {
+    /// Serializes the message wrapped in `FlashSwapReceiverMsg`
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = FlashSwapReceiverMsg::FlashSwapCallback(self);
+        to_binary(&msg)
+    }
+
+    /// Creates a cosmos msg calling the flash swap recipient
+    pub fn into_cosmos_msg<T: Into<String>>(self, contract_addr: T) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        let execute = WasmMsg::Execute {
+            contract_addr: contract_addr.into(),
+            msg,
+            funds: vec![],
+        };
+        Ok(execute.into())
+    }
+}
+
+/// Execute msg a flash swap recipient must implement
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+pub enum FlashSwapReceiverMsg {
+    FlashSwapCallback(FlashSwapCallbackMsg),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     /// Implements CW20. Returns the current balance of the given address, 0 if unset.
     Balance {
@@ -85,7 +372,102 @@ pub enum QueryMsg {
     Token2ForToken1Price {
         token2_amount: Uint128,
     },
+    /// Token1 required to receive exactly `token2_amount`
+    Token1ForExactToken2Price {
+        token2_amount: Uint128,
+    },
+    /// Token2 required to receive exactly `token1_amount`
+    Token2ForExactToken1Price {
+        token1_amount: Uint128,
+    },
+    /// Output of a swap together with its fees and price impact
+    SimulateSwap {
+        input_token: TokenSelect,
+        input_amount: Uint128,
+    },
+    /// Input of the other token required to receive exactly `output_amount` of `output_token`,
+    /// together with its fees
+    ReverseSimulateSwap {
+        output_token: TokenSelect,
+        output_amount: Uint128,
+    },
     Fee {},
+    /// Pending ownership proposal, if any
+    OwnershipProposal {},
+    /// Addresses holding `role`, ordered by address. The owner is only listed for the owner role.
+    Roles {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Config timelock and the queued config update, if any
+    PendingConfig {},
+    /// Operations that are paused by the owner or an active guardian pause
+    PauseFlags {},
+    /// Guardian, its active pause, if any, and whether its pause was used
+    Guardian {},
+    /// Which pool tokens are flagged as fee on transfer
+    FeeOnTransfer {},
+    /// Fees charged by a swap in the current block, including the dynamic lp fee
+    CurrentFee {},
+    /// Dynamic fee bounds, unset if the pool uses a static lp fee
+    DynamicFee {},
+    /// Output of a `ZapOut` of `amount` liquidity
+    ZapOutOutput {
+        amount: Uint128,
+        output_token: TokenSelect,
+    },
+    /// Liquidity minted by a `ZapIn` of `amount` of `input_token`
+    ZapInLiquidity {
+        input_token: TokenSelect,
+        amount: Uint128,
+    },
+    /// Time weighted average prices between two block times given in seconds
+    Twap {
+        start_time: u64,
+        end_time: u64,
+    },
+    /// Amplification of a stable pool
+    Amp {},
+    /// Token weights of a weighted pool
+    Weights {},
+    /// Unbonding period and total bonded LP tokens
+    StakingConfig {},
+    /// Bonded and unbonding LP tokens of `address`
+    Staker {
+        address: String,
+    },
+    /// Staking rewards `address` can claim
+    PendingRewards {
+        address: String,
+    },
+    RewardSchedules {},
+    /// Limit order by id
+    Order {
+        order_id: u64,
+    },
+    /// Limit orders of `owner` ordered by id
+    OrdersByOwner {
+        owner: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Limit orders selling `input_token` ordered from the lowest limit price, continuing after
+    /// the order with id `start_after`
+    OrdersByPrice {
+        input_token: TokenSelect,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Difference between the pool balances and the tracked reserves
+    ReserveDrift {},
+    /// Every asset of the pool with its reserve
+    Assets {},
+    /// Output of swapping `input_amount` of `input_denom` for every other asset
+    AssetPrices {
+        input_denom: Denom,
+        input_amount: Uint128,
+    },
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct MigrateMsg {
@@ -112,6 +494,51 @@ pub struct FeeResponse {
     pub lp_fee_percent: Decimal,
     pub protocol_fee_percent: Decimal,
     pub protocol_fee_recipient: String,
+    pub fee_switch: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingConfigResponse {
+    pub config_timelock: u64,
+    pub pending_config: Option<PendingConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposalResponse {
+    pub proposal: Option<OwnershipProposal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct RolesResponse {
+    pub addresses: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct GuardianResponse {
+    pub guardian: Option<GuardianConfig>,
+    pub pause: Option<GuardianPause>,
+    /// The guardian pause expired and can not be used again until the pauser resets it
+    pub pause_used: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeOnTransferResponse {
+    pub token1: bool,
+    pub token2: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct CurrentFeeResponse {
+    pub lp_fee_percent: Decimal,
+    pub protocol_fee_percent: Decimal,
+    pub total_fee_percent: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct DynamicFeeResponse {
+    pub dynamic_fee: Option<DynamicFeeConfig>,
+    /// Accumulated volatility decayed to the current block
+    pub volatility: Decimal256,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -123,3 +550,137 @@ pub struct Token1ForToken2PriceResponse {
 pub struct Token2ForToken1PriceResponse {
     pub token1_amount: Uint128,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Token1ForExactToken2PriceResponse {
+    pub token1_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Token2ForExactToken1PriceResponse {
+    pub token2_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SimulateSwapResponse {
+    pub output_amount: Uint128,
+    /// Lp fee charged on the input, denominated in the input token
+    pub lp_fee_amount: Uint128,
+    /// Protocol fee charged on the input, denominated in the input token
+    pub protocol_fee_amount: Uint128,
+    /// Price of the input token denominated in the output token before the swap
+    pub spot_price_before: Decimal256,
+    /// Price of the input token denominated in the output token after the swap
+    pub spot_price_after: Decimal256,
+    /// Output received per input token
+    pub execution_price: Decimal256,
+    /// Relative shortfall of the output against the input after fees at the spot price before
+    /// the swap
+    pub price_impact: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ReverseSimulateSwapResponse {
+    /// Input required including fees, rounded up
+    pub input_amount: Uint128,
+    /// Lp fee charged on the input, denominated in the input token
+    pub lp_fee_amount: Uint128,
+    /// Protocol fee charged on the input, denominated in the input token
+    pub protocol_fee_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ZapInLiquidityResponse {
+    pub liquidity: Uint128,
+    /// Part of the input that is swapped for the other token
+    pub swap_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ZapOutOutputResponse {
+    pub output_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AmpResponse {
+    pub amp: u64,
+    pub future_amp: u64,
+    pub future_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct WeightsResponse {
+    pub token1_weight: u64,
+    pub token2_weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StakingConfigResponse {
+    pub unbonding_period: u64,
+    pub total_bonded: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StakerResponse {
+    pub bonded: Uint128,
+    pub unbondings: Vec<Unbonding>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardAmount {
+    pub denom: Denom,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingRewardsResponse {
+    pub rewards: Vec<RewardAmount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardSchedulesResponse {
+    pub schedules: Vec<RewardSchedule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrdersResponse {
+    pub orders: Vec<LimitOrder>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TokenDrift {
+    pub reserve: Uint128,
+    /// Tokens held for limit orders and staking rewards
+    pub escrowed: Uint128,
+    pub balance: Uint128,
+    /// Balance above the reserve and escrowed tokens, sent out by `Skim`
+    pub excess: Uint128,
+    /// Balance missing to cover the reserve and escrowed tokens
+    pub deficit: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ReserveDriftResponse {
+    pub token1: TokenDrift,
+    pub token2: TokenDrift,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetsResponse {
+    pub assets: Vec<Token>,
+    pub lp_token_supply: Uint128,
+    pub lp_token_address: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetPricesResponse {
+    pub prices: Vec<AssetAmount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapResponse {
+    /// Average price of token1 denominated in token2
+    pub token1_price: Decimal256,
+    /// Average price of token2 denominated in token1
+    pub token2_price: Decimal256,
+}
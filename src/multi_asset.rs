@@ -0,0 +1,266 @@
+use cosmwasm_std::{Decimal256, StdError, StdResult, Uint128, Uint256, Uint512};
+use std::convert::TryInto;
+
+use crate::weighted::pow;
+
+// Every factor of the invariant is kept at or above one half, where the power series converges
+// quickly
+const MIN_INVARIANT_FACTOR_PERCENT: u64 = 50;
+
+/// Geometric mean of `amounts`, rounded down. At most four amounts fit the 512 bit product.
+pub fn geometric_mean(amounts: &[Uint128]) -> StdResult<Uint128> {
+    let mut product = Uint512::from(1u8);
+    for amount in amounts {
+        product = product
+            .checked_mul(Uint512::from(*amount))
+            .map_err(StdError::overflow)?;
+    }
+
+    // Largest mean whose power does not exceed the product
+    let mut low = Uint128::zero();
+    let mut high = amounts.iter().max().copied().unwrap_or_default();
+    while low < high {
+        let mid = high - (high - low) / Uint128::new(2);
+        let mut power = Uint512::from(1u8);
+        for _ in amounts {
+            power = power
+                .checked_mul(Uint512::from(mid))
+                .map_err(StdError::overflow)?;
+        }
+        if power <= product {
+            low = mid;
+        } else {
+            high = mid - Uint128::new(1);
+        }
+    }
+    Ok(low)
+}
+
+/// Splits `amounts` into the largest part proportional to `reserves` and the rest. Returns the
+/// proportional part of every amount and the share of the reserves it makes up as a fraction.
+fn split_proportional(
+    reserves: &[Uint128],
+    amounts: &[Uint128],
+) -> (Vec<Uint128>, (Uint128, Uint128)) {
+    let mut share = (amounts[0], reserves[0]);
+    for (amount, reserve) in amounts.iter().zip(reserves) {
+        if amount.full_mul(share.1) < share.0.full_mul(*reserve) {
+            share = (*amount, *reserve);
+        }
+    }
+    let proportional = reserves
+        .iter()
+        .map(|reserve| reserve.multiply_ratio(share.0, share.1))
+        .collect();
+    (proportional, share)
+}
+
+/// `amount * ratio`, rounded up
+fn mul_ceil(amount: Uint128, ratio: Decimal256) -> StdResult<Uint128> {
+    let one = Decimal256::one().atomics();
+    let product = Uint256::from(amount)
+        .checked_mul(ratio.atomics())
+        .map_err(StdError::overflow)?
+        .checked_add(one - Uint256::from(1u8))
+        .map_err(StdError::overflow)?;
+    Ok((product / one).try_into()?)
+}
+
+/// Product of `(numerator / denominator)^(1 / n)` over all assets, every ratio must be at most
+/// one and at least one half
+fn invariant_ratio(ratios: &[(Uint128, Uint128)]) -> StdResult<Decimal256> {
+    let exp = Decimal256::from_ratio(1u8, ratios.len() as u64);
+    let mut result = Decimal256::one();
+    for (numerator, denominator) in ratios {
+        if numerator.full_mul(100u64) < denominator.full_mul(MIN_INVARIANT_FACTOR_PERCENT) {
+            return Err(StdError::generic_err(format!(
+                "The imbalanced part may change a reserve by at most {}%",
+                MIN_INVARIANT_FACTOR_PERCENT
+            )));
+        }
+        result = result * pow(Decimal256::from_ratio(*numerator, *denominator), exp)?;
+    }
+    Ok(result)
+}
+
+/// Liquidity minted for depositing `amounts` into a pool with `reserves`. The part of the
+/// deposit that is not proportional to the reserves pays `fee` as if it was swapped into the
+/// other assets, the fee stays in the pool.
+pub fn get_deposit_liquidity(
+    reserves: &[Uint128],
+    amounts: &[Uint128],
+    liquidity_supply: Uint128,
+    fee: Decimal256,
+) -> StdResult<Uint128> {
+    let (proportional, share) = split_proportional(reserves, amounts);
+    let proportional_liquidity = liquidity_supply.multiply_ratio(share.0, share.1);
+    let liquidity_supply = liquidity_supply + proportional_liquidity;
+
+    // supply * (V' / V - 1) with the invariant V = (x1 * x2 * ... * xn)^(1 / n)
+    let mut ratios = vec![];
+    for ((reserve, amount), proportional) in reserves.iter().zip(amounts).zip(proportional) {
+        let reserve = *reserve + proportional;
+        let rest = *amount - proportional;
+        let rest_after_fee = rest - mul_ceil(rest, fee)?;
+        ratios.push((reserve, reserve + rest_after_fee));
+    }
+    let ratio = invariant_ratio(&ratios)?;
+    let imbalanced_liquidity: Uint128 = Uint256::from(liquidity_supply)
+        .checked_mul(Decimal256::one().atomics() - ratio.atomics())
+        .map_err(StdError::overflow)?
+        .checked_div(ratio.atomics())
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?;
+    Ok(proportional_liquidity + imbalanced_liquidity)
+}
+
+/// Liquidity burned for withdrawing `amounts` from a pool with `reserves`, rounded up. Every
+/// amount must be less than its reserve. The part of the withdrawal that is not proportional
+/// to the reserves pays `fee` as if the other assets were swapped into it.
+pub fn get_withdraw_liquidity(
+    reserves: &[Uint128],
+    amounts: &[Uint128],
+    liquidity_supply: Uint128,
+    fee: Decimal256,
+) -> StdResult<Uint128> {
+    let (proportional, share) = split_proportional(reserves, amounts);
+    let proportional_liquidity: Uint128 = liquidity_supply
+        .full_mul(share.0)
+        .checked_add(Uint256::from(share.1) - Uint256::from(1u8))
+        .map_err(StdError::overflow)?
+        .checked_div(Uint256::from(share.1))
+        .map_err(StdError::divide_by_zero)?
+        .try_into()?;
+    let liquidity_supply = liquidity_supply
+        .checked_sub(proportional_liquidity)
+        .map_err(StdError::overflow)?;
+
+    // supply * (1 - V' / V), the fee is added on top of the rest so it stays in the pool
+    let fee_on_output = Decimal256::from_ratio(fee.atomics(), (Decimal256::one() - fee).atomics());
+    let mut ratios = vec![];
+    for ((reserve, amount), proportional) in reserves.iter().zip(amounts).zip(proportional) {
+        let reserve = *reserve - proportional;
+        let rest = *amount - proportional;
+        let rest_with_fee = rest + mul_ceil(rest, fee_on_output)?;
+        let new_reserve = reserve
+            .checked_sub(rest_with_fee)
+            .map_err(StdError::overflow)?;
+        ratios.push((new_reserve, reserve));
+    }
+    let ratio = invariant_ratio(&ratios)?;
+    let imbalanced_liquidity = mul_ceil(liquidity_supply, Decimal256::one() - ratio)?;
+    Ok(proportional_liquidity + imbalanced_liquidity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uints(values: &[u128]) -> Vec<Uint128> {
+        values.iter().map(|value| Uint128::new(*value)).collect()
+    }
+
+    #[test]
+    fn test_geometric_mean() {
+        assert_eq!(
+            geometric_mean(&uints(&[1_000_000, 990_000])).unwrap(),
+            Uint128::new(994_987)
+        );
+        assert_eq!(
+            geometric_mean(&uints(&[1_000, 8_000, 125_000])).unwrap(),
+            Uint128::new(10_000)
+        );
+        assert_eq!(
+            geometric_mean(&uints(&[1_000, 8_000, 0])).unwrap(),
+            Uint128::zero()
+        );
+        let max = Uint128::MAX;
+        assert_eq!(geometric_mean(&[max, max, max, max]).unwrap(), max);
+    }
+
+    #[test]
+    fn test_deposit_liquidity() {
+        let reserves = uints(&[1_000_000, 2_000_000, 4_000_000]);
+        let supply = Uint128::new(3_000_000);
+        let fee = Decimal256::permille(3);
+
+        // Proportional deposits mint the same share of the supply without a fee
+        let liquidity =
+            get_deposit_liquidity(&reserves, &uints(&[100_000, 200_000, 400_000]), supply, fee)
+                .unwrap();
+        assert_eq!(liquidity, Uint128::new(300_000));
+
+        // Depositing a single asset without a fee grows the invariant by its cube root
+        // (1.728)^(1/3) = 1.2
+        let liquidity = get_deposit_liquidity(
+            &reserves,
+            &uints(&[728_000, 0, 0]),
+            supply,
+            Decimal256::zero(),
+        )
+        .unwrap();
+        assert!(liquidity >= Uint128::new(599_999) && liquidity <= Uint128::new(600_000));
+
+        // The fee is charged on the imbalanced part only
+        let balanced =
+            get_deposit_liquidity(&reserves, &uints(&[100_000, 200_000, 400_000]), supply, fee)
+                .unwrap();
+        let imbalanced =
+            get_deposit_liquidity(&reserves, &uints(&[100_000, 200_000, 500_000]), supply, fee)
+                .unwrap();
+        let imbalanced_without_fee = get_deposit_liquidity(
+            &reserves,
+            &uints(&[100_000, 200_000, 500_000]),
+            supply,
+            Decimal256::zero(),
+        )
+        .unwrap();
+        assert!(balanced < imbalanced);
+        assert!(imbalanced < imbalanced_without_fee);
+
+        let err =
+            get_deposit_liquidity(&reserves, &uints(&[2_000_000, 0, 0]), supply, fee).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("The imbalanced part may change a reserve by at most 50%")
+        );
+    }
+
+    #[test]
+    fn test_withdraw_liquidity() {
+        let reserves = uints(&[1_000_000, 2_000_000, 4_000_000]);
+        let supply = Uint128::new(3_000_000);
+        let fee = Decimal256::permille(3);
+
+        let liquidity =
+            get_withdraw_liquidity(&reserves, &uints(&[100_000, 200_000, 400_000]), supply, fee)
+                .unwrap();
+        assert_eq!(liquidity, Uint128::new(300_000));
+
+        // Withdrawing a single asset without a fee shrinks the invariant by its cube root
+        // (0.512)^(1/3) = 0.8
+        let liquidity = get_withdraw_liquidity(
+            &reserves,
+            &uints(&[0, 0, 1_952_000]),
+            supply,
+            Decimal256::zero(),
+        )
+        .unwrap();
+        assert!(liquidity >= Uint128::new(600_000) && liquidity <= Uint128::new(600_001));
+
+        let err =
+            get_withdraw_liquidity(&reserves, &uints(&[0, 0, 2_100_000]), supply, fee).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("The imbalanced part may change a reserve by at most 50%")
+        );
+
+        // Burning the liquidity of a deposit withdraws less than the deposit
+        let deposit = uints(&[0, 0, 1_000_000]);
+        let minted = get_deposit_liquidity(&reserves, &deposit, supply, fee).unwrap();
+        let reserves_after = uints(&[1_000_000, 2_000_000, 5_000_000]);
+        let burned =
+            get_withdraw_liquidity(&reserves_after, &deposit, supply + minted, fee).unwrap();
+        assert!(burned > minted);
+    }
+}
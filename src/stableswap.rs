@@ -0,0 +1,192 @@
+use cosmwasm_std::{StdError, StdResult, Uint128, Uint512};
+use std::convert::TryInto;
+
+// Number of coins in a pool
+const N_COINS: u8 = 2;
+// Newton's method usually converges within a handful of rounds, this only bounds the gas used
+const ITERATIONS: u8 = 64;
+
+fn mul(a: Uint512, b: Uint512) -> StdResult<Uint512> {
+    a.checked_mul(b).map_err(StdError::overflow)
+}
+
+fn div(a: Uint512, b: Uint512) -> StdResult<Uint512> {
+    a.checked_div(b).map_err(StdError::divide_by_zero)
+}
+
+fn add(a: Uint512, b: Uint512) -> StdResult<Uint512> {
+    a.checked_add(b).map_err(StdError::overflow)
+}
+
+fn sub(a: Uint512, b: Uint512) -> StdResult<Uint512> {
+    a.checked_sub(b).map_err(StdError::overflow)
+}
+
+fn has_converged(a: Uint512, b: Uint512) -> bool {
+    let one = Uint512::from(1u8);
+    if a > b {
+        a - b <= one
+    } else {
+        b - a <= one
+    }
+}
+
+/// Computes the StableSwap invariant D of the reserves x and y for the amplification `amp`:
+/// A * n^n * (x + y) + D = A * D * n^n + D^(n + 1) / (n^n * x * y)
+pub fn compute_d(amp: u64, x: Uint128, y: Uint128) -> StdResult<Uint128> {
+    let n = Uint512::from(N_COINS);
+    let x = Uint512::from(x);
+    let y = Uint512::from(y);
+    let sum = add(x, y)?;
+    if sum.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let ann = mul(Uint512::from(amp), mul(n, n)?)?;
+    let mut d = sum;
+    for _ in 0..ITERATIONS {
+        // D^(n + 1) / (n^n * x * y)
+        let d_p = div(mul(div(mul(d, d)?, mul(x, n)?)?, d)?, mul(y, n)?)?;
+        let d_prev = d;
+        let numerator = mul(add(mul(ann, sum)?, mul(d_p, n)?)?, d)?;
+        let denominator = add(
+            mul(sub(ann, Uint512::from(1u8))?, d)?,
+            mul(add(n, Uint512::from(1u8))?, d_p)?,
+        )?;
+        d = div(numerator, denominator)?;
+        if has_converged(d, d_prev) {
+            return Ok(d.try_into()?);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// Computes the reserve y that keeps the invariant `d` when the other reserve is `x`
+pub fn compute_y(amp: u64, x: Uint128, d: Uint128) -> StdResult<Uint128> {
+    let n = Uint512::from(N_COINS);
+    let x = Uint512::from(x);
+    let d = Uint512::from(d);
+    let ann = mul(Uint512::from(amp), mul(n, n)?)?;
+
+    // y^2 + (x + D / Ann - D) * y = D^(n + 1) / (n^n * x * Ann)
+    let c = div(mul(div(mul(d, d)?, mul(x, n)?)?, d)?, mul(ann, n)?)?;
+    let b = add(x, div(d, ann)?)?;
+
+    let mut y = d;
+    for _ in 0..ITERATIONS {
+        let y_prev = y;
+        y = div(add(mul(y, y)?, c)?, sub(add(mul(y, n)?, b)?, d)?)?;
+        if has_converged(y, y_prev) {
+            return Ok(y.try_into()?);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// Reserves scaled so their ratio y' / x' is the marginal price -dy/dx of x in y on the
+/// invariant curve, the larger one fits in 128 bits:
+/// -dy/dx = y * (n^n * Ann * x^2 * y + D^3) / (x * (n^n * Ann * x * y^2 + D^3))
+pub fn compute_price_reserves(amp: u64, x: Uint128, y: Uint128) -> StdResult<(Uint128, Uint128)> {
+    if x.is_zero() || y.is_zero() {
+        return Ok((x, y));
+    }
+
+    let n = Uint512::from(N_COINS);
+    let d = Uint512::from(compute_d(amp, x, y)?);
+    let x = Uint512::from(x);
+    let y = Uint512::from(y);
+    let ann = mul(Uint512::from(amp), mul(n, n)?)?;
+    let nn_ann_xy = mul(mul(mul(n, n)?, ann)?, mul(x, y)?)?;
+    let d3 = mul(mul(d, d)?, d)?;
+    let (x_term, y_term) = shr_to_fit(
+        add(mul(nn_ann_xy, y)?, d3)?,
+        add(mul(nn_ann_xy, x)?, d3)?,
+        384,
+    );
+    let (x_price, y_price) = shr_to_fit(mul(x, x_term)?, mul(y, y_term)?, 128);
+    // Only a ratio beyond 128 bits rounds the smaller one to zero
+    Ok((
+        x_price.max(Uint512::from(1u8)).try_into()?,
+        y_price.max(Uint512::from(1u8)).try_into()?,
+    ))
+}
+
+/// Shifts both values right until the larger one fits in `bits` bits, which keeps their ratio
+fn shr_to_fit(mut a: Uint512, mut b: Uint512, bits: u32) -> (Uint512, Uint512) {
+    let max = Uint512::MAX >> (512 - bits);
+    while a > max || b > max {
+        a >>= 1;
+        b >>= 1;
+    }
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_d() {
+        // Balanced reserves, D is the sum of the reserves
+        assert_eq!(
+            compute_d(100, Uint128::new(1_000_000), Uint128::new(1_000_000)).unwrap(),
+            Uint128::new(2_000_000)
+        );
+        assert_eq!(
+            compute_d(100, Uint128::zero(), Uint128::zero()).unwrap(),
+            Uint128::zero()
+        );
+
+        // Imbalanced reserves are worth a little less than their sum
+        let d = compute_d(100, Uint128::new(1_500_000), Uint128::new(500_000)).unwrap();
+        assert!(d < Uint128::new(2_000_000));
+        assert!(d > Uint128::new(1_990_000));
+    }
+
+    #[test]
+    fn test_compute_y() {
+        let x = Uint128::new(1_000_000);
+        let y = Uint128::new(1_000_000);
+        let d = compute_d(100, x, y).unwrap();
+        let y_new = compute_y(100, x, d).unwrap();
+        assert!(y_new >= y - Uint128::new(1) && y_new <= y + Uint128::new(1));
+
+        // Close to the peg almost all of the input is received as output
+        let y_new = compute_y(100, x + Uint128::new(10_000), d).unwrap();
+        assert!(y - y_new > Uint128::new(9_990));
+        assert!(y - y_new <= Uint128::new(10_000));
+    }
+
+    #[test]
+    fn test_compute_price_reserves() {
+        // Balanced reserves trade at the peg
+        let (x, y) =
+            compute_price_reserves(100, Uint128::new(1_000_000), Uint128::new(1_000_000)).unwrap();
+        assert_eq!(x, y);
+
+        // The marginal price matches the output of a small swap along the curve
+        let (x, y) = (Uint128::new(1_500_000_000), Uint128::new(500_000_000));
+        let (x_price, y_price) = compute_price_reserves(100, x, y).unwrap();
+        let d = compute_d(100, x, y).unwrap();
+        let dy = y - compute_y(100, x + Uint128::new(1_000), d).unwrap();
+        let price = y_price.u128() as f64 / x_price.u128() as f64;
+        assert!((price - dy.u128() as f64 / 1_000.0).abs() < 0.001);
+        assert!(price > 0.99 && price < 1.0);
+
+        // A low amplification moves the price towards the reserve ratio
+        let (x_price, y_price) = compute_price_reserves(1, x, y).unwrap();
+        let price = y_price.u128() as f64 / x_price.u128() as f64;
+        assert!(price > 0.64 && price < 0.65);
+
+        // Reserves whose invariant still fits in 128 bits do not overflow
+        let max = Uint128::MAX;
+        let (x_price, y_price) = compute_price_reserves(1_000_000, max >> 2, max >> 3).unwrap();
+        assert!(x_price > Uint128::zero() && y_price > Uint128::zero());
+    }
+}
@@ -0,0 +1,124 @@
+use cosmwasm_std::{Decimal256, StdResult, Uint128, Uint256};
+use std::convert::TryInto;
+
+use crate::state::{RewardSchedule, StakerReward};
+
+/// Streams the rewards of `schedule` up to `now` into its reward per share. While nothing is
+/// bonded the schedule is paused by pushing back its end time, so no rewards are lost.
+pub fn accrue_rewards(schedule: &mut RewardSchedule, total_bonded: Uint128, now: u64) {
+    if schedule.last_update_time >= schedule.end_time || now <= schedule.last_update_time {
+        return;
+    }
+
+    if total_bonded.is_zero() {
+        schedule.end_time += now - schedule.last_update_time;
+        schedule.last_update_time = now;
+        return;
+    }
+
+    let until = now.min(schedule.end_time);
+    let streamed =
+        schedule.reward_rate * Decimal256::from_ratio(until - schedule.last_update_time, 1u8);
+    schedule.reward_per_share = schedule.reward_per_share + streamed / Uint256::from(total_bonded);
+    schedule.last_update_time = until;
+}
+
+/// Adds `amount` to a schedule streamed over the next `duration` seconds. Rewards of the
+/// schedule that have not been streamed yet are spread over the new duration as well.
+/// The schedule must be accrued up to `now` first.
+pub fn fund_rewards(schedule: &mut RewardSchedule, amount: Uint128, duration: u64, now: u64) {
+    let remaining = if schedule.end_time > now {
+        schedule.reward_rate * Decimal256::from_ratio(schedule.end_time - now, 1u8)
+    } else {
+        Decimal256::zero()
+    };
+
+    // Rounds down, so the streamed rewards never exceed the funded amount
+    schedule.reward_rate =
+        (remaining + Decimal256::from_ratio(amount, 1u8)) / Uint256::from(duration);
+    schedule.last_update_time = now;
+    schedule.end_time = now + duration;
+}
+
+/// Moves the rewards earned by `bonded` LP tokens since the last update into `pending`
+pub fn accrue_staker_rewards(
+    reward: &mut StakerReward,
+    bonded: Uint128,
+    reward_per_share: Decimal256,
+) -> StdResult<()> {
+    let earned: Uint128 =
+        (Uint256::from(bonded) * (reward_per_share - reward.reward_per_share_paid)).try_into()?;
+    reward.pending += earned;
+    reward.reward_per_share_paid = reward_per_share;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cw20::Denom;
+
+    fn new_schedule(now: u64) -> RewardSchedule {
+        RewardSchedule {
+            denom: Denom::Native("reward".to_string()),
+            reward_rate: Decimal256::zero(),
+            end_time: now,
+            last_update_time: now,
+            reward_per_share: Decimal256::zero(),
+        }
+    }
+
+    #[test]
+    fn test_accrue_rewards() {
+        let mut schedule = new_schedule(1_000);
+        fund_rewards(&mut schedule, Uint128::new(1_000), 100, 1_000);
+        assert_eq!(schedule.reward_rate, Decimal256::from_ratio(10u8, 1u8));
+        assert_eq!(schedule.end_time, 1_100);
+
+        // Nothing bonded for 20 seconds, the schedule is paused
+        accrue_rewards(&mut schedule, Uint128::zero(), 1_020);
+        assert_eq!(schedule.reward_per_share, Decimal256::zero());
+        assert_eq!(schedule.end_time, 1_120);
+
+        // 500 streamed to 250 bonded tokens
+        accrue_rewards(&mut schedule, Uint128::new(250), 1_070);
+        assert_eq!(schedule.reward_per_share, Decimal256::from_ratio(2u8, 1u8));
+
+        // Streaming stops at the end time
+        accrue_rewards(&mut schedule, Uint128::new(250), 2_000);
+        assert_eq!(schedule.reward_per_share, Decimal256::from_ratio(4u8, 1u8));
+        assert_eq!(schedule.last_update_time, 1_120);
+    }
+
+    #[test]
+    fn test_fund_rewards() {
+        let mut schedule = new_schedule(1_000);
+        fund_rewards(&mut schedule, Uint128::new(1_000), 100, 1_000);
+
+        // Half of the first funding is left and spread over the new duration
+        accrue_rewards(&mut schedule, Uint128::new(100), 1_050);
+        fund_rewards(&mut schedule, Uint128::new(1_500), 200, 1_050);
+        assert_eq!(schedule.reward_rate, Decimal256::from_ratio(10u8, 1u8));
+        assert_eq!(schedule.end_time, 1_250);
+
+        // Rates that do not divide evenly round down
+        let mut schedule = new_schedule(1_000);
+        fund_rewards(&mut schedule, Uint128::new(100), 3, 1_000);
+        assert!(
+            schedule.reward_rate * Decimal256::from_ratio(3u8, 1u8)
+                <= Decimal256::from_ratio(100u8, 1u8)
+        );
+    }
+
+    #[test]
+    fn test_accrue_staker_rewards() {
+        let mut reward = StakerReward::default();
+        accrue_staker_rewards(&mut reward, Uint128::new(100), Decimal256::percent(150)).unwrap();
+        assert_eq!(reward.pending, Uint128::new(150));
+        assert_eq!(reward.reward_per_share_paid, Decimal256::percent(150));
+
+        // Only the increase since the last update is earned
+        accrue_staker_rewards(&mut reward, Uint128::new(10), Decimal256::percent(255)).unwrap();
+        assert_eq!(reward.pending, Uint128::new(160));
+    }
+}
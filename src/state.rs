@@ -1,9 +1,11 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Decimal, Uint128};
-use cw20::Denom;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Decimal256, Empty, Uint128, Uint256};
+use cw20::{Denom, Expiration};
+use cw_storage_plus::{Item, Map};
+
+use crate::msg::TokenSelect;
 
 pub const LP_TOKEN: Item<Addr> = Item::new("lp_token");
 
@@ -11,20 +13,422 @@ pub const LP_TOKEN: Item<Addr> = Item::new("lp_token");
 pub struct Token {
     pub reserve: Uint128,
     pub denom: Denom,
+    /// Set for cw20 tokens that deliver less than the transferred amount, e.g. taxed tokens.
+    /// Deposits of these tokens are credited with the balance change of the pool.
+    #[serde(default)]
+    pub fee_on_transfer: bool,
 }
 
 pub const TOKEN1: Item<Token> = Item::new("token1");
 pub const TOKEN2: Item<Token> = Item::new("token2");
 
+// Assets of a multi asset pool keyed by `denom_key`
+pub const ASSETS: Map<&str, Token> = Map::new("assets");
+
 pub const OWNER: Item<Option<Addr>> = Item::new("owner");
 
+/// Owner proposed by the current owner, who becomes the owner once it accepts before `expiry`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipProposal {
+    pub owner: Addr,
+    pub expiry: Option<Expiration>,
+}
+
+pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership_proposal");
+
+/// Administrative roles. The owner holds every role and grants the others.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Held by `OWNER`, transferred with the ownership messages
+    Owner,
+    /// Updates and cancels fee and recipient changes
+    FeeManager,
+    /// Pauses and unpauses operations
+    Pauser,
+    /// Manages the amplification, staking config, staking rewards and guardian
+    ConfigAdmin,
+}
+
+impl Role {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::FeeManager => "fee_manager",
+            Role::Pauser => "pauser",
+            Role::ConfigAdmin => "config_admin",
+        }
+    }
+}
+
+// Addresses granted a role, keyed by role and address
+pub const ROLES: Map<(&str, &Addr), Empty> = Map::new("roles");
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct Fees {
     pub protocol_fee_recipient: Addr,
     pub protocol_fee_percent: Decimal,
     pub lp_fee_percent: Decimal,
+    /// Keep the protocol fee in the reserves and mint it as LP tokens to the recipient when
+    /// liquidity changes, instead of transferring it on every swap
+    #[serde(default)]
+    pub fee_switch: bool,
+}
+
+impl Fees {
+    /// Protocol fee transferred out of every swap input, zero while the fee switch is on
+    pub fn transferred_protocol_fee_percent(&self) -> Decimal {
+        if self.fee_switch {
+            Decimal::zero()
+        } else {
+            self.protocol_fee_percent
+        }
+    }
 }
 
 pub const FEES: Item<Fees> = Item::new("fees");
 
-pub const FROZEN: Item<bool> = Item::new("frozen");
+/// Lp fee that moves between `min_fee_percent` and `max_fee_percent` with the recent volatility
+/// of the pool price, replaces the static `lp_fee_percent` while set
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct DynamicFeeConfig {
+    pub min_fee_percent: Decimal,
+    pub max_fee_percent: Decimal,
+    /// Accumulated relative price movement at which the fee reaches its maximum, e.g. 0.05
+    /// for 5%
+    pub max_fee_volatility: Decimal,
+    /// Seconds over which the accumulated volatility decays to zero
+    pub volatility_window: u64,
+}
+
+pub const DYNAMIC_FEE: Item<DynamicFeeConfig> = Item::new("dynamic_fee");
+
+/// Relative price movement accumulated by swaps, decaying over the volatility window
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Volatility {
+    pub accumulator: Decimal256,
+    /// Price of token1 denominated in token2 after the last update
+    pub last_price: Decimal256,
+    pub last_update_time: u64,
+}
+
+pub const VOLATILITY: Item<Volatility> = Item::new("volatility");
+
+/// Fee config queued by the owner, it can be applied once the block time reaches
+/// `effective_time`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingConfig {
+    pub fees: Fees,
+    pub dynamic_fee: Option<DynamicFeeConfig>,
+    /// Timelock of the config updates after this one
+    pub config_timelock: u64,
+    pub effective_time: u64,
+}
+
+// Seconds a config update is queued before it can be applied, unset for pools without timelock
+pub const CONFIG_TIMELOCK: Item<u64> = Item::new("config_timelock");
+pub const PENDING_CONFIG: Item<PendingConfig> = Item::new("pending_config");
+
+// Product of the reserves after the last liquidity change while the fee switch is on
+pub const K_LAST: Item<Uint256> = Item::new("k_last");
+
+/// Operations the owner can pause independently
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct PauseFlags {
+    /// Pauses `AddLiquidity` and `ZapIn`
+    pub add_liquidity: bool,
+    /// Pauses `RemoveLiquidity` and `ZapOut`
+    pub remove_liquidity: bool,
+    /// Pauses every swap against the pool, including zaps, flash swaps, limit orders and
+    /// pass through swaps
+    pub swap: bool,
+    /// Pauses `PassThroughSwap`
+    pub pass_through_swap: bool,
+}
+
+impl PauseFlags {
+    pub fn is_paused(&self, operation: &PoolOperation) -> bool {
+        match operation {
+            PoolOperation::AddLiquidity => self.add_liquidity,
+            PoolOperation::RemoveLiquidity => self.remove_liquidity,
+            PoolOperation::Swap => self.swap,
+            PoolOperation::PassThroughSwap => self.pass_through_swap,
+        }
+    }
+
+    /// Operations paused by either set of flags
+    pub fn union(&self, other: &PauseFlags) -> PauseFlags {
+        PauseFlags {
+            add_liquidity: self.add_liquidity || other.add_liquidity,
+            remove_liquidity: self.remove_liquidity || other.remove_liquidity,
+            swap: self.swap || other.swap,
+            pass_through_swap: self.pass_through_swap || other.pass_through_swap,
+        }
+    }
+
+    /// Pauses or unpauses deposits and swaps, like the frozen flag of earlier versions
+    pub fn freeze(&mut self, freeze: bool) {
+        self.add_liquidity = freeze;
+        self.swap = freeze;
+        self.pass_through_swap = freeze;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolOperation {
+    AddLiquidity,
+    RemoveLiquidity,
+    Swap,
+    PassThroughSwap,
+}
+
+pub const PAUSE_FLAGS: Item<PauseFlags> = Item::new("pause_flags");
+
+/// Address that can pause operations for at most `max_pause_duration` seconds, but not unpause
+/// them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct GuardianConfig {
+    pub guardian: Addr,
+    pub max_pause_duration: u64,
+}
+
+pub const GUARDIAN: Item<GuardianConfig> = Item::new("guardian");
+
+/// Operations paused by the guardian until the block time `expires_at`, in seconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct GuardianPause {
+    pub pause_flags: PauseFlags,
+    pub expires_at: u64,
+}
+
+impl GuardianPause {
+    pub fn is_active(&self, now: u64) -> bool {
+        now < self.expires_at
+    }
+}
+
+pub const GUARDIAN_PAUSE: Item<GuardianPause> = Item::new("guardian_pause");
+
+/// Running sums of the pool prices multiplied by the seconds they were in effect
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceCumulative {
+    /// Price of token1 denominated in token2
+    pub token1_price_cumulative: Decimal256,
+    /// Price of token2 denominated in token1
+    pub token2_price_cumulative: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservation {
+    /// Block time in seconds at which the cumulative prices were recorded
+    pub time: u64,
+    pub cumulative: PriceCumulative,
+}
+
+/// Slot of the latest observation in the ring buffer and the number of slots written
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PriceObservationIndex {
+    pub latest: u32,
+    pub len: u32,
+}
+
+// Ring buffer of the latest price observations keyed by slot, the oldest observation is
+// overwritten once every slot is written
+pub const PRICE_OBSERVATIONS: Map<u32, PriceObservation> = Map::new("price_observations");
+pub const PRICE_OBSERVATION_INDEX: Item<PriceObservationIndex> =
+    Item::new("price_observation_index");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolType {
+    /// Uniswap style x * y = k pool
+    #[default]
+    ConstantProduct,
+    /// Curve StableSwap pool for assets that trade close to 1:1, both tokens must use the same
+    /// number of decimals
+    Stable,
+    /// Balancer style pool with a constant x^w1 * y^w2 = k
+    Weighted,
+    /// Pool of up to four assets with a constant x1 * x2 * ... * xn = k, the assets are held in
+    /// `ASSETS` instead of `TOKEN1` and `TOKEN2`
+    MultiAsset,
+}
+
+pub const POOL_TYPE: Item<PoolType> = Item::new("pool_type");
+
+/// Amplification of a stable pool, ramped linearly from `initial_amp` at `initial_time` to
+/// `future_amp` at `future_time`. Times are block times in seconds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AmpConfig {
+    pub initial_amp: u64,
+    pub initial_time: u64,
+    pub future_amp: u64,
+    pub future_time: u64,
+}
+
+impl AmpConfig {
+    pub fn current_amp(&self, now: u64) -> u64 {
+        if now >= self.future_time {
+            return self.future_amp;
+        }
+
+        let elapsed = (now - self.initial_time) as u128;
+        let duration = (self.future_time - self.initial_time) as u128;
+        if self.future_amp > self.initial_amp {
+            let change = (self.future_amp - self.initial_amp) as u128 * elapsed / duration;
+            self.initial_amp + change as u64
+        } else {
+            let change = (self.initial_amp - self.future_amp) as u128 * elapsed / duration;
+            self.initial_amp - change as u64
+        }
+    }
+}
+
+pub const AMP: Item<AmpConfig> = Item::new("amp");
+
+/// Relative weights of the pool tokens, e.g. 80 and 20 for an 80/20 pool
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Weights {
+    pub token1_weight: u64,
+    pub token2_weight: u64,
+}
+
+pub const WEIGHTS: Item<Weights> = Item::new("weights");
+
+/// Amounts sent out by a flash swap whose repayment is checked in the reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FlashSwap {
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128,
+    /// Pool balances before the amounts were sent out
+    pub token1_balance: Uint128,
+    pub token2_balance: Uint128,
+}
+
+// Present while a flash swap callback runs, the pool rejects all other messages until then
+pub const PENDING_FLASH_SWAP: Item<FlashSwap> = Item::new("pending_flash_swap");
+
+/// Deposit of fee on transfer tokens that is settled in the reply to the inbound transfer, with
+/// the pool balances before the transfer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingDeposit {
+    Swap {
+        sender: Addr,
+        input_token: TokenSelect,
+        recipient: Addr,
+        min_token: Uint128,
+        balance: Uint128,
+    },
+    AddLiquidity {
+        sender: Addr,
+        token1_amount: Uint128,
+        token2_amount: Uint128,
+        min_liquidity: Uint128,
+        /// Only set for the tokens that are measured, the others are credited as requested
+        token1_balance: Option<Uint128>,
+        token2_balance: Option<Uint128>,
+    },
+}
+
+// Present while the inbound transfer of a fee on transfer deposit runs
+pub const PENDING_DEPOSIT: Item<PendingDeposit> = Item::new("pending_deposit");
+
+/// Settings of the built-in LP token staking, staking is disabled until the owner sets them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StakingConfig {
+    /// Seconds unbonded LP tokens stay locked before they can be withdrawn
+    pub unbonding_period: u64,
+}
+
+pub const STAKING_CONFIG: Item<StakingConfig> = Item::new("staking_config");
+
+pub const TOTAL_BONDED: Item<Uint128> = Item::new("total_bonded");
+// LP tokens bonded by every staker
+pub const BONDED: Map<&Addr, Uint128> = Map::new("bonded");
+
+/// Rewards of one denom streamed to the bonded LP tokens at a constant rate until `end_time`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardSchedule {
+    pub denom: Denom,
+    /// Rewards streamed per second
+    pub reward_rate: Decimal256,
+    pub end_time: u64,
+    pub last_update_time: u64,
+    /// Rewards streamed per bonded LP token since the schedule was created
+    pub reward_per_share: Decimal256,
+}
+
+// Reward schedules keyed by `denom_key` of their denom
+pub const REWARD_SCHEDULES: Map<&str, RewardSchedule> = Map::new("reward_schedules");
+
+/// Rewards of one denom owed to a staker
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct StakerReward {
+    /// Reward per share of the schedule when the staker rewards were last updated
+    pub reward_per_share_paid: Decimal256,
+    pub pending: Uint128,
+}
+
+// Staker rewards keyed by the staker and `denom_key` of the reward denom
+pub const STAKER_REWARDS: Map<(&Addr, &str), StakerReward> = Map::new("staker_rewards");
+
+/// LP tokens that can be withdrawn from `release_time` on
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Unbonding {
+    pub amount: Uint128,
+    pub release_time: u64,
+}
+
+pub const UNBONDINGS: Map<&Addr, Vec<Unbonding>> = Map::new("unbondings");
+
+/// Returns a storage key that is unique for a native denom or a cw20 contract
+pub fn denom_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(denom) => format!("native:{}", denom),
+        Denom::Cw20(addr) => format!("cw20:{}", addr),
+    }
+}
+
+/// Limit order escrowing `input_amount` until the pool can swap it for at least `min_output`
+/// after the executor tip
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub owner: Addr,
+    pub input_token: TokenSelect,
+    pub input_amount: Uint128,
+    pub min_output: Uint128,
+    /// Limit price, `min_output` per input token
+    pub price: Decimal256,
+    pub expiration: Option<Expiration>,
+}
+
+impl LimitOrder {
+    /// Key of the order below the input token prefix of `PRICE_ORDERS`, the big endian price
+    /// followed by the id so orders iterate from the lowest price
+    pub fn price_key(&self) -> Vec<u8> {
+        let mut key = self.price.atomics().to_be_bytes().to_vec();
+        key.extend_from_slice(&self.id.to_be_bytes());
+        key
+    }
+}
+
+pub const ORDER_COUNT: Item<u64> = Item::new("order_count");
+pub const ORDERS: Map<u64, LimitOrder> = Map::new("orders");
+// Orders keyed by their owner and id
+pub const OWNER_ORDERS: Map<(&Addr, u64), LimitOrder> = Map::new("owner_orders");
+// Orders keyed by `order_side_key` of their input token and `LimitOrder::price_key`
+pub const PRICE_ORDERS: Map<(&str, &[u8]), LimitOrder> = Map::new("price_orders");
+
+// Balances held for limit orders and reward schedules, which are not part of the reserves,
+// keyed by `denom_key`
+pub const ESCROWED_BALANCES: Map<&str, Uint128> = Map::new("escrowed_balances");
+
+pub fn order_side_key(input_token: &TokenSelect) -> &'static str {
+    match input_token {
+        TokenSelect::Token1 => "token1",
+        TokenSelect::Token2 => "token2",
+    }
+}
@@ -0,0 +1,155 @@
+use cosmwasm_std::{Decimal256, StdError, StdResult, Uint128, Uint256};
+use std::convert::TryInto;
+
+// Stop the series once terms no longer change the result, this only bounds the gas used
+const MAX_SERIES_TERMS: u32 = 128;
+
+/// Computes `base^exp` for a base between 0 and 1
+pub fn pow(base: Decimal256, exp: Decimal256) -> StdResult<Decimal256> {
+    if base > Decimal256::one() {
+        return Err(StdError::generic_err("Power base must not exceed one"));
+    }
+
+    let whole_exp: Uint128 = (exp.atomics() / Decimal256::one().atomics()).try_into()?;
+    let whole_exp = whole_exp.u128();
+    let frac_exp = exp - Decimal256::from_ratio(whole_exp, 1u8);
+
+    let mut result = pow_int(base, whole_exp);
+    if !frac_exp.is_zero() {
+        result = result * pow_frac(base, frac_exp)?;
+    }
+    Ok(result)
+}
+
+/// Weighted invariant x^(weight_x / W) * y^(weight_y / W), which equals sqrt(x * y) for equal
+/// weights. It is computed as max * (min / max)^(weight_min / W) so the power base stays below one.
+pub fn compute_invariant(
+    x: Uint128,
+    y: Uint128,
+    weight_x: u64,
+    weight_y: u64,
+) -> StdResult<Uint128> {
+    let (min, max, weight_min) = if x <= y {
+        (x, y, weight_x)
+    } else {
+        (y, x, weight_y)
+    };
+    if min.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let base = Decimal256::from_ratio(min, max);
+    let exp = Decimal256::from_ratio(weight_min, weight_x as u128 + weight_y as u128);
+    Ok((Uint256::from(max) * pow(base, exp)?).try_into()?)
+}
+
+fn pow_int(mut base: Decimal256, mut exp: u128) -> Decimal256 {
+    let mut result = Decimal256::one();
+    while exp > 0 {
+        if exp % 2 == 1 {
+            result = result * base;
+        }
+        base = base * base;
+        exp /= 2;
+    }
+    result
+}
+
+/// `base^exp` for 0 < exp < 1. The binomial series converges slowly for small bases, so square
+/// roots first bring the base above one half and the exponent is doubled for each of them.
+fn pow_frac(mut base: Decimal256, mut exp: Decimal256) -> StdResult<Decimal256> {
+    while !base.is_zero() && base < Decimal256::percent(50) {
+        base = base.sqrt();
+        exp = exp + exp;
+    }
+
+    let whole_exp: Uint128 = (exp.atomics() / Decimal256::one().atomics()).try_into()?;
+    let whole_exp = whole_exp.u128();
+    let frac_exp = exp - Decimal256::from_ratio(whole_exp, 1u8);
+    let mut result = pow_int(base, whole_exp);
+    if !frac_exp.is_zero() {
+        result = result * binomial_series(base, frac_exp);
+    }
+    Ok(result)
+}
+
+/// Binomial series of (1 - x)^exp for 0 < exp < 1. Every term after the first is subtracted:
+/// (1 - x)^exp = 1 - exp * x - exp * (1 - exp) / 2 * x^2 - ...
+fn binomial_series(base: Decimal256, exp: Decimal256) -> Decimal256 {
+    let x = Decimal256::one() - base;
+    let mut term = exp * x;
+    let mut sum = term;
+    for k in 2..MAX_SERIES_TERMS {
+        term = term * (Decimal256::from_ratio(k - 1, 1u8) - exp) * x / Uint256::from(k);
+        if term.is_zero() {
+            break;
+        }
+        sum = sum + term;
+    }
+
+    if sum >= Decimal256::one() {
+        Decimal256::zero()
+    } else {
+        Decimal256::one() - sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_pow() {
+        let half = Decimal256::percent(50);
+        assert_eq!(pow(half, Decimal256::zero()).unwrap(), Decimal256::one());
+        assert_eq!(
+            pow(half, Decimal256::from_ratio(3u8, 1u8)).unwrap(),
+            Decimal256::permille(125)
+        );
+
+        // sqrt(0.25) = 0.5
+        let result = pow(Decimal256::percent(25), Decimal256::percent(50)).unwrap();
+        assert!(result > Decimal256::from_str("0.499999999999").unwrap());
+        assert!(result < Decimal256::from_str("0.500000000001").unwrap());
+
+        // 0.8^2.5 = 0.572433402...
+        let result = pow(Decimal256::percent(80), Decimal256::percent(250)).unwrap();
+        assert!(result > Decimal256::from_str("0.572433402").unwrap());
+        assert!(result < Decimal256::from_str("0.572433403").unwrap());
+
+        // 0.001^(2 / 98) = 0.868511373751352629...
+        let result = pow(Decimal256::permille(1), Decimal256::from_ratio(2u8, 98u8)).unwrap();
+        assert!(result > Decimal256::from_str("0.868511373751").unwrap());
+        assert!(result < Decimal256::from_str("0.868511373752").unwrap());
+
+        let err = pow(Decimal256::percent(150), Decimal256::one()).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Power base must not exceed one"));
+    }
+
+    #[test]
+    fn test_compute_invariant() {
+        // 1_600_000^0.8 * 100_000^0.2 = 918_958.68...
+        let invariant =
+            compute_invariant(Uint128::new(1_600_000), Uint128::new(100_000), 80, 20).unwrap();
+        assert_eq!(invariant, Uint128::new(918_958));
+
+        // The weights follow their tokens when the smaller reserve is token1
+        let invariant =
+            compute_invariant(Uint128::new(100_000), Uint128::new(1_600_000), 20, 80).unwrap();
+        assert_eq!(invariant, Uint128::new(918_958));
+
+        // Equal weights give the geometric mean
+        let invariant =
+            compute_invariant(Uint128::new(40_000), Uint128::new(10_000), 50, 50).unwrap();
+        assert_eq!(invariant, Uint128::new(20_000));
+
+        // Reserves far apart still converge, the result is rounded down
+        let invariant =
+            compute_invariant(Uint128::new(1), Uint128::new(10u128.pow(18)), 50, 50).unwrap();
+        assert_eq!(invariant, Uint128::new(999_999_999));
+
+        let invariant = compute_invariant(Uint128::zero(), Uint128::new(10_000), 80, 20).unwrap();
+        assert_eq!(invariant, Uint128::zero());
+    }
+}